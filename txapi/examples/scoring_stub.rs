@@ -0,0 +1,137 @@
+//! A stub scoring webhook, to try the `scored_transactions` channel locally.
+//!
+//! Scores the transactions with a naive heuristic, after an optional delay and
+//! with an optional share of failures, to exercise the timeouts and retries:
+//!
+//! ```sh
+//! STUB_DELAY_MS=20 STUB_FAILURE_RATE=0.1 cargo run --example scoring_stub
+//! SCORING_URL=http://localhost:9998/score cargo run
+//! ```
+//!
+//! - STUB_PORT: listening port (default 9998)
+//! - STUB_DELAY_MS: maximum random delay before replying (default 0)
+//! - STUB_FAILURE_RATE: share of the requests failing with a 503 (default 0)
+
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use rand::Rng;
+use serde_json::{json, Value};
+use std::time::Duration;
+use txapi::domain::prelude::*;
+
+#[derive(Clone)]
+struct Settings {
+    delay_ms: u64,
+    failure_rate: f64,
+}
+
+async fn score(
+    State(settings): State<Settings>,
+    Json(transaction): Json<Transaction>,
+) -> Result<Json<Value>, StatusCode> {
+    let (delay_ms, failed) = {
+        let mut rng = rand::rng();
+        (
+            rng.random_range(0..=settings.delay_ms),
+            rng.random_bool(settings.failure_rate),
+        )
+    };
+    tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+    if failed {
+        return Err(StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    let (_, max_amount) = transaction.category.typical_amount_range();
+    let mut score = 1;
+    if transaction.amount_usd_cents > max_amount {
+        score += 5;
+    }
+    if transaction.is_online {
+        score += 2;
+    }
+    Ok(Json(json!({ "score": score as f64 / 10.0 })))
+}
+
+#[tokio::main]
+async fn main() {
+    let env_or = |name: &str, default: &str| std::env::var(name).unwrap_or(default.to_string());
+    let port: u16 = env_or("STUB_PORT", "9998")
+        .parse()
+        .expect("invalid STUB_PORT");
+    let settings = Settings {
+        delay_ms: env_or("STUB_DELAY_MS", "0")
+            .parse()
+            .expect("invalid STUB_DELAY_MS"),
+        failure_rate: env_or("STUB_FAILURE_RATE", "0")
+            .parse::<f64>()
+            .expect("invalid STUB_FAILURE_RATE")
+            .clamp(0.0, 1.0),
+    };
+
+    let app = Router::new()
+        .route("/score", post(score))
+        .with_state(settings);
+    let listener = tokio::net::TcpListener::bind(("0.0.0.0", port))
+        .await
+        .unwrap();
+    println!(
+        "Scoring stub listening on {}",
+        listener.local_addr().unwrap()
+    );
+    axum::serve(listener, app).await.unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use txapi::domain::transactions::Location;
+
+    /// The transaction of the API examples, for a large amount.
+    ///
+    /// Examples only see the public API, so the crate's test fixture
+    /// (`Transaction::fixture`) cannot be reused here.
+    fn transaction(is_online: bool) -> Transaction {
+        Transaction {
+            id: "11df919988c134d97bbff2678eb68e22".to_string(),
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+            cc_number: "4473593503484549".to_string(),
+            category: TransactionCategory::Grocery,
+            amount_usd_cents: 1_000_000,
+            location: Location {
+                city: "San Francisco".to_string(),
+                country_iso: "US".to_string(),
+                latitude: 37.774929,
+                longitude: -122.419418,
+            },
+            is_online,
+            is_fraud: None,
+            source: None,
+            authorization: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn scores_the_transactions() {
+        let settings = Settings {
+            delay_ms: 0,
+            failure_rate: 0.0,
+        };
+        let Json(reply) = score(State(settings.clone()), Json(transaction(true)))
+            .await
+            .unwrap();
+        assert_eq!(reply, json!({ "score": 0.8 }));
+        let Json(reply) = score(State(settings), Json(transaction(false)))
+            .await
+            .unwrap();
+        assert_eq!(reply, json!({ "score": 0.6 }));
+    }
+
+    #[tokio::test]
+    async fn fails_at_the_failure_rate() {
+        let settings = Settings {
+            delay_ms: 0,
+            failure_rate: 1.0,
+        };
+        let reply = score(State(settings), Json(transaction(true))).await;
+        assert_eq!(reply.err(), Some(StatusCode::SERVICE_UNAVAILABLE));
+    }
+}
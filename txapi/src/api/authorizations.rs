@@ -0,0 +1,17 @@
+use crate::core::prelude::*;
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+
+/// Authorizations endpoint
+///
+/// Returns the outcomes of the authorization simulation: decisions of the
+/// authorizing client, timeouts, outcomes per label and reply latency.
+/// Returns 404 when the simulation is disabled.
+pub async fn endpoint(State(state): State<AppState>) -> impl IntoResponse {
+    let Some(authorizer) = &state.authorizer else {
+        let body = json!({ "error": "the authorization simulation is disabled" });
+        return (StatusCode::NOT_FOUND, Json(body)).into_response();
+    };
+
+    (StatusCode::OK, Json(authorizer.summary())).into_response()
+}
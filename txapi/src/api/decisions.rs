@@ -0,0 +1,20 @@
+use crate::core::{decisions::ClientEvaluation, prelude::*};
+use axum::{extract::State, response::IntoResponse, Json};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[derive(Serialize)]
+pub struct DecisionsResponse {
+    pub clients: BTreeMap<String, ClientEvaluation>,
+}
+
+/// Decisions endpoint
+///
+/// Returns the evaluation of the fraud decisions sent over the websocket,
+/// per client id: confusion matrix, precision, recall, false-positive rate
+/// and decision latency.
+pub async fn endpoint(State(state): State<AppState>) -> impl IntoResponse {
+    Json(DecisionsResponse {
+        clients: state.decisions.evaluations(),
+    })
+}
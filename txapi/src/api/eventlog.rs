@@ -0,0 +1,60 @@
+use crate::{core::prelude::*, stream::eventlog::LogQuery};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::json;
+
+/// Default and maximum number of records returned by a query.
+const DEFAULT_LIMIT: usize = 1000;
+const MAX_LIMIT: usize = 10_000;
+
+#[derive(Deserialize)]
+pub struct LogParams {
+    pub from_seq: Option<u64>,
+    pub to_seq: Option<u64>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+/// Event log endpoint
+///
+/// Returns the logged transactions matching the sequence number range
+/// (`from_seq`, `to_seq`) and/or time range (`since`, `until`, RFC3339),
+/// in sequence order. Returns 404 when the event log is disabled.
+pub async fn endpoint(
+    State(state): State<AppState>,
+    Query(params): Query<LogParams>,
+) -> impl IntoResponse {
+    let Some(event_log) = state.event_log.clone() else {
+        let body = json!({ "error": "the event log is disabled" });
+        return (StatusCode::NOT_FOUND, Json(body)).into_response();
+    };
+
+    let limit = params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let query = LogQuery {
+        from_seq: params.from_seq,
+        to_seq: params.to_seq,
+        since: params.since,
+        until: params.until,
+    };
+    let records = tokio::task::spawn_blocking(move || event_log.query(&query, limit)).await;
+
+    match records {
+        Ok(Ok(records)) => (StatusCode::OK, Json(records)).into_response(),
+        Ok(Err(e)) => {
+            tracing::error!("Failed to query the event log: {}", e);
+            let body = json!({ "error": "failed to read the event log" });
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Event log query panicked: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
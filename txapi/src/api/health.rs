@@ -1,10 +1,19 @@
-use axum::{http::StatusCode, response::IntoResponse, Json};
+use crate::{core::prelude::*, stream::source::SourceHealth};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use serde::Serialize;
 
 #[derive(Serialize)]
 pub struct HealthResponse {
     pub status: String,
     pub version: String,
+    pub sources: Vec<SourceStatus>,
+}
+
+#[derive(Serialize)]
+pub struct SourceStatus {
+    pub name: String,
+    #[serde(flatten)]
+    pub health: SourceHealth,
 }
 
 /// Health check endpoint
@@ -12,10 +21,21 @@ pub struct HealthResponse {
 /// Returns 200 OK if the service is running properly.
 /// This endpoint can be used by container orchestrators and load balancers
 /// to determine if the service is healthy.
-pub async fn endpoint() -> impl IntoResponse {
+///
+/// The response includes the health of each transaction source, a failed
+/// source does not make the service unhealthy.
+pub async fn endpoint(State(state): State<AppState>) -> impl IntoResponse {
+    let sources = state
+        .sources
+        .health()
+        .into_iter()
+        .map(|(name, health)| SourceStatus { name, health })
+        .collect();
+
     let response = HealthResponse {
         status: "ok".to_string(),
         version: env!("CARGO_PKG_VERSION").to_string(),
+        sources,
     };
 
     (StatusCode::OK, Json(response))
@@ -0,0 +1,272 @@
+use super::has_bearer_token;
+use crate::{core::prelude::*, domain::prelude::*};
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// Maximum number of transactions accepted in a single request.
+const MAX_BATCH_SIZE: usize = 1000;
+
+/// Source name tagged on the ingested transactions.
+const SOURCE_NAME: &str = "ingest";
+
+#[derive(Serialize)]
+pub struct IngestResponse {
+    pub accepted: usize,
+}
+
+#[derive(Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<String>,
+}
+
+/// Ingest endpoint
+///
+/// Broadcasts hand-crafted transactions to the transactions channel, so that
+/// edge cases can be injected into a running demo. Requires the INGEST_TOKEN
+/// as a bearer token, and is disabled when INGEST_TOKEN is not set.
+///
+/// A batch is validated as a whole: if any transaction is invalid nothing is
+/// broadcast. Ids must be unique within the batch and must not be those of
+/// live transactions, still awaiting decisions or an authorization, which
+/// would otherwise be confused. When the authorization simulation is
+/// enabled, the batch is authorized before it is broadcast. Returns 202 Accepted with the number
+/// of broadcast transactions.
+pub async fn endpoint(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    payload: Result<Json<serde_json::Value>, JsonRejection>,
+) -> impl IntoResponse {
+    let Some(token) = state.ingest_token.as_deref() else {
+        return error(StatusCode::FORBIDDEN, "ingestion is disabled", vec![]);
+    };
+    if !has_bearer_token(&headers, token) {
+        return error(
+            StatusCode::UNAUTHORIZED,
+            "invalid or missing bearer token",
+            vec![],
+        );
+    }
+
+    // the body is either a single transaction or a batch
+    let parsed = match payload {
+        Ok(Json(serde_json::Value::Array(batch))) => batch
+            .into_iter()
+            .enumerate()
+            .map(|(idx, item)| serde_json::from_value(item).map_err(|e| format!("[{}] {}", idx, e)))
+            .collect(),
+        Ok(Json(item)) => serde_json::from_value(item)
+            .map(|transaction| vec![transaction])
+            .map_err(|e| e.to_string()),
+        Err(rejection) => Err(rejection.body_text()),
+    };
+    let transactions: Vec<Transaction> = match parsed {
+        Ok(transactions) => transactions,
+        Err(e) => {
+            return error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "payload is not a transaction or a list of transactions",
+                vec![e],
+            )
+        }
+    };
+    if transactions.is_empty() || transactions.len() > MAX_BATCH_SIZE {
+        let message = format!("batch size must be between 1 and {}", MAX_BATCH_SIZE);
+        return error(StatusCode::UNPROCESSABLE_ENTITY, &message, vec![]);
+    }
+
+    let mut ids = HashSet::new();
+    let details: Vec<String> = transactions
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, transaction)| {
+            let mut errors = transaction.validate().err().unwrap_or_default();
+            if !ids.insert(transaction.id.as_str()) {
+                errors.push(format!("duplicate id in the batch: {}", transaction.id));
+            }
+            (!errors.is_empty()).then_some((idx, errors))
+        })
+        .flat_map(|(idx, errors)| errors.into_iter().map(move |e| format!("[{}] {}", idx, e)))
+        .collect();
+    if !details.is_empty() {
+        return error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "invalid transactions",
+            details,
+        );
+    }
+
+    let details: Vec<String> = transactions
+        .iter()
+        .enumerate()
+        .filter(|(_, transaction)| {
+            state.decisions.is_pending(&transaction.id)
+                || state
+                    .authorizer
+                    .as_ref()
+                    .is_some_and(|authorizer| authorizer.is_pending(&transaction.id))
+        })
+        .map(|(idx, transaction)| format!("[{}] id of a live transaction: {}", idx, transaction.id))
+        .collect();
+    if !details.is_empty() {
+        return error(
+            StatusCode::CONFLICT,
+            "transaction ids already in use",
+            details,
+        );
+    }
+
+    let accepted = transactions.len();
+    let transactions = transactions.into_iter().map(|mut transaction| {
+        transaction.source = Some(SOURCE_NAME.to_string());
+        transaction.authorization = None;
+        let authorizer = state.authorizer.clone();
+        async move {
+            match authorizer {
+                Some(authorizer) => authorizer.authorize(transaction).await,
+                None => transaction,
+            }
+        }
+    });
+    for transaction in futures::future::join_all(transactions).await {
+        tracing::debug!("Ingesting transaction {}", transaction.id);
+        state.transactions_tx.send(transaction);
+    }
+
+    (StatusCode::ACCEPTED, Json(IngestResponse { accepted })).into_response()
+}
+
+fn error(status: StatusCode, message: &str, details: Vec<String>) -> axum::response::Response {
+    let body = ErrorResponse {
+        error: message.to_string(),
+        details,
+    };
+    (status, Json(body)).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::WsConfig;
+    use axum::{body::to_bytes, http::header::AUTHORIZATION};
+    use serde_json::{json, Value};
+
+    fn state(ingest_token: Option<&str>) -> AppState {
+        let mut state = AppState::for_tests(100, 0, WsConfig::default());
+        state.ingest_token = ingest_token.map(str::to_string);
+        state
+    }
+
+    fn transaction(id: &str) -> Value {
+        let mut transaction = Transaction::simple_mock();
+        transaction.id = id.to_string();
+        serde_json::to_value(transaction).unwrap()
+    }
+
+    /// Posts the payload with the token, returns the status and the body.
+    async fn ingest(state: &AppState, token: Option<&str>, payload: Value) -> (StatusCode, Value) {
+        let mut headers = HeaderMap::new();
+        if let Some(token) = token {
+            let value = format!("Bearer {}", token).parse().unwrap();
+            headers.insert(AUTHORIZATION, value);
+        }
+        let response = endpoint(State(state.clone()), headers, Ok(Json(payload)))
+            .await
+            .into_response();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn requires_the_bearer_token() {
+        let disabled = state(None);
+        let (status, _) = ingest(&disabled, Some("secret"), transaction("t1")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let state = state(Some("secret"));
+        let (status, _) = ingest(&state, None, transaction("t1")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = ingest(&state, Some("wrong"), transaction("t1")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _) = ingest(&state, Some("secret"), transaction("t1")).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+    }
+
+    #[tokio::test]
+    async fn broadcasts_single_transactions_and_batches() {
+        let state = state(Some("secret"));
+        let mut rx = state.transactions_tx.subscribe();
+
+        let (status, body) = ingest(&state, Some("secret"), transaction("t1")).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(body, json!({"accepted": 1}));
+        let batch = json!([transaction("t2"), transaction("t3")]);
+        let (status, body) = ingest(&state, Some("secret"), batch).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(body, json!({"accepted": 2}));
+
+        for id in ["t1", "t2", "t3"] {
+            let transaction = rx.recv().await.unwrap().data;
+            assert_eq!(transaction.id, id);
+            assert_eq!(transaction.source.as_deref(), Some(SOURCE_NAME));
+        }
+    }
+
+    #[tokio::test]
+    async fn enforces_the_batch_size() {
+        let state = state(Some("secret"));
+        let (status, _) = ingest(&state, Some("secret"), json!([])).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+
+        let batch: Vec<Value> = (0..=MAX_BATCH_SIZE)
+            .map(|i| transaction(&format!("t{}", i)))
+            .collect();
+        let (status, _) = ingest(&state, Some("secret"), Value::Array(batch)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_batches_as_a_whole() {
+        let state = state(Some("secret"));
+        let mut rx = state.transactions_tx.subscribe();
+
+        let (status, body) = ingest(&state, Some("secret"), json!({"id": "t1"})).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["details"].as_array().unwrap().len(), 1);
+
+        let mut invalid = transaction("t2");
+        invalid["timestamp"] = json!("yesterday");
+        let batch = json!([transaction("t1"), invalid, transaction("t1")]);
+        let (status, body) = ingest(&state, Some("secret"), batch).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"], "invalid transactions");
+        assert_eq!(
+            body["details"],
+            json!([
+                "[1] timestamp is not RFC3339: yesterday",
+                "[2] duplicate id in the batch: t1",
+            ])
+        );
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_the_ids_of_live_transactions() {
+        let state = state(Some("secret"));
+        let live: Transaction = serde_json::from_value(transaction("t1")).unwrap();
+        state.decisions.broadcast(&live);
+
+        let batch = json!([transaction("t2"), transaction("t1")]);
+        let (status, body) = ingest(&state, Some("secret"), batch).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["details"], json!(["[1] id of a live transaction: t1"]));
+    }
+}
@@ -0,0 +1,347 @@
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+use super::ws::models::{AckNotice, ChannelMsg, ErrorCode, ErrorNotice, Request};
+
+/// Websocket subprotocol selecting the JSON-RPC 2.0 protocol.
+pub const SUBPROTOCOL: &str = "jsonrpc-2.0";
+
+/// Subscription ids of a connection, by channel.
+///
+/// A subscription keeps its id when subscribing again to the channel to
+/// change its settings.
+#[derive(Debug, Default)]
+pub struct Subscriptions {
+    ids: HashMap<String, String>,
+    next_id: u64,
+}
+
+impl Subscriptions {
+    fn subscribe(&mut self, channel: &str) -> String {
+        let next_id = &mut self.next_id;
+        self.ids
+            .entry(channel.to_string())
+            .or_insert_with(|| {
+                *next_id += 1;
+                next_id.to_string()
+            })
+            .clone()
+    }
+
+    fn channel(&self, subscription: &str) -> Option<&str> {
+        self.ids
+            .iter()
+            .find(|(_, id)| *id == subscription)
+            .map(|(channel, _)| channel.as_str())
+    }
+}
+
+/// Parses a JSON-RPC 2.0 request.
+///
+/// The methods and their params are those of the default protocol, except
+/// that `unsubscribe` takes the `subscription` id returned by `subscribe`.
+pub fn parse(text: &str, subscriptions: &Subscriptions) -> Result<Request, ErrorNotice> {
+    let mut value: Value = serde_json::from_str(text).map_err(|e| ErrorNotice {
+        id: None,
+        method: None,
+        code: ErrorCode::ParseError,
+        message: format!("invalid JSON: {}", e),
+    })?;
+    let id = value
+        .get("id")
+        .cloned()
+        .and_then(|id| serde_json::from_value(id).ok());
+    let invalid = |id, message: &str| ErrorNotice {
+        id,
+        method: None,
+        code: ErrorCode::InvalidRequest,
+        message: message.to_string(),
+    };
+    if value.is_array() {
+        return Err(invalid(None, "batch requests are not supported"));
+    }
+    if value.get("jsonrpc").and_then(Value::as_str) != Some("2.0") {
+        return Err(invalid(id, "jsonrpc must be \"2.0\""));
+    }
+
+    // unsubscribe from the channel of the subscription
+    if value.get("method").and_then(Value::as_str) == Some("unsubscribe") {
+        if let Some(params) = value.get_mut("params").and_then(Value::as_object_mut) {
+            if let Some(subscription) = params.remove("subscription") {
+                match subscription
+                    .as_str()
+                    .and_then(|id| subscriptions.channel(id))
+                {
+                    Some(channel) => {
+                        params.insert("channel".to_string(), json!(channel));
+                    }
+                    None => {
+                        return Err(ErrorNotice {
+                            id,
+                            method: Some("unsubscribe".to_string()),
+                            code: ErrorCode::NotSubscribed,
+                            message: format!("unknown subscription {}", subscription),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    Request::from_value(value)
+}
+
+/// Encodes a message as a JSON-RPC 2.0 response or notification.
+///
+/// Acks and errors are the responses to the requests, none is sent for the
+/// notifications (requests without id) except for the messages that could
+/// not be read. Channel messages are sent as `subscription` notifications
+/// when the channel is subscribed, and as notifications named after the
+/// channel otherwise (such as the server heartbeats).
+pub fn encode(msg: ChannelMsg, subscriptions: &mut Subscriptions) -> Option<Value> {
+    match msg {
+        ChannelMsg::Ack { data } => acknowledge(data, subscriptions),
+        ChannelMsg::Error { data } => error(data),
+        msg => {
+            let Ok(Value::Object(mut params)) = serde_json::to_value(msg) else {
+                return None;
+            };
+            let channel = params.get("channel").and_then(Value::as_str)?.to_string();
+            // notices are about the channel given in their data
+            let subscribed = params
+                .get("data")
+                .and_then(|data| data.get("channel"))
+                .and_then(Value::as_str)
+                .filter(|_| matches!(channel.as_str(), "gap" | "lagged"))
+                .unwrap_or(&channel);
+
+            match subscriptions.ids.get(subscribed) {
+                Some(subscription) => {
+                    let mut notification = Map::new();
+                    notification.insert("subscription".to_string(), json!(subscription));
+                    notification.extend(params);
+                    Some(notify("subscription", Value::Object(notification)))
+                }
+                None => {
+                    params.remove("channel");
+                    Some(notify(&channel, Value::Object(params)))
+                }
+            }
+        }
+    }
+}
+
+fn acknowledge(ack: AckNotice, subscriptions: &mut Subscriptions) -> Option<Value> {
+    let result = match (ack.method.as_str(), &ack.channel) {
+        ("subscribe", Some(channel)) => json!({ "subscription": subscriptions.subscribe(channel) }),
+        ("unsubscribe", Some(channel)) => {
+            subscriptions.ids.remove(channel);
+            json!(true)
+        }
+        _ => json!(true),
+    };
+    let id = ack.id?;
+    Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+}
+
+fn error(notice: ErrorNotice) -> Option<Value> {
+    let unreadable = matches!(
+        notice.code,
+        ErrorCode::ParseError | ErrorCode::InvalidRequest
+    );
+    if notice.id.is_none() && !unreadable {
+        return None;
+    }
+
+    let code = match notice.code {
+        ErrorCode::ParseError => -32700,
+        ErrorCode::InvalidRequest => -32600,
+        ErrorCode::UnknownMethod => -32601,
+        ErrorCode::UnknownChannel | ErrorCode::InvalidParams => -32602,
+        ErrorCode::AlreadySubscribed => -32001,
+        ErrorCode::NotSubscribed => -32002,
+        ErrorCode::UnknownTransaction => -32003,
+        ErrorCode::AlreadyDecided => -32004,
+        ErrorCode::LimitExceeded => -32005,
+        ErrorCode::Forbidden => -32006,
+        ErrorCode::Unavailable => -32007,
+    };
+    Some(json!({
+        "jsonrpc": "2.0",
+        "id": notice.id,
+        "error": {
+            "code": code,
+            "message": notice.message,
+            "data": { "code": notice.code },
+        },
+    }))
+}
+
+fn notify(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::ws::models::{RequestId, WsMessage};
+
+    fn ack(id: Option<i64>, method: &str, channel: &str) -> ChannelMsg {
+        ChannelMsg::Ack {
+            data: AckNotice {
+                id: id.map(RequestId::Number),
+                method: method.to_string(),
+                channel: Some(channel.to_string()),
+            },
+        }
+    }
+
+    fn error_notice(id: Option<i64>, code: ErrorCode) -> ChannelMsg {
+        ChannelMsg::Error {
+            data: ErrorNotice {
+                id: id.map(RequestId::Number),
+                method: None,
+                code,
+                message: "message".to_string(),
+            },
+        }
+    }
+
+    fn transactions() -> ChannelMsg {
+        ChannelMsg::Transactions {
+            seq: 7,
+            data: vec![],
+        }
+    }
+
+    #[test]
+    fn subscriptions_are_notified_and_unsubscribed_by_id() {
+        let mut subscriptions = Subscriptions::default();
+        let text =
+            r#"{"jsonrpc":"2.0","id":1,"method":"subscribe","params":{"channel":"transactions"}}"#;
+        let request = parse(text, &subscriptions).unwrap();
+        assert_eq!(request.id, Some(RequestId::Number(1)));
+        assert!(
+            matches!(&request.message, WsMessage::Subscribe { params } if params.channel == "transactions")
+        );
+
+        let response = encode(
+            ack(Some(1), "subscribe", "transactions"),
+            &mut subscriptions,
+        );
+        let expected = json!({ "jsonrpc": "2.0", "id": 1, "result": { "subscription": "1" } });
+        assert_eq!(response, Some(expected));
+        // subscribing again keeps the subscription id
+        let response = encode(
+            ack(Some(2), "subscribe", "transactions"),
+            &mut subscriptions,
+        );
+        assert_eq!(response.unwrap()["result"]["subscription"], "1");
+
+        let notification = encode(transactions(), &mut subscriptions);
+        let expected = json!({
+            "jsonrpc": "2.0",
+            "method": "subscription",
+            "params": { "subscription": "1", "channel": "transactions", "seq": 7, "data": [] },
+        });
+        assert_eq!(notification, Some(expected));
+
+        let text =
+            r#"{"jsonrpc":"2.0","id":"u","method":"unsubscribe","params":{"subscription":"1"}}"#;
+        let request = parse(text, &subscriptions).unwrap();
+        assert_eq!(request.id, Some(RequestId::String("u".to_string())));
+        assert!(
+            matches!(&request.message, WsMessage::Unsubscribe { params } if params.channel == "transactions")
+        );
+        let response = encode(
+            ack(Some(3), "unsubscribe", "transactions"),
+            &mut subscriptions,
+        );
+        assert_eq!(response.unwrap()["result"], true);
+
+        // unsubscribed channels are notified under their name
+        let notification = encode(transactions(), &mut subscriptions).unwrap();
+        assert_eq!(notification["method"], "transactions");
+        assert_eq!(notification["params"], json!({ "seq": 7, "data": [] }));
+    }
+
+    #[test]
+    fn unknown_subscriptions_are_rejected() {
+        let subscriptions = Subscriptions::default();
+        let text =
+            r#"{"jsonrpc":"2.0","id":4,"method":"unsubscribe","params":{"subscription":"9"}}"#;
+        let error = parse(text, &subscriptions).unwrap_err();
+        assert_eq!(error.id, Some(RequestId::Number(4)));
+        assert_eq!(error.method.as_deref(), Some("unsubscribe"));
+        assert_eq!(error.code, ErrorCode::NotSubscribed);
+        assert_eq!(error.message, "unknown subscription \"9\"");
+    }
+
+    #[test]
+    fn notifications_get_no_response() {
+        let mut subscriptions = Subscriptions::default();
+        let text = r#"{"jsonrpc":"2.0","method":"subscribe","params":{"channel":"alerts"}}"#;
+        assert_eq!(parse(text, &subscriptions).unwrap().id, None);
+
+        assert_eq!(
+            encode(ack(None, "subscribe", "alerts"), &mut subscriptions),
+            None
+        );
+        // the subscription is still recorded
+        assert_eq!(subscriptions.channel("1"), Some("alerts"));
+        let error = error_notice(None, ErrorCode::AlreadySubscribed);
+        assert_eq!(encode(error, &mut subscriptions), None);
+
+        // unless they could not be read
+        let error = error_notice(None, ErrorCode::ParseError);
+        let response = encode(error, &mut subscriptions).unwrap();
+        assert_eq!(response["id"], Value::Null);
+        assert_eq!(response["error"]["code"], -32700);
+    }
+
+    #[test]
+    fn error_codes_are_mapped() {
+        let codes = [
+            (ErrorCode::ParseError, -32700),
+            (ErrorCode::InvalidRequest, -32600),
+            (ErrorCode::UnknownMethod, -32601),
+            (ErrorCode::UnknownChannel, -32602),
+            (ErrorCode::InvalidParams, -32602),
+            (ErrorCode::AlreadySubscribed, -32001),
+            (ErrorCode::NotSubscribed, -32002),
+            (ErrorCode::UnknownTransaction, -32003),
+            (ErrorCode::AlreadyDecided, -32004),
+            (ErrorCode::LimitExceeded, -32005),
+            (ErrorCode::Forbidden, -32006),
+            (ErrorCode::Unavailable, -32007),
+        ];
+        let mut subscriptions = Subscriptions::default();
+        for (code, number) in codes {
+            let response = encode(error_notice(Some(5), code), &mut subscriptions).unwrap();
+            let expected = json!({
+                "jsonrpc": "2.0",
+                "id": 5,
+                "error": { "code": number, "message": "message", "data": { "code": code } },
+            });
+            assert_eq!(response, expected);
+        }
+    }
+
+    #[test]
+    fn invalid_requests_are_rejected() {
+        let subscriptions = Subscriptions::default();
+        let batch =
+            r#"[{"jsonrpc":"2.0","id":1,"method":"subscribe","params":{"channel":"alerts"}}]"#;
+        let error = parse(batch, &subscriptions).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert_eq!(error.message, "batch requests are not supported");
+        assert_eq!(error.id, None);
+
+        let text = r#"{"id":2,"method":"subscribe","params":{"channel":"alerts"}}"#;
+        let error = parse(text, &subscriptions).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidRequest);
+        assert_eq!(error.id, Some(RequestId::Number(2)));
+
+        let error = parse("{", &subscriptions).unwrap_err();
+        assert_eq!(error.code, ErrorCode::ParseError);
+    }
+}
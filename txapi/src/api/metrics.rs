@@ -0,0 +1,12 @@
+use crate::core::prelude::*;
+use axum::{extract::State, http::header, response::IntoResponse};
+
+/// Metrics endpoint
+///
+/// Returns the server metrics in the Prometheus text exposition format.
+pub async fn endpoint(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(),
+    )
+}
@@ -1,2 +1,31 @@
+pub mod authorizations;
+pub mod decisions;
+pub mod eventlog;
 pub mod health;
+pub mod ingest;
+pub mod jsonrpc;
+pub mod metrics;
+pub mod protobuf;
+pub mod sse;
+pub mod v2;
 pub mod ws;
+
+use axum::http::{header, HeaderMap};
+
+/// Whether the request carries `token` as its bearer token.
+pub(crate) fn has_bearer_token(headers: &HeaderMap, token: &str) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .is_some_and(|provided| constant_time_eq(provided, token))
+}
+
+/// Compares the tokens without short-circuiting on the first difference.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
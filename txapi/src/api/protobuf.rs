@@ -0,0 +1,822 @@
+//! Protobuf encoding of the websocket messages.
+//!
+//! The messages are declared by hand with the `prost` derives, mirroring
+//! `proto/txapi.proto`: the two must be changed together, which the tests
+//! check by comparing the fields and enum values declared in both.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use super::ws::models::{
+    AckNotice as WsAckNotice, ChannelMsg, ErrorNotice as WsErrorNotice, RequestId as WsRequestId,
+    TransactionData,
+};
+use crate::domain::prelude as domain;
+
+/// Websocket subprotocol selecting the Protobuf encoding.
+pub const SUBPROTOCOL: &str = "txapi.protobuf";
+
+/// Encodes a message as a `ChannelMessage`.
+pub fn encode(msg: ChannelMsg) -> Vec<u8> {
+    ChannelMessage::from(msg).encode_to_vec()
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ChannelMessage {
+    #[prost(string, tag = "1")]
+    pub channel: String,
+    #[prost(uint64, optional, tag = "2")]
+    pub seq: Option<u64>,
+    #[prost(oneof = "Data", tags = "3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13")]
+    pub data: Option<Data>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum Data {
+    #[prost(message, tag = "3")]
+    Transactions(TransactionBatch),
+    #[prost(message, tag = "4")]
+    Heartbeat(Heartbeat),
+    #[prost(message, tag = "5")]
+    EnrichedTransactions(EnrichedTransactionBatch),
+    #[prost(message, tag = "6")]
+    ScoredTransactions(ScoredTransactionBatch),
+    #[prost(message, tag = "7")]
+    Alert(Alert),
+    #[prost(message, tag = "8")]
+    Stats(Stats),
+    #[prost(message, tag = "9")]
+    Authorization(AuthorizationRequest),
+    #[prost(message, tag = "10")]
+    Gap(GapNotice),
+    #[prost(message, tag = "11")]
+    Lagged(LagNotice),
+    #[prost(message, tag = "12")]
+    Ack(AckNotice),
+    #[prost(message, tag = "13")]
+    Error(ErrorNotice),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum TransactionCategory {
+    Unspecified = 0,
+    Grocery = 1,
+    GasStation = 2,
+    Restaurant = 3,
+    OnlineRetail = 4,
+    Entertainment = 5,
+    Travel = 6,
+    Healthcare = 7,
+    Utilities = 8,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Transaction {
+    #[prost(string, tag = "1")]
+    pub id: String,
+    #[prost(string, tag = "2")]
+    pub timestamp: String,
+    #[prost(string, tag = "3")]
+    pub cc_number: String,
+    #[prost(enumeration = "TransactionCategory", tag = "4")]
+    pub category: i32,
+    #[prost(uint64, tag = "5")]
+    pub amount_usd_cents: u64,
+    #[prost(string, tag = "6")]
+    pub city: String,
+    #[prost(string, tag = "7")]
+    pub country_iso: String,
+    #[prost(double, tag = "8")]
+    pub latitude: f64,
+    #[prost(double, tag = "9")]
+    pub longitude: f64,
+    #[prost(bool, tag = "10")]
+    pub is_online: bool,
+    #[prost(string, optional, tag = "12")]
+    pub source: Option<String>,
+    #[prost(message, optional, tag = "13")]
+    pub authorization: Option<Authorization>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum AuthorizationDecision {
+    Unspecified = 0,
+    Approve = 1,
+    Decline = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum AuthorizationStatus {
+    Unspecified = 0,
+    Replied = 1,
+    TimedOut = 2,
+    Unavailable = 3,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Authorization {
+    #[prost(enumeration = "AuthorizationDecision", tag = "1")]
+    pub decision: i32,
+    #[prost(enumeration = "AuthorizationStatus", tag = "2")]
+    pub status: i32,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TransactionBatch {
+    #[prost(message, repeated, tag = "1")]
+    pub transactions: Vec<Transaction>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Heartbeat {
+    #[prost(string, tag = "1")]
+    pub status: String,
+    #[prost(string, tag = "2")]
+    pub server_time: String,
+    #[prost(uint64, tag = "3")]
+    pub uptime_secs: u64,
+    #[prost(uint64, optional, tag = "4")]
+    pub transactions_seq: Option<u64>,
+    #[prost(btree_map = "string, string", tag = "5")]
+    pub sources: BTreeMap<String, String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct CardFeatures {
+    #[prost(uint64, tag = "1")]
+    pub count_1h: u64,
+    #[prost(uint64, tag = "2")]
+    pub amount_1h_usd_cents: u64,
+    #[prost(uint64, tag = "3")]
+    pub count_24h: u64,
+    #[prost(uint64, tag = "4")]
+    pub amount_24h_usd_cents: u64,
+    #[prost(uint64, tag = "5")]
+    pub count_7d: u64,
+    #[prost(uint64, tag = "6")]
+    pub amount_7d_usd_cents: u64,
+    #[prost(double, optional, tag = "7")]
+    pub secs_since_previous: Option<f64>,
+    #[prost(double, optional, tag = "8")]
+    pub km_from_previous: Option<f64>,
+    #[prost(string, optional, tag = "9")]
+    pub previous_country_iso: Option<String>,
+    #[prost(bool, tag = "10")]
+    pub first_in_category: bool,
+    #[prost(bool, tag = "11")]
+    pub first_in_country: bool,
+    #[prost(double, optional, tag = "12")]
+    pub avg_amount_usd_cents: Option<f64>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EnrichedTransaction {
+    #[prost(message, optional, tag = "1")]
+    pub transaction: Option<Transaction>,
+    #[prost(message, optional, tag = "2")]
+    pub features: Option<CardFeatures>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct EnrichedTransactionBatch {
+    #[prost(message, repeated, tag = "1")]
+    pub transactions: Vec<EnrichedTransaction>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Score {
+    #[prost(double, optional, tag = "1")]
+    pub score: Option<f64>,
+    #[prost(string, optional, tag = "2")]
+    pub error: Option<String>,
+    #[prost(uint32, tag = "3")]
+    pub attempts: u32,
+    #[prost(double, tag = "4")]
+    pub latency_ms: f64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ScoredTransaction {
+    #[prost(message, optional, tag = "1")]
+    pub transaction: Option<Transaction>,
+    #[prost(message, optional, tag = "2")]
+    pub scoring: Option<Score>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ScoredTransactionBatch {
+    #[prost(message, repeated, tag = "1")]
+    pub transactions: Vec<ScoredTransaction>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Alert {
+    #[prost(string, tag = "1")]
+    pub rule_id: String,
+    #[prost(double, tag = "2")]
+    pub score: f64,
+    #[prost(string, tag = "3")]
+    pub reason: String,
+    #[prost(message, optional, tag = "4")]
+    pub transaction: Option<EnrichedTransaction>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Aggregate {
+    #[prost(uint64, tag = "1")]
+    pub count: u64,
+    #[prost(uint64, tag = "2")]
+    pub amount_usd_cents: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct FraudRate {
+    #[prost(uint64, tag = "1")]
+    pub labelled: u64,
+    #[prost(uint64, tag = "2")]
+    pub fraudulent: u64,
+    #[prost(double, tag = "3")]
+    pub rate: f64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Stats {
+    #[prost(string, tag = "1")]
+    pub window: String,
+    #[prost(string, tag = "2")]
+    pub start: String,
+    #[prost(string, tag = "3")]
+    pub end: String,
+    #[prost(message, optional, tag = "4")]
+    pub total: Option<Aggregate>,
+    #[prost(btree_map = "string, message", tag = "5")]
+    pub by_category: BTreeMap<String, Aggregate>,
+    #[prost(btree_map = "string, message", tag = "6")]
+    pub by_country: BTreeMap<String, Aggregate>,
+    #[prost(message, optional, tag = "7")]
+    pub online: Option<Aggregate>,
+    #[prost(message, optional, tag = "8")]
+    pub offline: Option<Aggregate>,
+    #[prost(message, optional, tag = "9")]
+    pub fraud: Option<FraudRate>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AuthorizationRequest {
+    #[prost(uint64, tag = "1")]
+    pub timeout_ms: u64,
+    #[prost(message, optional, tag = "2")]
+    pub transaction: Option<Transaction>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct GapNotice {
+    #[prost(string, tag = "1")]
+    pub channel: String,
+    #[prost(uint64, tag = "2")]
+    pub requested: u64,
+    #[prost(uint64, tag = "3")]
+    pub available_from: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct LagNotice {
+    #[prost(string, tag = "1")]
+    pub channel: String,
+    #[prost(uint64, tag = "2")]
+    pub dropped: u64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct RequestId {
+    #[prost(oneof = "Id", tags = "1, 2")]
+    pub id: Option<Id>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+pub enum Id {
+    #[prost(int64, tag = "1")]
+    Number(i64),
+    #[prost(string, tag = "2")]
+    String(String),
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct AckNotice {
+    #[prost(message, optional, tag = "1")]
+    pub id: Option<RequestId>,
+    #[prost(string, tag = "2")]
+    pub method: String,
+    #[prost(string, optional, tag = "3")]
+    pub channel: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct ErrorNotice {
+    #[prost(message, optional, tag = "1")]
+    pub id: Option<RequestId>,
+    #[prost(string, optional, tag = "2")]
+    pub method: Option<String>,
+    #[prost(string, tag = "3")]
+    pub code: String,
+    #[prost(string, tag = "4")]
+    pub message: String,
+}
+
+impl From<ChannelMsg> for ChannelMessage {
+    fn from(msg: ChannelMsg) -> Self {
+        let (channel, seq, data) = match msg {
+            ChannelMsg::Transactions { seq, data } => (
+                "transactions",
+                Some(seq),
+                Data::Transactions(TransactionBatch {
+                    transactions: data.into_iter().map(Transaction::from).collect(),
+                }),
+            ),
+            ChannelMsg::Heartbeat { seq, data } => ("heartbeat", seq, Data::Heartbeat(data.into())),
+            ChannelMsg::EnrichedTransactions { seq, data } => (
+                "enriched_transactions",
+                Some(seq),
+                Data::EnrichedTransactions(EnrichedTransactionBatch {
+                    transactions: data.into_iter().map(EnrichedTransaction::from).collect(),
+                }),
+            ),
+            ChannelMsg::ScoredTransactions { seq, data } => (
+                "scored_transactions",
+                Some(seq),
+                Data::ScoredTransactions(ScoredTransactionBatch {
+                    transactions: data.into_iter().map(ScoredTransaction::from).collect(),
+                }),
+            ),
+            ChannelMsg::Alerts { seq, data } => ("alerts", Some(seq), Data::Alert(data.into())),
+            ChannelMsg::Stats { seq, data } => ("stats", Some(seq), Data::Stats(data.into())),
+            ChannelMsg::Authorization { data } => (
+                "authorization",
+                None,
+                Data::Authorization(AuthorizationRequest {
+                    timeout_ms: data.timeout_ms,
+                    transaction: Some(data.transaction.into()),
+                }),
+            ),
+            ChannelMsg::Gap { data } => (
+                "gap",
+                None,
+                Data::Gap(GapNotice {
+                    channel: data.channel,
+                    requested: data.requested,
+                    available_from: data.available_from,
+                }),
+            ),
+            ChannelMsg::Lagged { data } => (
+                "lagged",
+                None,
+                Data::Lagged(LagNotice {
+                    channel: data.channel,
+                    dropped: data.dropped,
+                }),
+            ),
+            ChannelMsg::Ack { data } => ("ack", None, Data::Ack(data.into())),
+            ChannelMsg::Error { data } => ("error", None, Data::Error(data.into())),
+        };
+        Self {
+            channel: channel.to_string(),
+            seq,
+            data: Some(data),
+        }
+    }
+}
+
+impl From<domain::TransactionCategory> for TransactionCategory {
+    fn from(category: domain::TransactionCategory) -> Self {
+        match category {
+            domain::TransactionCategory::Grocery => Self::Grocery,
+            domain::TransactionCategory::GasStation => Self::GasStation,
+            domain::TransactionCategory::Restaurant => Self::Restaurant,
+            domain::TransactionCategory::OnlineRetail => Self::OnlineRetail,
+            domain::TransactionCategory::Entertainment => Self::Entertainment,
+            domain::TransactionCategory::Travel => Self::Travel,
+            domain::TransactionCategory::Healthcare => Self::Healthcare,
+            domain::TransactionCategory::Utilities => Self::Utilities,
+        }
+    }
+}
+
+impl From<domain::Transaction> for Transaction {
+    fn from(transaction: domain::Transaction) -> Self {
+        Self {
+            id: transaction.id,
+            timestamp: transaction.timestamp,
+            cc_number: transaction.cc_number,
+            category: TransactionCategory::from(transaction.category) as i32,
+            amount_usd_cents: transaction.amount_usd_cents,
+            city: transaction.location.city,
+            country_iso: transaction.location.country_iso,
+            latitude: transaction.location.latitude,
+            longitude: transaction.location.longitude,
+            is_online: transaction.is_online,
+            source: transaction.source,
+            authorization: transaction.authorization.map(Authorization::from),
+        }
+    }
+}
+
+impl From<TransactionData> for Transaction {
+    fn from(data: TransactionData) -> Self {
+        let fields = match data {
+            TransactionData::Full(transaction) => return transaction.into(),
+            TransactionData::Projected(fields) => fields,
+        };
+        let string = |name: &str| fields.get(name).and_then(Value::as_str).map(str::to_string);
+        let number = |name: &str| fields.get(name).and_then(Value::as_f64);
+        let boolean = |name: &str| fields.get(name).and_then(Value::as_bool);
+
+        Self {
+            id: string("id").unwrap_or_default(),
+            timestamp: string("timestamp").unwrap_or_default(),
+            cc_number: string("cc_number").unwrap_or_default(),
+            category: fields
+                .get("category")
+                .and_then(|category| domain::TransactionCategory::deserialize(category).ok())
+                .map_or(TransactionCategory::Unspecified, TransactionCategory::from)
+                as i32,
+            amount_usd_cents: fields
+                .get("amount_usd_cents")
+                .and_then(Value::as_u64)
+                .unwrap_or_default(),
+            city: string("city").unwrap_or_default(),
+            country_iso: string("country_iso").unwrap_or_default(),
+            latitude: number("latitude").unwrap_or_default(),
+            longitude: number("longitude").unwrap_or_default(),
+            is_online: boolean("is_online").unwrap_or_default(),
+            source: string("source"),
+            authorization: fields
+                .get("authorization")
+                .and_then(|authorization| domain::Authorization::deserialize(authorization).ok())
+                .map(Authorization::from),
+        }
+    }
+}
+
+impl From<domain::Authorization> for Authorization {
+    fn from(authorization: domain::Authorization) -> Self {
+        let decision = match authorization.decision {
+            domain::AuthorizationDecision::Approve => AuthorizationDecision::Approve,
+            domain::AuthorizationDecision::Decline => AuthorizationDecision::Decline,
+        };
+        let status = match authorization.status {
+            domain::AuthorizationStatus::Replied => AuthorizationStatus::Replied,
+            domain::AuthorizationStatus::TimedOut => AuthorizationStatus::TimedOut,
+            domain::AuthorizationStatus::Unavailable => AuthorizationStatus::Unavailable,
+        };
+        Self {
+            decision: decision as i32,
+            status: status as i32,
+        }
+    }
+}
+
+impl From<domain::Heartbeat> for Heartbeat {
+    fn from(heartbeat: domain::Heartbeat) -> Self {
+        Self {
+            status: heartbeat.status,
+            server_time: time(heartbeat.server_time),
+            uptime_secs: heartbeat.uptime_secs,
+            transactions_seq: heartbeat.transactions_seq,
+            sources: heartbeat.sources,
+        }
+    }
+}
+
+impl From<domain::CardFeatures> for CardFeatures {
+    fn from(features: domain::CardFeatures) -> Self {
+        Self {
+            count_1h: features.count_1h,
+            amount_1h_usd_cents: features.amount_1h_usd_cents,
+            count_24h: features.count_24h,
+            amount_24h_usd_cents: features.amount_24h_usd_cents,
+            count_7d: features.count_7d,
+            amount_7d_usd_cents: features.amount_7d_usd_cents,
+            secs_since_previous: features.secs_since_previous,
+            km_from_previous: features.km_from_previous,
+            previous_country_iso: features.previous_country_iso,
+            first_in_category: features.first_in_category,
+            first_in_country: features.first_in_country,
+            avg_amount_usd_cents: features.avg_amount_usd_cents,
+        }
+    }
+}
+
+impl From<domain::EnrichedTransaction> for EnrichedTransaction {
+    fn from(enriched: domain::EnrichedTransaction) -> Self {
+        Self {
+            transaction: Some(enriched.transaction.into()),
+            features: Some(enriched.features.into()),
+        }
+    }
+}
+
+impl From<domain::ScoredTransaction> for ScoredTransaction {
+    fn from(scored: domain::ScoredTransaction) -> Self {
+        Self {
+            transaction: Some(scored.transaction.into()),
+            scoring: Some(Score {
+                score: scored.scoring.score,
+                error: scored.scoring.error,
+                attempts: scored.scoring.attempts,
+                latency_ms: scored.scoring.latency_ms,
+            }),
+        }
+    }
+}
+
+impl From<domain::Alert> for Alert {
+    fn from(alert: domain::Alert) -> Self {
+        Self {
+            rule_id: alert.rule_id,
+            score: alert.score,
+            reason: alert.reason,
+            transaction: Some(alert.transaction.into()),
+        }
+    }
+}
+
+impl From<domain::Aggregate> for Aggregate {
+    fn from(aggregate: domain::Aggregate) -> Self {
+        Self {
+            count: aggregate.count,
+            amount_usd_cents: aggregate.amount_usd_cents,
+        }
+    }
+}
+
+impl From<domain::Stats> for Stats {
+    fn from(stats: domain::Stats) -> Self {
+        Self {
+            window: stats.window,
+            start: time(stats.start),
+            end: time(stats.end),
+            total: Some(stats.total.into()),
+            by_category: stats
+                .by_category
+                .into_iter()
+                .map(|(category, aggregate)| (name(&category), aggregate.into()))
+                .collect(),
+            by_country: stats
+                .by_country
+                .into_iter()
+                .map(|(country, aggregate)| (country, aggregate.into()))
+                .collect(),
+            online: Some(stats.online.into()),
+            offline: Some(stats.offline.into()),
+            fraud: stats.fraud.map(|fraud| FraudRate {
+                labelled: fraud.labelled,
+                fraudulent: fraud.fraudulent,
+                rate: fraud.rate,
+            }),
+        }
+    }
+}
+
+impl From<WsRequestId> for RequestId {
+    fn from(id: WsRequestId) -> Self {
+        Self {
+            id: Some(match id {
+                WsRequestId::Number(n) => Id::Number(n),
+                WsRequestId::String(s) => Id::String(s),
+            }),
+        }
+    }
+}
+
+impl From<WsAckNotice> for AckNotice {
+    fn from(ack: WsAckNotice) -> Self {
+        Self {
+            id: ack.id.map(RequestId::from),
+            method: ack.method,
+            channel: ack.channel,
+        }
+    }
+}
+
+impl From<WsErrorNotice> for ErrorNotice {
+    fn from(notice: WsErrorNotice) -> Self {
+        Self {
+            id: notice.id.map(RequestId::from),
+            method: notice.method,
+            code: name(&notice.code),
+            message: notice.message,
+        }
+    }
+}
+
+/// Formats a time as it is serialized in JSON.
+fn time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+/// Returns the name of a unit enum variant as it is serialized in JSON.
+fn name<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(name)) => name,
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    /// Field of a message or value of an enum, as `Name.field = tag: type`.
+    type Schema = BTreeSet<String>;
+
+    /// Reads the schema of `proto/txapi.proto`.
+    fn proto_schema() -> Schema {
+        let source = include_str!("../../proto/txapi.proto");
+        let mut scopes: Vec<(&str, &str)> = Vec::new();
+        let mut fields = Vec::new();
+        let mut schema = Schema::new();
+
+        for line in source.lines() {
+            let line = line.split("//").next().unwrap_or_default().trim();
+            if let Some(header) = line.strip_suffix(" {") {
+                let (kind, name) = header.split_once(' ').expect("scope name");
+                scopes.push((kind, name));
+                continue;
+            }
+            if line == "}" {
+                scopes.pop();
+                continue;
+            }
+            let Some((left, tag)) = line.strip_suffix(';').and_then(|l| l.split_once(" = ")) else {
+                continue;
+            };
+            match scopes.last() {
+                // `syntax = ...` and other top-level options
+                None => {}
+                Some(("enum", name)) => {
+                    let prefix = format!("{}_", screaming_snake_case(name));
+                    let value = left.strip_prefix(&prefix).expect("enum value prefix");
+                    schema.insert(format!("{}.{} = {}", name, value, tag));
+                }
+                _ => {
+                    let message = scopes.iter().rev().find(|(kind, _)| *kind == "message");
+                    let message = message.expect("field outside of a message").1;
+                    let (ty, name) = left.rsplit_once(' ').expect("field type");
+                    let (label, ty) = match ty.split_once(' ') {
+                        Some((label @ ("optional" | "repeated"), ty)) => (label, ty),
+                        _ => ("", ty),
+                    };
+                    fields.push((message, name, tag, label, ty));
+                }
+            }
+        }
+
+        let messages: BTreeSet<_> = source
+            .lines()
+            .filter_map(|line| line.strip_prefix("message ")?.strip_suffix(" {"))
+            .collect();
+        for (message, name, tag, label, ty) in fields {
+            // message fields are always optional in proto3
+            let label = if messages.contains(ty) && label == "optional" {
+                ""
+            } else {
+                label
+            };
+            schema.insert(field(message, name, tag, label, ty));
+        }
+        schema
+    }
+
+    /// Reads the schema of the messages declared with the `prost` derives.
+    fn prost_schema() -> Schema {
+        let source = include_str!("protobuf.rs");
+        let source = source.split("#[cfg(test)]").next().unwrap_or_default();
+        let mut schema = Schema::new();
+        // fields of the oneofs, by oneof name
+        let mut oneofs: BTreeMap<String, Vec<(String, String, String)>> = BTreeMap::new();
+        let mut owners = BTreeMap::new();
+        let mut derive = "";
+        let mut item = "";
+        let mut attribute: Option<&str> = None;
+
+        for line in source.lines().map(str::trim) {
+            if line.starts_with("#[derive(") {
+                derive = line;
+            } else if let Some(name) = line
+                .strip_prefix("pub struct ")
+                .or_else(|| line.strip_prefix("pub enum "))
+            {
+                item = name.trim_end_matches(" {");
+            } else if let Some(args) = line.strip_prefix("#[prost(") {
+                attribute = Some(args.trim_end_matches(")]"));
+            } else if derive.contains("Enumeration") {
+                if let Some((variant, value)) = line.trim_end_matches(',').split_once(" = ") {
+                    let value = format!("{}.{} = {}", item, screaming_snake_case(variant), value);
+                    schema.insert(value);
+                }
+            } else if let Some(args) = attribute.take() {
+                if let Some(oneof) = args.strip_prefix("oneof = \"") {
+                    let oneof = oneof.split('"').next().unwrap_or_default();
+                    owners.insert(oneof.to_string(), item.to_string());
+                    continue;
+                }
+                let tag = args.split("tag = \"").nth(1).expect("field tag");
+                let tag = tag.trim_end_matches('"');
+                let (name, rust_ty) = match line.strip_prefix("pub ") {
+                    Some(field) => field.trim_end_matches(',').split_once(": ").expect("field"),
+                    None => line
+                        .trim_end_matches("),")
+                        .split_once('(')
+                        .expect("variant"),
+                };
+                let rust_ty = rust_ty
+                    .trim_start_matches("Option<")
+                    .trim_start_matches("Vec<")
+                    .trim_end_matches('>');
+                let ty = if let Some(ty) = args.strip_prefix("enumeration = \"") {
+                    ty.split('"').next().unwrap_or_default().to_string()
+                } else if let Some(map) = args.strip_prefix("btree_map = \"") {
+                    let (key, value) = map
+                        .split('"')
+                        .next()
+                        .unwrap_or_default()
+                        .split_once(", ")
+                        .expect("map types");
+                    let value = if value == "message" {
+                        rust_ty.rsplit(", ").next().unwrap_or_default()
+                    } else {
+                        value
+                    };
+                    format!("map<{}, {}>", key, value)
+                } else if args.starts_with("message") {
+                    rust_ty.to_string()
+                } else {
+                    args.split(',').next().unwrap_or_default().to_string()
+                };
+                let label = if args.contains(", repeated,") {
+                    "repeated"
+                } else if args.contains(", optional,") && !args.starts_with("message") {
+                    "optional"
+                } else {
+                    ""
+                };
+
+                if derive.contains("Oneof") {
+                    let variant = (snake_case(name), tag.to_string(), ty);
+                    oneofs.entry(item.to_string()).or_default().push(variant);
+                } else {
+                    schema.insert(field(item, name, tag, label, &ty));
+                }
+            }
+        }
+
+        for (oneof, variants) in oneofs {
+            let owner = owners.get(&oneof).expect("oneof owner");
+            for (name, tag, ty) in variants {
+                schema.insert(field(owner, &name, &tag, "", &ty));
+            }
+        }
+        schema
+    }
+
+    fn field(message: &str, name: &str, tag: &str, label: &str, ty: &str) -> String {
+        format!("{}.{} = {}: {} {}", message, name, tag, label, ty)
+    }
+
+    fn snake_case(name: &str) -> String {
+        let mut snake = String::new();
+        for (idx, c) in name.char_indices() {
+            if c.is_uppercase() && idx > 0 {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        }
+        snake
+    }
+
+    fn screaming_snake_case(name: &str) -> String {
+        snake_case(name).to_uppercase()
+    }
+
+    #[test]
+    fn messages_match_the_proto_file() {
+        let proto = proto_schema();
+        let prost = prost_schema();
+        assert!(proto.len() > 100, "{:#?}", proto);
+
+        let missing: Vec<_> = proto.difference(&prost).collect();
+        let extra: Vec<_> = prost.difference(&proto).collect();
+        assert!(
+            missing.is_empty() && extra.is_empty(),
+            "declared in txapi.proto only: {:#?}\ndeclared in protobuf.rs only: {:#?}",
+            missing,
+            extra
+        );
+    }
+}
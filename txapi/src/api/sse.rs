@@ -0,0 +1,412 @@
+use super::ws::{
+    self,
+    client::WsClient,
+    models::{ChannelMsg, GapNotice, LagNotice},
+};
+use crate::{core::prelude::*, domain::prelude::*, filter::TransactionFilter};
+use axum::{
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Json,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use std::convert::Infallible;
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+
+/// Events waiting to be streamed to a client. Beyond it the client falls
+/// behind the broadcast channel and receives a `lagged` event.
+const EVENT_BUFFER: usize = 100;
+
+/// Query parameters of the SSE endpoint, the filters and projection apply to
+/// the transactions channel.
+///
+/// Lists are comma separated, such as `categories=grocery,travel`.
+#[derive(Deserialize)]
+pub struct SseParams {
+    /// Sequence number to resume the transactions from, when the request has
+    /// no `Last-Event-ID` header
+    pub resume_from: Option<u64>,
+
+    /// Filter expression, see `filter::expr::Expr`
+    pub expr: Option<String>,
+
+    /// Fields of the transactions to send, all of them by default
+    pub fields: Option<String>,
+
+    pub categories: Option<String>,
+    pub countries: Option<String>,
+    pub card_networks: Option<String>,
+    pub min_amount_usd_cents: Option<u64>,
+    pub max_amount_usd_cents: Option<u64>,
+    pub is_online: Option<bool>,
+}
+
+impl SseParams {
+    /// Returns the settings of the subscriber, as those of a websocket client
+    /// subscribed to the transactions channel.
+    fn subscriber(&self) -> Result<WsClient, String> {
+        let expr = self
+            .expr
+            .as_deref()
+            .map(str::parse)
+            .transpose()
+            .map_err(|e| format!("invalid filter expression: {}", e))?;
+        let fields = list::<String>("fields", &self.fields)?;
+        if let Some(fields) = &fields {
+            ws::check_fields(fields)?;
+        }
+        let filter = TransactionFilter {
+            categories: list("categories", &self.categories)?,
+            countries: list("countries", &self.countries)?,
+            min_amount_usd_cents: self.min_amount_usd_cents,
+            max_amount_usd_cents: self.max_amount_usd_cents,
+            is_online: self.is_online,
+            card_networks: list("card_networks", &self.card_networks)?,
+        };
+
+        Ok(WsClient {
+            filter: Some(filter),
+            expr,
+            fields,
+            ..Default::default()
+        })
+    }
+}
+
+/// Server-Sent Events endpoint
+///
+/// Streams the `transactions` or `heartbeat` channel as events named after
+/// the channel, carrying the same JSON payloads as the websocket API. The
+/// transaction events have their sequence number as id, so that a client
+/// reconnecting with `Last-Event-ID` resumes after the last transaction it
+/// received. Returns 404 for the other channels.
+pub async fn endpoint(
+    Path(channel): Path<String>,
+    Query(params): Query<SseParams>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Response {
+    let (tx, rx) = mpsc::channel(EVENT_BUFFER);
+    match channel.as_str() {
+        "transactions" => {
+            let subscriber = match params.subscriber() {
+                Ok(subscriber) => subscriber,
+                Err(message) => {
+                    let body = json!({ "error": message });
+                    return (StatusCode::BAD_REQUEST, Json(body)).into_response();
+                }
+            };
+            let last_event_id = headers
+                .get("last-event-id")
+                .and_then(|id| id.to_str().ok())
+                .and_then(|id| id.trim().parse::<u64>().ok());
+            let from = last_event_id
+                .map(|id| id.saturating_add(1))
+                .or(params.resume_from);
+            tokio::spawn(transactions(tx, subscriber, from, state));
+        }
+        "heartbeat" => {
+            tokio::spawn(heartbeats(tx, state));
+        }
+        _ => {
+            let body = json!({ "error": format!("unknown channel: {}", channel) });
+            return (StatusCode::NOT_FOUND, Json(body)).into_response();
+        }
+    }
+
+    let events = futures::stream::unfold(rx, |mut rx| async {
+        rx.recv()
+            .await
+            .map(|event| (Ok::<_, Infallible>(event), rx))
+    });
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+/// Streams the transactions matching the filters of the subscriber, after
+/// replaying the retained ones from the `from` sequence number if any.
+async fn transactions(
+    tx: mpsc::Sender<Event>,
+    mut subscriber: WsClient,
+    from: Option<u64>,
+    state: AppState,
+) {
+    state.metrics.sse_connections.inc();
+    let mut transactions_rx = state.transactions_tx.subscribe();
+
+    let replayed = async {
+        let Some(from) = from else {
+            return true;
+        };
+        let logged = ws::read_log(from, &state).await;
+        let resume = ws::retained(from, logged, &state);
+        if let Some(available_from) = resume.gap.or(resume.ahead) {
+            let notice = GapNotice {
+                channel: "transactions".to_string(),
+                requested: from,
+                available_from,
+            };
+            if !emit(&tx, "gap", None, ChannelMsg::Gap { data: notice }).await {
+                return false;
+            }
+        }
+        // resuming beyond the live transactions, as after a restart of the
+        // server without event log
+        if resume.ahead.is_some() {
+            return true;
+        }
+        subscriber.replayed_until = Some(
+            resume
+                .replay
+                .last()
+                .map_or(from.saturating_sub(1), |transaction| transaction.seq),
+        );
+        for transaction in resume.replay {
+            if subscriber.matches(&transaction.data)
+                && !emit_transaction(&tx, &subscriber, transaction.seq, transaction.data).await
+            {
+                return false;
+            }
+        }
+        true
+    };
+
+    if replayed.await {
+        loop {
+            let transaction = tokio::select! {
+                _ = state.cancellation_token.cancelled() => break,
+                // the client disconnected
+                _ = tx.closed() => break,
+                transaction = transactions_rx.recv() => transaction,
+            };
+
+            let sent = match transaction {
+                Ok(transaction) => {
+                    if subscriber.was_replayed(transaction.seq)
+                        || !subscriber.matches(&transaction.data)
+                    {
+                        continue;
+                    }
+                    emit_transaction(&tx, &subscriber, transaction.seq, transaction.data).await
+                }
+                Err(RecvError::Lagged(n)) => {
+                    let notice = LagNotice {
+                        channel: "transactions".to_string(),
+                        dropped: n,
+                    };
+                    emit(&tx, "lagged", None, ChannelMsg::Lagged { data: notice }).await
+                }
+                Err(RecvError::Closed) => break,
+            };
+            if !sent {
+                break;
+            }
+        }
+    }
+    state.metrics.sse_connections.dec();
+}
+
+/// Streams the server heartbeats.
+async fn heartbeats(tx: mpsc::Sender<Event>, state: AppState) {
+    state.metrics.sse_connections.inc();
+    let mut heartbeat_rx = state.heartbeat_tx.subscribe();
+
+    loop {
+        let heartbeat = tokio::select! {
+            _ = state.cancellation_token.cancelled() => break,
+            // the client disconnected
+            _ = tx.closed() => break,
+            heartbeat = heartbeat_rx.recv() => heartbeat,
+        };
+
+        let sent = match heartbeat {
+            Ok(heartbeat) => {
+                let msg = ChannelMsg::Heartbeat {
+                    seq: Some(heartbeat.seq),
+                    data: heartbeat.data,
+                };
+                emit(&tx, "heartbeat", None, msg).await
+            }
+            // missed heartbeats are superseded by the next one
+            Err(RecvError::Lagged(_)) => true,
+            Err(RecvError::Closed) => break,
+        };
+        if !sent {
+            break;
+        }
+    }
+    state.metrics.sse_connections.dec();
+}
+
+async fn emit_transaction(
+    tx: &mpsc::Sender<Event>,
+    subscriber: &WsClient,
+    seq: u64,
+    transaction: Transaction,
+) -> bool {
+    let msg = ChannelMsg::Transactions {
+        seq,
+        data: vec![subscriber.project(transaction)],
+    };
+    emit(tx, "transactions", Some(seq), msg).await
+}
+
+/// Queues an event for the client, returning false once it disconnected.
+async fn emit(tx: &mpsc::Sender<Event>, name: &str, id: Option<u64>, msg: ChannelMsg) -> bool {
+    let event = match Event::default().event(name).json_data(&msg) {
+        Ok(event) => event,
+        Err(e) => {
+            tracing::error!("error serializing event: {}", e);
+            return true;
+        }
+    };
+    let event = match id {
+        Some(id) => event.id(id.to_string()),
+        None => event,
+    };
+    tx.send(event).await.is_ok()
+}
+
+/// Parses a comma separated list of values of a query parameter.
+fn list<T: DeserializeOwned>(name: &str, value: &Option<String>) -> Result<Option<Vec<T>>, String> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            serde_json::from_value(json!(item)).map_err(|_| format!("invalid {}: {}", name, item))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::WsConfig;
+    use axum::{routing::get, Router};
+    use serde_json::Value;
+
+    /// Event read from the stream: name, id and data.
+    type SseEvent = (String, Option<u64>, Value);
+
+    /// State retaining the last `retention` of the 5 sent transactions.
+    fn state(retention: usize) -> AppState {
+        let state = AppState::for_tests(100, retention, WsConfig::default());
+        for _ in 0..5 {
+            state.transactions_tx.send(Transaction::simple_mock());
+        }
+        state
+    }
+
+    async fn serve(state: AppState) -> String {
+        let app = Router::new()
+            .route("/sse/v1/{channel}", get(endpoint))
+            .with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/sse/v1", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        url
+    }
+
+    /// Reads the first `count` events of the stream.
+    async fn events(mut response: reqwest::Response, count: usize) -> Vec<SseEvent> {
+        let mut text = String::new();
+        let mut events = Vec::new();
+        while events.len() < count {
+            let chunk = response.chunk().await.unwrap().expect("more events");
+            text.push_str(std::str::from_utf8(&chunk).unwrap());
+            while let Some(end) = text.find("\n\n") {
+                let block: String = text.drain(..end + 2).collect();
+                let (mut name, mut id, mut data) = (String::new(), None, Value::Null);
+                for line in block.lines() {
+                    match line.split_once(':') {
+                        Some(("event", value)) => name = value.trim().to_string(),
+                        Some(("id", value)) => id = value.trim().parse().ok(),
+                        Some(("data", value)) => data = serde_json::from_str(value).unwrap(),
+                        _ => {}
+                    }
+                }
+                if !name.is_empty() {
+                    events.push((name, id, data));
+                }
+            }
+        }
+        events.truncate(count);
+        events
+    }
+
+    async fn request(url: &str, last_event_id: Option<&str>) -> reqwest::Response {
+        let request = reqwest::Client::new().get(url);
+        let request = match last_event_id {
+            Some(id) => request.header("Last-Event-ID", id),
+            None => request,
+        };
+        request.send().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn resumes_after_the_last_event_id() {
+        let url = serve(state(10)).await;
+        let response = request(&format!("{}/transactions", url), Some("2")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let events = events(response, 3).await;
+        let ids: Vec<_> = events.iter().map(|(_, id, _)| *id).collect();
+        assert_eq!(ids, [Some(3), Some(4), Some(5)]);
+        assert!(events.iter().all(|(name, _, _)| name == "transactions"));
+        assert_eq!(events[0].2["seq"], 3);
+    }
+
+    #[tokio::test]
+    async fn notifies_the_gap_beyond_the_retention() {
+        let url = serve(state(2)).await;
+        let response = request(&format!("{}/transactions?resume_from=1", url), None).await;
+
+        let events = events(response, 3).await;
+        let (name, id, data) = &events[0];
+        assert_eq!((name.as_str(), *id), ("gap", None));
+        assert_eq!(data["data"]["requested"], 1);
+        assert_eq!(data["data"]["available_from"], 4);
+        let ids: Vec<_> = events[1..].iter().map(|(_, id, _)| *id).collect();
+        assert_eq!(ids, [Some(4), Some(5)]);
+    }
+
+    #[tokio::test]
+    async fn last_event_id_does_not_overflow() {
+        let url = serve(state(10)).await;
+        let id = u64::MAX.to_string();
+        let response = request(&format!("{}/transactions", url), Some(&id)).await;
+
+        let events = events(response, 1).await;
+        let (name, _, data) = &events[0];
+        assert_eq!(name, "gap");
+        assert_eq!(data["data"]["requested"], u64::MAX);
+    }
+
+    #[tokio::test]
+    async fn invalid_parameters_are_rejected() {
+        let url = serve(state(10)).await;
+        for query in [
+            "categories=nope",
+            "card_networks=nope",
+            "fields=nope",
+            "expr=amount_usd_cents%20%3E",
+            "min_amount_usd_cents=abc",
+        ] {
+            let response = request(&format!("{}/transactions?{}", url, query), None).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", query);
+        }
+        let response = request(&format!("{}/alerts", url), None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
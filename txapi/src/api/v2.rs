@@ -0,0 +1,445 @@
+//! Version 2 of the websocket messages, served on `/ws/v2`.
+//!
+//! Messages are built from the same `ChannelMsg` as version 1 and converted
+//! here, so that both versions are served from one domain model:
+//!
+//! - every message is an event envelope with its `type`, the `version` of the
+//!   schema and the server `time`
+//! - transactions nest their location, card and amount, and have a typed
+//!   UTC timestamp, null when the source sent an invalid one
+//! - enriched and scored transactions, alerts and authorization requests
+//!   nest their transaction instead of flattening it
+//!
+//! Version 1 (`api::ws::models`) keeps its wire format unchanged.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+
+use super::ws::models::{
+    AckNotice, ChannelMsg, ErrorNotice, GapNotice, LagNotice, TransactionData as V1TransactionData,
+};
+use crate::domain::{prelude as domain, transactions::Location};
+
+/// Version of the schema, sent in every event.
+pub const VERSION: u32 = 2;
+
+/// A message sent to the client.
+#[derive(Serialize, Debug)]
+pub struct Event {
+    /// Channel of the event, such as `transactions`, or `ack` and `error`
+    /// for the replies to the requests
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+
+    pub version: u32,
+
+    /// Sequence number, absent on the notices and the client heartbeats
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seq: Option<u64>,
+
+    /// Server time when the event was sent
+    pub time: DateTime<Utc>,
+
+    pub data: EventData,
+}
+
+/// Payload of an event, depending on its type.
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum EventData {
+    Transactions(Vec<TransactionData>),
+    EnrichedTransactions(Vec<EnrichedTransaction>),
+    ScoredTransactions(Vec<ScoredTransaction>),
+    Alert(Alert),
+    Authorization(AuthorizationRequest),
+    Heartbeat(domain::Heartbeat),
+    Stats(domain::Stats),
+    Gap(GapNotice),
+    Lagged(LagNotice),
+    Ack(AckNotice),
+    Error(ErrorNotice),
+}
+
+/// A transaction sent to the client, whole or limited to the fields
+/// requested on subscribe.
+///
+/// The fields are requested with their version 1 names, `city` selecting
+/// `location.city` and `cc_number` the whole `card` for example.
+#[derive(Serialize, Debug)]
+#[serde(untagged)]
+pub enum TransactionData {
+    Full(Transaction),
+    Projected(Map<String, Value>),
+}
+
+#[derive(Serialize, Debug)]
+pub struct Transaction {
+    pub id: String,
+
+    /// Time of the transaction, in UTC, null when it is not RFC 3339
+    pub timestamp: Option<DateTime<Utc>>,
+
+    /// The timestamp as sent by the source, when it is not RFC 3339
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_timestamp: Option<String>,
+
+    pub card: Card,
+
+    pub category: domain::TransactionCategory,
+
+    pub amount: Amount,
+
+    pub location: Location,
+
+    pub is_online: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authorization: Option<domain::Authorization>,
+}
+
+/// Card of a transaction.
+#[derive(Serialize, Debug)]
+pub struct Card {
+    /// Credit card number (mock data only)
+    pub number: String,
+
+    /// Network, derived from the card number prefix
+    pub network: domain::CardNetwork,
+}
+
+/// Amount of a transaction.
+#[derive(Serialize, Debug)]
+pub struct Amount {
+    /// Amount in the minor unit of the currency, 4599 being $45.99
+    pub cents: u64,
+
+    /// ISO 4217 currency code
+    pub currency: &'static str,
+}
+
+#[derive(Serialize, Debug)]
+pub struct EnrichedTransaction {
+    pub transaction: Transaction,
+    pub features: domain::CardFeatures,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ScoredTransaction {
+    pub transaction: Transaction,
+    pub scoring: domain::Score,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Alert {
+    pub rule_id: String,
+    pub score: f64,
+    pub reason: String,
+    pub transaction: EnrichedTransaction,
+}
+
+#[derive(Serialize, Debug)]
+pub struct AuthorizationRequest {
+    pub timeout_ms: u64,
+    pub transaction: Transaction,
+}
+
+impl From<ChannelMsg> for Event {
+    fn from(msg: ChannelMsg) -> Self {
+        let (kind, seq, data) = match msg {
+            ChannelMsg::Transactions { seq, data } => (
+                "transactions",
+                Some(seq),
+                EventData::Transactions(data.into_iter().map(TransactionData::from).collect()),
+            ),
+            ChannelMsg::Heartbeat { seq, data } => ("heartbeat", seq, EventData::Heartbeat(data)),
+            ChannelMsg::EnrichedTransactions { seq, data } => (
+                "enriched_transactions",
+                Some(seq),
+                EventData::EnrichedTransactions(
+                    data.into_iter().map(EnrichedTransaction::from).collect(),
+                ),
+            ),
+            ChannelMsg::ScoredTransactions { seq, data } => (
+                "scored_transactions",
+                Some(seq),
+                EventData::ScoredTransactions(
+                    data.into_iter().map(ScoredTransaction::from).collect(),
+                ),
+            ),
+            ChannelMsg::Alerts { seq, data } => {
+                ("alerts", Some(seq), EventData::Alert(data.into()))
+            }
+            ChannelMsg::Stats { seq, data } => ("stats", Some(seq), EventData::Stats(data)),
+            ChannelMsg::Authorization { data } => (
+                "authorization",
+                None,
+                EventData::Authorization(AuthorizationRequest {
+                    timeout_ms: data.timeout_ms,
+                    transaction: data.transaction.into(),
+                }),
+            ),
+            ChannelMsg::Gap { data } => ("gap", None, EventData::Gap(data)),
+            ChannelMsg::Lagged { data } => ("lagged", None, EventData::Lagged(data)),
+            ChannelMsg::Ack { data } => ("ack", None, EventData::Ack(data)),
+            ChannelMsg::Error { data } => ("error", None, EventData::Error(data)),
+        };
+        Self {
+            kind,
+            version: VERSION,
+            seq,
+            time: Utc::now(),
+            data,
+        }
+    }
+}
+
+impl From<domain::Transaction> for Transaction {
+    fn from(transaction: domain::Transaction) -> Self {
+        let (timestamp, raw_timestamp) = timestamp(transaction.timestamp);
+        Self {
+            timestamp,
+            raw_timestamp,
+            card: Card {
+                network: domain::CardNetwork::from_cc_number(&transaction.cc_number),
+                number: transaction.cc_number,
+            },
+            id: transaction.id,
+            category: transaction.category,
+            amount: Amount {
+                cents: transaction.amount_usd_cents,
+                currency: "USD",
+            },
+            location: transaction.location,
+            is_online: transaction.is_online,
+            source: transaction.source,
+            authorization: transaction.authorization,
+        }
+    }
+}
+
+impl From<V1TransactionData> for TransactionData {
+    fn from(data: V1TransactionData) -> Self {
+        let fields = match data {
+            V1TransactionData::Full(transaction) => return Self::Full(transaction.into()),
+            V1TransactionData::Projected(fields) => fields,
+        };
+
+        let mut projected = Map::new();
+        let mut location = Map::new();
+        for (name, value) in fields {
+            match name.as_str() {
+                "timestamp" => {
+                    let raw = value.as_str().unwrap_or_default().to_string();
+                    let (at, raw) = timestamp(raw);
+                    projected.insert(name, json!(at));
+                    if let Some(raw) = raw {
+                        projected.insert("raw_timestamp".to_string(), json!(raw));
+                    }
+                }
+                "cc_number" => {
+                    let number = value.as_str().unwrap_or_default();
+                    let network = domain::CardNetwork::from_cc_number(number);
+                    projected.insert(
+                        "card".to_string(),
+                        json!(Card {
+                            number: number.to_string(),
+                            network
+                        }),
+                    );
+                }
+                "amount_usd_cents" => {
+                    projected.insert(
+                        "amount".to_string(),
+                        json!({ "cents": value, "currency": "USD" }),
+                    );
+                }
+                "city" | "country_iso" | "latitude" | "longitude" => {
+                    location.insert(name, value);
+                }
+                _ => {
+                    projected.insert(name, value);
+                }
+            }
+        }
+        if !location.is_empty() {
+            projected.insert("location".to_string(), Value::Object(location));
+        }
+        Self::Projected(projected)
+    }
+}
+
+impl From<domain::EnrichedTransaction> for EnrichedTransaction {
+    fn from(enriched: domain::EnrichedTransaction) -> Self {
+        Self {
+            transaction: enriched.transaction.into(),
+            features: enriched.features,
+        }
+    }
+}
+
+impl From<domain::ScoredTransaction> for ScoredTransaction {
+    fn from(scored: domain::ScoredTransaction) -> Self {
+        Self {
+            transaction: scored.transaction.into(),
+            scoring: scored.scoring,
+        }
+    }
+}
+
+impl From<domain::Alert> for Alert {
+    fn from(alert: domain::Alert) -> Self {
+        Self {
+            rule_id: alert.rule_id,
+            score: alert.score,
+            reason: alert.reason,
+            transaction: alert.transaction.into(),
+        }
+    }
+}
+
+/// Parses an RFC 3339 timestamp, returning the raw one instead when it is
+/// invalid, as custom sources may not validate their timestamps.
+fn timestamp(raw: String) -> (Option<DateTime<Utc>>, Option<String>) {
+    match DateTime::parse_from_rfc3339(&raw) {
+        Ok(at) => (Some(at.with_timezone(&Utc)), None),
+        Err(_) => (None, Some(raw)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::ws::models::TransactionData as V1TransactionData;
+    use std::collections::BTreeMap;
+
+    /// The example transaction, with an offset to normalize and a label
+    /// that must not be serialized.
+    fn transaction() -> domain::Transaction {
+        domain::Transaction {
+            timestamp: "2024-01-01T01:00:00+01:00".to_string(),
+            is_fraud: Some(false),
+            source: Some("mock".to_string()),
+            ..domain::Transaction::fixture()
+        }
+    }
+
+    fn heartbeat() -> domain::Heartbeat {
+        domain::Heartbeat {
+            status: "ok".to_string(),
+            server_time: "2024-01-01T00:00:00Z".parse().unwrap(),
+            uptime_secs: 120,
+            transactions_seq: Some(1043),
+            sources: BTreeMap::from([("mock".to_string(), "running".to_string())]),
+        }
+    }
+
+    fn v2(msg: ChannelMsg) -> String {
+        let mut event = Event::from(msg);
+        event.time = "2024-01-01T00:00:01Z".parse().unwrap();
+        serde_json::to_string(&event).unwrap()
+    }
+
+    fn v1(msg: &ChannelMsg) -> String {
+        serde_json::to_string(msg).unwrap()
+    }
+
+    #[test]
+    fn v1_transaction() {
+        let msg = ChannelMsg::Transactions {
+            seq: 1043,
+            data: vec![V1TransactionData::Full(transaction())],
+        };
+        assert_eq!(
+            v1(&msg),
+            r#"{"channel":"transactions","seq":1043,"data":[{"id":"11df919988c134d97bbff2678eb68e22","timestamp":"2024-01-01T01:00:00+01:00","cc_number":"4473593503484549","category":"grocery","amount_usd_cents":10000,"city":"San Francisco","country_iso":"US","latitude":37.774929,"longitude":-122.419418,"is_online":false,"source":"mock"}]}"#
+        );
+    }
+
+    #[test]
+    fn v1_heartbeat() {
+        let msg = ChannelMsg::Heartbeat {
+            seq: Some(12),
+            data: heartbeat(),
+        };
+        assert_eq!(
+            v1(&msg),
+            r#"{"channel":"heartbeat","seq":12,"data":{"status":"ok","server_time":"2024-01-01T00:00:00Z","uptime_secs":120,"transactions_seq":1043,"sources":{"mock":"running"}}}"#
+        );
+    }
+
+    #[test]
+    fn v2_transaction() {
+        let msg = ChannelMsg::Transactions {
+            seq: 1043,
+            data: vec![V1TransactionData::Full(transaction())],
+        };
+        assert_eq!(
+            v2(msg),
+            r#"{"type":"transactions","version":2,"seq":1043,"time":"2024-01-01T00:00:01Z","data":[{"id":"11df919988c134d97bbff2678eb68e22","timestamp":"2024-01-01T00:00:00Z","card":{"number":"4473593503484549","network":"visa"},"category":"grocery","amount":{"cents":10000,"currency":"USD"},"location":{"city":"San Francisco","country_iso":"US","latitude":37.774929,"longitude":-122.419418},"is_online":false,"source":"mock"}]}"#
+        );
+    }
+
+    #[test]
+    fn v2_heartbeat() {
+        let msg = ChannelMsg::Heartbeat {
+            seq: Some(12),
+            data: heartbeat(),
+        };
+        assert_eq!(
+            v2(msg),
+            r#"{"type":"heartbeat","version":2,"seq":12,"time":"2024-01-01T00:00:01Z","data":{"status":"ok","server_time":"2024-01-01T00:00:00Z","uptime_secs":120,"transactions_seq":1043,"sources":{"mock":"running"}}}"#
+        );
+    }
+
+    #[test]
+    fn v2_projected_transaction() {
+        let mut fields = Map::new();
+        fields.insert("timestamp".to_string(), json!("2024-01-01T00:00:00Z"));
+        fields.insert("city".to_string(), json!("San Francisco"));
+        fields.insert("amount_usd_cents".to_string(), json!(10000));
+        let msg = ChannelMsg::Transactions {
+            seq: 1043,
+            data: vec![V1TransactionData::Projected(fields)],
+        };
+        assert_eq!(
+            v2(msg),
+            r#"{"type":"transactions","version":2,"seq":1043,"time":"2024-01-01T00:00:01Z","data":[{"amount":{"cents":10000,"currency":"USD"},"location":{"city":"San Francisco"},"timestamp":"2024-01-01T00:00:00Z"}]}"#
+        );
+    }
+
+    #[test]
+    fn fraud_label_is_not_sent() {
+        let mut transaction = transaction();
+        transaction.is_fraud = Some(true);
+        let msg = || ChannelMsg::Transactions {
+            seq: 1043,
+            data: vec![V1TransactionData::Full(transaction.clone())],
+        };
+
+        assert!(!v1(&msg()).contains("is_fraud"));
+        assert!(!v2(msg()).contains("is_fraud"));
+        let msgpack = rmp_serde::to_vec_named(&msg()).unwrap();
+        assert!(!msgpack.windows(8).any(|bytes| bytes == b"is_fraud"));
+
+        // the label is still read from the ingested and replayed transactions
+        let json = serde_json::to_value(&transaction).unwrap();
+        assert_eq!(json.get("is_fraud"), None);
+        let mut labelled = json;
+        labelled["is_fraud"] = json!(true);
+        let read: domain::Transaction = serde_json::from_value(labelled).unwrap();
+        assert_eq!(read.is_fraud, Some(true));
+    }
+
+    #[test]
+    fn v2_invalid_timestamp() {
+        let mut transaction = transaction();
+        transaction.timestamp = "1700000000".to_string();
+        let transaction = serde_json::to_value(Transaction::from(transaction)).unwrap();
+        assert_eq!(transaction["timestamp"], Value::Null);
+        assert_eq!(transaction["raw_timestamp"], "1700000000");
+    }
+}
@@ -1,26 +1,211 @@
-use crate::core::prelude::*;
+use super::{has_bearer_token, jsonrpc, protobuf, v2};
+use crate::{
+    core::{config::WsConfig, decisions::DecisionError, prelude::*},
+    domain::prelude::*,
+    stream::{
+        eventlog::{LogEntry, LogQuery},
+        sequenced::{Resume, Sequenced},
+    },
+};
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        State,
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Query, State,
     },
-    response::IntoResponse,
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
 };
 use futures::{
     sink::SinkExt,
     stream::{SplitSink, SplitStream, StreamExt},
 };
-use models::{ChannelMsg, WsMessage};
-use std::sync::Arc;
-use tokio::sync::Mutex;
-use tracing::{debug, error};
+use models::{
+    AckNotice, ChannelMsg, ErrorCode, ErrorNotice, GapNotice, LagNotice, Request, RequestId,
+    TransactionData, WsMessage,
+};
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{broadcast::error::RecvError, mpsc, Mutex},
+    time::{self, Interval},
+};
+use tracing::{debug, error, info, warn};
+
+/// Query parameters of the websocket endpoint.
+#[derive(serde::Deserialize)]
+pub struct ConnectParams {
+    /// Identifies the client in the evaluation of its decisions, a random
+    /// id is assigned when omitted.
+    pub client_id: Option<String>,
+}
+
+/// Version of the websocket API, selected by the endpoint path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    /// `/ws/v1`, see `models`
+    V1,
+    /// `/ws/v2`, see `api::v2`
+    V2,
+}
+
+impl ApiVersion {
+    /// Subprotocols offered by the server, in order of preference. Version 2
+    /// is only available in the encodings of the serde models.
+    fn subprotocols(self) -> &'static [&'static str] {
+        match self {
+            Self::V1 => &Protocol::SUBPROTOCOLS,
+            Self::V2 => &[Protocol::MESSAGEPACK, Protocol::CBOR],
+        }
+    }
+}
+
+/// Protocol of a connection, negotiated with the `Sec-WebSocket-Protocol`
+/// header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// The `method`/`params` messages and `channel` tagged replies, by default
+    Json,
+    /// JSON-RPC 2.0, see `api::jsonrpc`
+    JsonRpc,
+    /// The default protocol, with the messages encoded in MessagePack
+    MessagePack,
+    /// The default protocol, with the messages encoded in CBOR
+    Cbor,
+    /// The default protocol, with the messages encoded in Protobuf, see
+    /// `api::protobuf`
+    Protobuf,
+}
+
+impl Protocol {
+    /// Subprotocols offered by the server, in order of preference.
+    const SUBPROTOCOLS: [&'static str; 4] = [
+        jsonrpc::SUBPROTOCOL,
+        Self::MESSAGEPACK,
+        Self::CBOR,
+        protobuf::SUBPROTOCOL,
+    ];
+    const MESSAGEPACK: &'static str = "txapi.msgpack";
+    const CBOR: &'static str = "txapi.cbor";
+
+    fn from_subprotocol(subprotocol: Option<&HeaderValue>) -> Self {
+        match subprotocol.and_then(|subprotocol| subprotocol.to_str().ok()) {
+            Some(jsonrpc::SUBPROTOCOL) => Self::JsonRpc,
+            Some(Self::MESSAGEPACK) => Self::MessagePack,
+            Some(Self::CBOR) => Self::Cbor,
+            Some(protobuf::SUBPROTOCOL) => Self::Protobuf,
+            _ => Self::Json,
+        }
+    }
+}
+
+/// The sending half of a websocket, encoding the messages in the protocol
+/// of the connection.
+pub struct Outbound {
+    sink: SplitSink<WebSocket, Message>,
+    protocol: Protocol,
+    version: ApiVersion,
+    /// Subscription ids, for the JSON-RPC protocol
+    subscriptions: jsonrpc::Subscriptions,
+}
+
+impl Outbound {
+    /// Parses a request in the protocol of the connection.
+    ///
+    /// Requests are JSON text messages whatever the encoding of the replies.
+    fn parse(&self, text: &str) -> Result<Request, ErrorNotice> {
+        match self.protocol {
+            Protocol::JsonRpc => jsonrpc::parse(text, &self.subscriptions),
+            _ => Request::parse(text),
+        }
+    }
+
+    /// Decodes a request sent as a binary message, in the MessagePack and
+    /// CBOR encodings only.
+    fn decode(&self, bytes: &[u8]) -> Result<Request, ErrorNotice> {
+        let value = match self.protocol {
+            Protocol::MessagePack => {
+                rmp_serde::from_slice::<serde_json::Value>(bytes).map_err(|e| e.to_string())
+            }
+            Protocol::Cbor => {
+                ciborium::from_reader::<serde_json::Value, _>(bytes).map_err(|e| e.to_string())
+            }
+            _ => {
+                return Err(ErrorNotice {
+                    id: None,
+                    method: None,
+                    code: ErrorCode::InvalidRequest,
+                    message: "binary requests are not supported, send JSON text".to_string(),
+                })
+            }
+        };
+        match value {
+            Ok(value) => Request::from_value(value),
+            Err(e) => Err(ErrorNotice {
+                id: None,
+                method: None,
+                code: ErrorCode::ParseError,
+                message: format!("invalid {:?}: {}", self.protocol, e),
+            }),
+        }
+    }
+}
 
 /// The endpoint for the websocket API.
 ///
 /// This function upgrades the websocket connection and handles the incoming
 /// messages.
-pub async fn endpoint(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| handle(socket, state))
+pub async fn endpoint(
+    ws: WebSocketUpgrade,
+    Query(params): Query<ConnectParams>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    upgrade(ws, params, &headers, state, ApiVersion::V1)
+}
+
+/// The endpoint for the version 2 of the websocket API.
+pub async fn endpoint_v2(
+    ws: WebSocketUpgrade,
+    Query(params): Query<ConnectParams>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    upgrade(ws, params, &headers, state, ApiVersion::V2)
+}
+
+/// Upgrades the connection. The authorizing client must present the
+/// AUTHORIZATION_TOKEN as a bearer token, so that no other connection can
+/// take over the authorization path.
+fn upgrade(
+    ws: WebSocketUpgrade,
+    params: ConnectParams,
+    headers: &HeaderMap,
+    state: AppState,
+    version: ApiVersion,
+) -> Response {
+    let client_id = params
+        .client_id
+        .filter(|id| !id.is_empty())
+        .unwrap_or_else(|| format!("anonymous-{:08x}", rand::random::<u32>()));
+    let authorizer = state
+        .authorizer
+        .as_ref()
+        .map(|authorizer| authorizer.config());
+    if let Some(config) = authorizer.filter(|config| config.client_id == client_id) {
+        if !has_bearer_token(headers, &config.token) {
+            warn!(
+                "Rejected authorizing client {}: invalid or missing bearer token",
+                client_id
+            );
+            return (StatusCode::UNAUTHORIZED, "invalid or missing bearer token").into_response();
+        }
+    }
+    let ws = ws.protocols(version.subprotocols().iter().copied());
+    let protocol = Protocol::from_subprotocol(ws.selected_protocol());
+    ws.on_upgrade(move |socket| handle(socket, client_id, protocol, version, state))
 }
 
 /// Handles the incoming messages from the websocket.
@@ -29,13 +214,37 @@ pub async fn endpoint(ws: WebSocketUpgrade, State(state): State<AppState>) -> im
 /// creates a channel for messages between the websocket and the server.
 ///
 /// It then spawns two tasks to handle the reading and writing of messages.
-async fn handle(socket: WebSocket, state: AppState) {
+async fn handle(
+    socket: WebSocket,
+    client_id: String,
+    protocol: Protocol,
+    version: ApiVersion,
+    state: AppState,
+) {
     let (sender, receiver) = socket.split();
+    state.metrics.ws_connections.inc();
+    debug!(
+        "Client {} connected to {:?} with {:?}",
+        client_id, version, protocol
+    );
 
-    let client = Arc::new(Mutex::new(client::WsClient::default()));
-    let sender = Arc::new(Mutex::new(sender));
+    let client = Arc::new(Mutex::new(client::WsClient {
+        id: client_id,
+        ..Default::default()
+    }));
+    let sender = Arc::new(Mutex::new(Outbound {
+        sink: sender,
+        protocol,
+        version,
+        subscriptions: Default::default(),
+    }));
 
-    let read_task = tokio::spawn(read(receiver, client.clone()));
+    let read_task = tokio::spawn(read(
+        receiver,
+        client.clone(),
+        sender.clone(),
+        state.clone(),
+    ));
     let write_task = tokio::spawn(write(sender, client, state.clone()));
 
     tokio::pin!(read_task);
@@ -57,22 +266,107 @@ async fn handle(socket: WebSocket, state: AppState) {
             }
         },
     }
+
+    state.metrics.ws_connections.dec();
 }
 
 /// Read side of the websocket connection.
 ///
 /// This function reads messages from the websocket and handles
 /// the received messages.
-async fn read(mut receiver: SplitStream<WebSocket>, client: Arc<Mutex<client::WsClient>>) {
-    while let Some(Ok(msg)) = receiver.next().await {
-        if let Message::Text(text) = msg {
-            match serde_json::from_str::<WsMessage>(&text) {
-                Err(e) => error!("Invalid message: {}", e),
-                Ok(ws_msg) => {
-                    let mut client = client.lock().await;
-                    handle_incoming(&ws_msg, &mut client).await;
+///
+/// It also keeps the connection alive: the client is pinged every
+/// `WsConfig::ping_interval`, and the connection is closed when no pong
+/// comes back within `WsConfig::pong_timeout`, or when the client sends
+/// nothing for `WsConfig::idle_timeout`. The task exits when the client
+/// closes the connection.
+async fn read(
+    mut receiver: SplitStream<WebSocket>,
+    client: Arc<Mutex<client::WsClient>>,
+    sender: Arc<Mutex<Outbound>>,
+    state: AppState,
+) {
+    let config = &state.ws_config;
+
+    let mut ping_timer = config
+        .ping_interval
+        .map(|interval| time::interval_at(time::Instant::now() + interval, interval));
+    let mut pong_deadline: Option<time::Instant> = None;
+    let mut last_activity = time::Instant::now();
+
+    let reason = loop {
+        let idle_deadline = config.idle_timeout.map(|timeout| last_activity + timeout);
+
+        tokio::select! {
+            msg = receiver.next() => {
+                let msg = match msg {
+                    None => break None,
+                    Some(Err(e)) => {
+                        debug!("error reading message: {:?}", e);
+                        break None;
+                    }
+                    Some(Ok(msg)) => msg,
+                };
+
+                match msg {
+                    Message::Text(text) => {
+                        last_activity = time::Instant::now();
+                        let request = sender.lock().await.parse(&text);
+                        handle_request(request, &client, &sender, &state).await;
+                    }
+                    Message::Binary(bytes) => {
+                        last_activity = time::Instant::now();
+                        let request = sender.lock().await.decode(&bytes);
+                        handle_request(request, &client, &sender, &state).await;
+                    }
+                    // pings are answered by the websocket library
+                    Message::Ping(_) => debug!("received ping"),
+                    Message::Pong(_) => pong_deadline = None,
+                    Message::Close(frame) => {
+                        match frame {
+                            Some(frame) => info!("Client closed the connection: {} {}", frame.code, frame.reason),
+                            None => info!("Client closed the connection"),
+                        }
+                        break None;
+                    }
                 }
             }
+
+            // ping the client, unless a pong is still expected
+            _ = tick_opt(ping_timer.as_mut()), if pong_deadline.is_none() => {
+                let deadline = time::Instant::now() + config.pong_timeout;
+                pong_deadline = Some(deadline);
+
+                // the writer may be stuck on the full socket of a vanished peer,
+                // which is a pong timeout as well
+                let ping = async {
+                    let mut sender = sender.lock().await;
+                    sender.sink.send(Message::Ping(Default::default())).await
+                };
+                match time::timeout_at(deadline, ping).await {
+                    Ok(Ok(())) => {}
+                    Ok(Err(e)) => debug!("error sending ping: {:?}", e),
+                    Err(_) => break Some("pong timeout"),
+                }
+            }
+
+            _ = sleep_until_opt(pong_deadline) => break Some("pong timeout"),
+
+            _ = sleep_until_opt(idle_deadline) => break Some("idle timeout"),
+        }
+    };
+
+    if let Some(reason) = reason {
+        info!("Closing connection: {}", reason);
+        state.metrics.ws_keepalive_disconnects.inc();
+
+        // the peer may be gone, do not wait on a full socket forever
+        let closing = async {
+            let mut sender = sender.lock().await;
+            close(&mut sender, close_code::AWAY, reason).await;
+        };
+        if time::timeout(config.pong_timeout, closing).await.is_err() {
+            debug!("timed out sending close frame");
         }
     }
 }
@@ -81,8 +375,19 @@ async fn read(mut receiver: SplitStream<WebSocket>, client: Arc<Mutex<client::Ws
 ///
 /// This function handles the writing of messages to the websocket. It streams
 /// the data for each of the client's subscribed channels.
+///
+/// When the client falls behind the broadcast buffer of a channel, the lag
+/// policy of `Relay::lagged` applies. The task exits when the broadcast
+/// channels are closed.
+///
+/// Clients that override the heartbeat interval get their own heartbeats,
+/// built from the server status, instead of the broadcast ones.
+///
+/// Clients that requested batching get their transactions in batches, sent
+/// when full or when the oldest pending transaction has waited for the
+/// maximum linger time.
 async fn write(
-    sender: Arc<Mutex<SplitSink<WebSocket, Message>>>,
+    sender: Arc<Mutex<Outbound>>,
     client: Arc<Mutex<client::WsClient>>,
     state: AppState,
 ) {
@@ -91,71 +396,757 @@ async fn write(
     // Create subscriptions for heartbeat and transactions channels.
     let mut heartbeat_rx = state.heartbeat_tx.subscribe();
     let mut transactions_rx = state.transactions_tx.subscribe();
+    let mut enriched_rx = state.enriched_tx.subscribe();
+    let mut alerts_rx = state.alerts_tx.subscribe();
+    let mut scored_rx = state.scored_tx.subscribe();
+    let mut stats_rx = state.stats_tx.subscribe();
+    let mut relay = Relay {
+        sender: &sender,
+        client: &client,
+        state: &state,
+        lag_events: VecDeque::new(),
+    };
+
+    // the authorizing client receives the authorization requests
+    let client_id = client.lock().await.id.clone();
+    let mut authorizations_rx = state
+        .authorizer
+        .as_ref()
+        .filter(|authorizer| authorizer.config().client_id == client_id)
+        .map(|authorizer| {
+            info!("Authorizing client {} connected", client_id);
+            authorizer.connect()
+        });
+
+    let updated = client.lock().await.updated.clone();
+    let mut heartbeat_timer: Option<(Duration, Interval)> = None;
+    let mut batch = Vec::new();
+    let mut linger_deadline = None;
 
     loop {
+        let (interval, batching) = {
+            let client = client.lock().await;
+            (client.heartbeat_interval, client.batching)
+        };
+
+        // flush the pending batch when batching was turned off
+        if batching.is_none() && !batch.is_empty() {
+            flush(&sender, &mut batch, &state).await;
+            linger_deadline = None;
+        }
+
+        // (re)start the client heartbeat timer when the interval was overridden
+        if interval != heartbeat_timer.as_ref().map(|(interval, _)| *interval) {
+            heartbeat_timer = interval.map(|interval| {
+                let timer = time::interval_at(time::Instant::now() + interval, interval);
+                (interval, timer)
+            });
+        }
+
         tokio::select! {
-            // heartbeat channel - all clients
+            // client settings changed
+            _ = updated.notified() => {}
+
+            // heartbeat channel - all clients, unless they have their own heartbeats
             heartbeat = heartbeat_rx.recv() => {
-                match heartbeat {
-                    Err(_) => error!("Error receiving heartbeat from channel"),
-                    Ok(heartbeat) => {
+                if heartbeat.is_ok() && heartbeat_timer.is_some() {
+                    continue;
+                }
+                let flow = relay.forward(Channel::Heartbeat, heartbeat, |heartbeat| {
+                    ChannelMsg::Heartbeat { seq: Some(heartbeat.seq), data: heartbeat.data }
+                }).await;
+                match flow {
+                    Flow::Continue => {}
+                    Flow::Closed => break,
+                    Flow::Disconnected => return,
+                }
+            }
+
+            // client heartbeats, at the overridden interval
+            _ = tick(&mut heartbeat_timer) => {
+                let mut sender = sender.lock().await;
+                let msg = ChannelMsg::Heartbeat { seq: None, data: state.status.heartbeat() };
+                send(&mut sender, msg, &state).await;
+            }
+
+            // enriched transactions channel
+            enriched = enriched_rx.recv() => {
+                let flow = relay.forward(Channel::EnrichedTransactions, enriched, |enriched| {
+                    ChannelMsg::EnrichedTransactions { seq: enriched.seq, data: vec![enriched.data] }
+                }).await;
+                match flow {
+                    Flow::Continue => {}
+                    Flow::Closed => break,
+                    Flow::Disconnected => return,
+                }
+            }
+
+            // scored transactions channel
+            scored = scored_rx.recv() => {
+                let flow = relay.forward(Channel::ScoredTransactions, scored, |scored| {
+                    ChannelMsg::ScoredTransactions { seq: scored.seq, data: vec![scored.data] }
+                }).await;
+                match flow {
+                    Flow::Continue => {}
+                    Flow::Closed => break,
+                    Flow::Disconnected => return,
+                }
+            }
+
+            // alerts channel
+            alert = alerts_rx.recv() => {
+                let flow = relay.forward(Channel::Alerts, alert, |alert| {
+                    ChannelMsg::Alerts { seq: alert.seq, data: alert.data }
+                }).await;
+                match flow {
+                    Flow::Continue => {}
+                    Flow::Closed => break,
+                    Flow::Disconnected => return,
+                }
+            }
+
+            // stats channel
+            stats = stats_rx.recv() => {
+                let flow = relay.forward(Channel::Stats, stats, |stats| {
+                    ChannelMsg::Stats { seq: stats.seq, data: stats.data }
+                }).await;
+                match flow {
+                    Flow::Continue => {}
+                    Flow::Closed => break,
+                    Flow::Disconnected => return,
+                }
+            }
+
+            // authorization requests, for the authorizing client only
+            request = recv_opt(authorizations_rx.as_mut()) => {
+                match request {
+                    // replaced by a newer connection of the client
+                    None => authorizations_rx = None,
+                    Some(request) => {
                         let mut sender = sender.lock().await;
-                        send(&mut sender, ChannelMsg::Heartbeat { data: heartbeat }).await;
+                        send(&mut sender, ChannelMsg::Authorization { data: request }, &state).await;
                     }
                 }
             }
 
+            // partial batch waited long enough
+            _ = sleep_until_opt(linger_deadline) => {
+                flush(&sender, &mut batch, &state).await;
+                linger_deadline = None;
+            }
+
             // transactions channel
             transaction = transactions_rx.recv() => {
                 match transaction {
-                    Err(_) => error!("Error receiving transaction from channel"),
+                    Err(RecvError::Lagged(dropped)) => {
+                        if !client.lock().await.is_subscribed(&Channel::Transactions) {
+                            continue;
+                        }
+                        state.metrics.ws_dropped_transactions.add(dropped);
+                        // the pending transactions precede the dropped ones
+                        flush(&sender, &mut batch, &state).await;
+                        linger_deadline = None;
+                        if let Flow::Disconnected = relay.lagged(Channel::Transactions, dropped).await {
+                            return;
+                        }
+                    }
+                    Err(RecvError::Closed) => break,
                     Ok(transaction) => {
                         let client = client.lock().await;
-                        if client.is_subscribed(&Channel::Transactions) {
-                            let mut sender = sender.lock().await;
-                            send(&mut sender, ChannelMsg::Transactions { data: vec![transaction] }).await;
+                        if !client.is_subscribed(&Channel::Transactions)
+                            || client.was_replayed(transaction.seq)
+                            || !client.matches(&transaction.data) {
+                            continue;
+                        }
+                        match client.batching {
+                            None => {
+                                let mut sender = sender.lock().await;
+                                let msg = ChannelMsg::Transactions { seq: transaction.seq, data: vec![client.project(transaction.data)] };
+                                send(&mut sender, msg, &state).await;
+                            }
+                            Some(batching) => {
+                                if batch.is_empty() {
+                                    linger_deadline = Some(time::Instant::now() + batching.max_linger);
+                                }
+                                batch.push(Sequenced { seq: transaction.seq, data: client.project(transaction.data) });
+                                if batch.len() >= batching.max_size {
+                                    flush(&sender, &mut batch, &state).await;
+                                    linger_deadline = None;
+                                }
+                            }
                         }
                     }
                 }
             }
         }
     }
+
+    info!("Broadcast channel closed, closing connection");
+    flush(&sender, &mut batch, &state).await;
+    let mut sender = sender.lock().await;
+    close(&mut sender, close_code::AWAY, "server shutting down").await;
+}
+
+/// What the write task does after a message of a broadcast channel.
+enum Flow {
+    Continue,
+    /// The channel was closed, the server is shutting down
+    Closed,
+    /// The client was disconnected
+    Disconnected,
+}
+
+/// Forwards the messages of the broadcast channels to a client.
+struct Relay<'a> {
+    sender: &'a Mutex<Outbound>,
+    client: &'a Mutex<client::WsClient>,
+    state: &'a AppState,
+    /// Times the client lagged, on any channel
+    lag_events: VecDeque<Instant>,
+}
+
+impl Relay<'_> {
+    /// Sends the message built by `to_msg` if the client receives the
+    /// channel, or applies the lag policy if the client lagged.
+    async fn forward<T: Clone>(
+        &mut self,
+        channel: client::Channel,
+        received: Result<Sequenced<T>, RecvError>,
+        to_msg: impl FnOnce(Sequenced<T>) -> ChannelMsg,
+    ) -> Flow {
+        match received {
+            Err(RecvError::Closed) => Flow::Closed,
+            Err(RecvError::Lagged(dropped)) => self.lagged(channel, dropped).await,
+            Ok(message) => {
+                if self.receives(&channel).await {
+                    let mut sender = self.sender.lock().await;
+                    send(&mut sender, to_msg(message), self.state).await;
+                }
+                Flow::Continue
+            }
+        }
+    }
+
+    /// Lag policy, the same on every channel: a client receiving the channel
+    /// gets a `lagged` notice with the number of skipped messages, and is
+    /// disconnected when it lagged more than `WsConfig::max_lag_events`
+    /// times within `WsConfig::lag_window`.
+    async fn lagged(&mut self, channel: client::Channel, dropped: u64) -> Flow {
+        if !self.receives(&channel).await {
+            return Flow::Continue;
+        }
+        self.state.metrics.ws_lag_events.inc();
+        warn!(
+            "Client lagged behind, {} {} messages dropped",
+            dropped,
+            channel.name()
+        );
+
+        let mut sender = self.sender.lock().await;
+        let notice = LagNotice {
+            channel: channel.name().to_string(),
+            dropped,
+        };
+        send(&mut sender, ChannelMsg::Lagged { data: notice }, self.state).await;
+
+        if is_chronically_slow(&mut self.lag_events, &self.state.ws_config) {
+            info!("Disconnecting slow consumer");
+            self.state.metrics.ws_slow_consumer_disconnects.inc();
+            close(&mut sender, close_code::POLICY, "slow consumer").await;
+            return Flow::Disconnected;
+        }
+        Flow::Continue
+    }
+
+    /// Whether the client receives the channel, the heartbeats being sent
+    /// to every client.
+    async fn receives(&self, channel: &client::Channel) -> bool {
+        *channel == client::Channel::Heartbeat || self.client.lock().await.is_subscribed(channel)
+    }
+}
+
+/// Sends the pending batch of transactions, if any.
+async fn flush(
+    sender: &Mutex<Outbound>,
+    batch: &mut Vec<Sequenced<TransactionData>>,
+    state: &AppState,
+) {
+    let Some(last) = batch.last() else {
+        return;
+    };
+    let msg = ChannelMsg::Transactions {
+        seq: last.seq,
+        data: batch
+            .drain(..)
+            .map(|transaction| transaction.data)
+            .collect(),
+    };
+    let mut sender = sender.lock().await;
+    send(&mut sender, msg, state).await;
+}
+
+/// Waits for the next tick of the timer, or forever if there is none.
+async fn tick(timer: &mut Option<(Duration, Interval)>) {
+    tick_opt(timer.as_mut().map(|(_, timer)| timer)).await
+}
+
+/// Waits for the next tick of the interval, or forever if there is none.
+async fn tick_opt(timer: Option<&mut Interval>) {
+    match timer {
+        Some(timer) => {
+            timer.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Receives the next message of the queue, or waits forever if there is none.
+async fn recv_opt<T>(rx: Option<&mut mpsc::Receiver<T>>) -> Option<T> {
+    match rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits until the deadline, or forever if there is none.
+async fn sleep_until_opt(deadline: Option<time::Instant>) {
+    match deadline {
+        Some(deadline) => time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Records a lag event and checks if the client lagged too often within
+/// the configured window.
+fn is_chronically_slow(lag_events: &mut VecDeque<Instant>, config: &WsConfig) -> bool {
+    if config.max_lag_events == 0 {
+        return false;
+    }
+
+    let now = Instant::now();
+    lag_events.push_back(now);
+    while lag_events
+        .front()
+        .is_some_and(|at| now.duration_since(*at) > config.lag_window)
+    {
+        lag_events.pop_front();
+    }
+
+    lag_events.len() > config.max_lag_events
+}
+
+/// Handles a request read from the websocket.
+///
+/// The request is answered with an `error` when it is invalid or cannot be
+/// fulfilled, carrying the request id if any.
+async fn handle_request(
+    request: Result<Request, ErrorNotice>,
+    client: &Mutex<client::WsClient>,
+    sender: &Mutex<Outbound>,
+    state: &AppState,
+) {
+    let notice = match request {
+        Err(notice) => notice,
+        Ok(request) => {
+            let logged = match &request.message {
+                WsMessage::Subscribe { params } if params.channel == "transactions" => {
+                    match params.resume_from {
+                        Some(from) => read_log(from, state).await,
+                        None => Vec::new(),
+                    }
+                }
+                _ => Vec::new(),
+            };
+            let mut client = client.lock().await;
+            let handled = handle_incoming(
+                &request.message,
+                request.id.as_ref(),
+                logged,
+                &mut client,
+                sender,
+                state,
+            )
+            .await;
+            let Err((code, message)) = handled else {
+                return;
+            };
+            ErrorNotice {
+                id: request.id,
+                method: Some(request.message.method().to_string()),
+                code,
+                message,
+            }
+        }
+    };
+    debug!("Invalid request: {}", notice.message);
+    let mut sender = sender.lock().await;
+    send(&mut sender, ChannelMsg::Error { data: notice }, state).await;
 }
 
 /// Handles the incoming messages from the websocket.
 ///
 /// This function handles the incoming messages from the websocket and
-/// returns the appropriate response.
-async fn handle_incoming(msg: &WsMessage, client: &mut client::WsClient) {
+/// returns the appropriate response: subscriptions are acknowledged with an
+/// `ack`, decisions and authorizations only when they carry a request id.
+/// The `logged` transactions are the ones read from the event log when
+/// resuming, see `read_log`.
+async fn handle_incoming(
+    msg: &WsMessage,
+    id: Option<&RequestId>,
+    logged: Vec<Sequenced<Transaction>>,
+    client: &mut client::WsClient,
+    sender: &Mutex<Outbound>,
+    state: &AppState,
+) -> Result<(), (ErrorCode, String)> {
     // handle the incoming message
     match msg {
         // subscribe to a channel
-        WsMessage::Subscribe { params } => match params.channel.parse() {
-            Err(e) => error!("Invalid channel: {}", e),
-            Ok(channel) => {
-                client.subscribe(channel);
-                debug!("Successfully subscribed to {} channel", params.channel)
+        WsMessage::Subscribe { params } => {
+            let channel: client::Channel = params
+                .channel
+                .parse()
+                .map_err(|e| (ErrorCode::UnknownChannel, e))?;
+            // subscribing again to these channels replaces their settings
+            let reconfigurable = matches!(
+                channel,
+                client::Channel::Transactions | client::Channel::Heartbeat
+            );
+            if client.is_subscribed(&channel) && !reconfigurable {
+                let message = format!("already subscribed to {}", params.channel);
+                return Err((ErrorCode::AlreadySubscribed, message));
             }
-        },
+
+            if channel == client::Channel::Transactions {
+                let expr = params
+                    .expr
+                    .as_deref()
+                    .map(str::parse)
+                    .transpose()
+                    .map_err(|e| {
+                        let message = format!("invalid filter expression: {}", e);
+                        (ErrorCode::InvalidParams, message)
+                    })?;
+                if let Some(fields) = &params.fields {
+                    check_fields(fields).map_err(|message| (ErrorCode::InvalidParams, message))?;
+                }
+                client.batching = params.batch.as_ref().map(client::Batching::from);
+                client.filter = params.filter.clone();
+                client.expr = expr;
+                client.fields = params.fields.clone();
+            }
+            ack(sender, id, msg, Some(&params.channel), state).await;
+
+            if let (client::Channel::Transactions, Some(from)) = (&channel, params.resume_from) {
+                let mut sender = sender.lock().await;
+                resume(from, logged, client, &mut sender, state).await;
+            }
+            // subscribing without an interval restores the server one
+            if channel == client::Channel::Heartbeat {
+                client.heartbeat_interval = params.interval_secs.map(|secs| {
+                    let secs = secs.clamp(MIN_HEARTBEAT_INTERVAL_SECS, MAX_HEARTBEAT_INTERVAL_SECS);
+                    Duration::from_secs(secs)
+                });
+            }
+            client.subscribe(channel);
+            client.updated.notify_one();
+            debug!("Successfully subscribed to {} channel", params.channel)
+        }
 
         // unsubscribe from a channel
-        WsMessage::Unsubscribe { params } => match params.channel.parse() {
-            Err(e) => error!("Invalid channel: {}", e),
-            Ok(channel) => {
-                client.unsubscribe(channel);
-                debug!("Successfully unsubscribed from {} channel", params.channel)
+        WsMessage::Unsubscribe { params } => {
+            let channel: client::Channel = params
+                .channel
+                .parse()
+                .map_err(|e| (ErrorCode::UnknownChannel, e))?;
+            if !client.is_subscribed(&channel) {
+                let message = format!("not subscribed to {}", params.channel);
+                return Err((ErrorCode::NotSubscribed, message));
             }
-        },
+
+            match channel {
+                client::Channel::Heartbeat => client.heartbeat_interval = None,
+                client::Channel::Transactions => {
+                    client.batching = None;
+                    client.filter = None;
+                    client.expr = None;
+                    client.fields = None;
+                }
+                client::Channel::EnrichedTransactions
+                | client::Channel::ScoredTransactions
+                | client::Channel::Alerts
+                | client::Channel::Stats => {}
+            }
+            client.unsubscribe(channel);
+            client.updated.notify_one();
+            ack(sender, id, msg, Some(&params.channel), state).await;
+            debug!("Successfully unsubscribed from {} channel", params.channel)
+        }
+
+        // evaluate a decision on a transaction
+        WsMessage::Decision { params } => {
+            if let Some(score) = params.score.filter(|score| !(0.0..=1.0).contains(score)) {
+                let message = format!("score must be between 0 and 1, got {}", score);
+                return Err((ErrorCode::InvalidParams, message));
+            }
+            let fraud = params.decision == models::Decision::Fraud;
+            state
+                .decisions
+                .decide(&client.id, &params.transaction_id, fraud)
+                .map_err(|e| {
+                    let code = match e {
+                        DecisionError::UnknownTransaction(_) => ErrorCode::UnknownTransaction,
+                        DecisionError::AlreadyDecided(_) => ErrorCode::AlreadyDecided,
+                    };
+                    (code, e.to_string())
+                })?;
+            if id.is_some() {
+                ack(sender, id, msg, None, state).await;
+            }
+        }
+
+        // reply to an authorization request
+        WsMessage::Authorize { params } => {
+            let Some(authorizer) = &state.authorizer else {
+                let message = "the authorization simulation is disabled".to_string();
+                return Err((ErrorCode::Unavailable, message));
+            };
+            if authorizer.config().client_id != client.id {
+                let message = format!("{} is not the authorizing client", client.id);
+                return Err((ErrorCode::Forbidden, message));
+            }
+            authorizer
+                .reply(&params.transaction_id, params.decision)
+                .map_err(|message| (ErrorCode::UnknownTransaction, message))?;
+            if id.is_some() {
+                ack(sender, id, msg, None, state).await;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Acknowledges a request.
+async fn ack(
+    sender: &Mutex<Outbound>,
+    id: Option<&RequestId>,
+    msg: &WsMessage,
+    channel: Option<&str>,
+    state: &AppState,
+) {
+    let notice = AckNotice {
+        id: id.cloned(),
+        method: msg.method().to_string(),
+        channel: channel.map(str::to_string),
+    };
+    let mut sender = sender.lock().await;
+    send(&mut sender, ChannelMsg::Ack { data: notice }, state).await;
+}
+
+/// Checks that the projected fields are fields of the transactions.
+pub(crate) fn check_fields(fields: &[String]) -> Result<(), String> {
+    if fields.is_empty() {
+        return Err("fields must not be empty".to_string());
+    }
+    match fields
+        .iter()
+        .find(|field| !Transaction::FIELDS.contains(&field.as_str()))
+    {
+        Some(field) => Err(format!(
+            "unknown field `{}`, expected one of {}",
+            field,
+            Transaction::FIELDS.join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Bounds of the heartbeat interval requested by the clients.
+const MIN_HEARTBEAT_INTERVAL_SECS: u64 = 1;
+const MAX_HEARTBEAT_INTERVAL_SECS: u64 = 3600;
+
+/// Maximum number of transactions replayed from the event log on resume.
+const MAX_EVENT_LOG_REPLAY: u64 = 100_000;
+
+/// Reads the transactions from the `from` sequence number that were evicted
+/// from the retention buffer back from the event log, when it is enabled.
+///
+/// The log is read before taking any client lock, as the query can scan up
+/// to `MAX_EVENT_LOG_REPLAY` records.
+pub(crate) async fn read_log(from: u64, state: &AppState) -> Vec<Sequenced<Transaction>> {
+    let oldest = state.transactions_tx.oldest_seq();
+    let Some(event_log) = state.event_log.clone().filter(|_| from < oldest) else {
+        return Vec::new();
+    };
+
+    let query = LogQuery {
+        from_seq: Some(from.max(oldest.saturating_sub(MAX_EVENT_LOG_REPLAY))),
+        to_seq: Some(oldest - 1),
+        ..Default::default()
+    };
+    let logged = tokio::task::spawn_blocking(move || event_log.query(&query, usize::MAX)).await;
+    match logged {
+        Ok(Ok(entries)) => entries
+            .into_iter()
+            .filter_map(|entry| match entry {
+                LogEntry::Record(record) => Some(Sequenced {
+                    seq: record.seq,
+                    data: record.data,
+                }),
+                LogEntry::Gap(_) => None,
+            })
+            .collect(),
+        Ok(Err(e)) => {
+            error!("Failed to read the event log: {}", e);
+            Vec::new()
+        }
+        Err(e) => {
+            error!("Event log query panicked: {}", e);
+            Vec::new()
+        }
+    }
+}
+
+/// Returns the transactions retained from the `from` sequence number,
+/// preceded by the `logged` ones read by `read_log`.
+///
+/// Only the logged transactions leading without holes up to the retention
+/// buffer are replayed, `gap` is set if some of the requested transactions
+/// are no longer available.
+pub(crate) fn retained(
+    from: u64,
+    mut logged: Vec<Sequenced<Transaction>>,
+    state: &AppState,
+) -> Resume<Transaction> {
+    let mut resume = state.transactions_tx.resume(from);
+
+    let Some(oldest) = resume.gap else {
+        return resume;
+    };
+    // the buffer may have moved on since the log was read
+    if logged.last().is_none_or(|last| last.seq + 1 != oldest) {
+        return resume;
+    }
+    let contiguous = logged
+        .windows(2)
+        .rposition(|pair| pair[1].seq != pair[0].seq + 1)
+        .map_or(0, |idx| idx + 1);
+    logged.drain(..contiguous);
+
+    resume.gap = Some(logged[0].seq).filter(|first| *first > from);
+    resume.replay = logged.into_iter().chain(resume.replay).collect();
+    resume
+}
+
+/// Replays the retained transactions from the `from` sequence number.
+///
+/// A gap notice is sent first if some of the requested transactions are no
+/// longer available, see `retained`, or if `from` is beyond the next
+/// sequence number, the live transactions being sent from then on. The
+/// replayed sequence numbers are recorded on the client so that the write
+/// side skips them when they are also received live.
+async fn resume(
+    from: u64,
+    logged: Vec<Sequenced<Transaction>>,
+    client: &mut client::WsClient,
+    sender: &mut Outbound,
+    state: &AppState,
+) {
+    let resume = retained(from, logged, state);
+
+    if let Some(available_from) = resume.gap.or(resume.ahead) {
+        let notice = GapNotice {
+            channel: "transactions".to_string(),
+            requested: from,
+            available_from,
+        };
+        send(sender, ChannelMsg::Gap { data: notice }, state).await;
+    }
+    // the live transactions are not skipped when resuming beyond them
+    if resume.ahead.is_some() {
+        return;
+    }
+
+    debug!(
+        "Replaying {} transactions from {}",
+        resume.replay.len(),
+        from
+    );
+    // filtered out transactions are replayed too, as far as the write side is concerned
+    client.replayed_until = Some(
+        resume
+            .replay
+            .last()
+            .map_or(from.saturating_sub(1), |transaction| transaction.seq),
+    );
+    let batch_size = client.batching.map_or(1, |batching| batching.max_size);
+    let mut replay = resume
+        .replay
+        .into_iter()
+        .filter(|transaction| client.matches(&transaction.data))
+        .peekable();
+    while replay.peek().is_some() {
+        let batch: Vec<_> = replay.by_ref().take(batch_size).collect();
+        let seq = batch.last().map(|transaction| transaction.seq);
+        let msg = ChannelMsg::Transactions {
+            seq: seq.unwrap_or_default(),
+            data: batch
+                .into_iter()
+                .map(|transaction| client.project(transaction.data))
+                .collect(),
+        };
+        send(sender, msg, state).await;
     }
 }
 
 /// Sends a message by serializing the message and sending it to the websocket.
-async fn send(tx: &mut SplitSink<WebSocket, Message>, msg: ChannelMsg) {
-    if let Ok(serialized) = serde_json::to_string(&msg) {
-        match tx.send(Message::Text(serialized.into())).await {
-            Ok(_) => debug!("sent message: {:?}", msg),
+///
+/// The JSON protocols send text messages, the other encodings binary ones.
+async fn send(tx: &mut Outbound, msg: ChannelMsg, state: &AppState) {
+    debug!("sending message: {:?}", msg);
+    let serialized = match (tx.protocol, tx.version) {
+        (Protocol::JsonRpc, _) => match jsonrpc::encode(msg, &mut tx.subscriptions) {
+            Some(msg) => serialize(Protocol::Json, &msg),
+            None => return,
+        },
+        (Protocol::Protobuf, _) => Ok(Message::Binary(protobuf::encode(msg).into())),
+        (protocol, ApiVersion::V1) => serialize(protocol, &msg),
+        (protocol, ApiVersion::V2) => serialize(protocol, &v2::Event::from(msg)),
+    };
+    match serialized {
+        Ok(message) => match tx.sink.send(message).await {
+            Ok(_) => state.metrics.ws_messages_sent.inc(),
             Err(e) => error!("error sending message: {:?}", e),
+        },
+        Err(e) => error!("error serializing message: {}", e),
+    }
+}
+
+/// Serializes a message in the encoding of the serde based protocols.
+fn serialize<T: serde::Serialize>(protocol: Protocol, msg: &T) -> Result<Message, String> {
+    match protocol {
+        Protocol::MessagePack => rmp_serde::to_vec_named(msg)
+            .map(|bytes| Message::Binary(bytes.into()))
+            .map_err(|e| e.to_string()),
+        Protocol::Cbor => {
+            let mut bytes = Vec::new();
+            ciborium::into_writer(msg, &mut bytes)
+                .map(|()| Message::Binary(bytes.into()))
+                .map_err(|e| e.to_string())
         }
+        _ => serde_json::to_string(msg)
+            .map(|text| Message::Text(text.into()))
+            .map_err(|e| e.to_string()),
+    }
+}
+
+/// Sends a close frame with the given code and reason.
+async fn close(tx: &mut Outbound, code: u16, reason: &str) {
+    let frame = CloseFrame {
+        code,
+        reason: reason.into(),
+    };
+    if let Err(e) = tx.sink.send(Message::Close(Some(frame))).await {
+        debug!("error sending close frame: {:?}", e);
     }
 }
 
@@ -163,36 +1154,312 @@ async fn send(tx: &mut SplitSink<WebSocket, Message>, msg: ChannelMsg) {
 ///
 /// This module includes the message types for the websocket API such as
 /// subscribe, unsubscribe, and heartbeat messages.
-mod models {
-    use crate::domain::prelude::*;
+pub mod models {
+    use crate::{domain::prelude::*, filter::TransactionFilter};
     use serde::{Deserialize, Serialize};
 
     #[derive(Deserialize, Serialize, Debug)]
     #[serde(tag = "method")]
     pub enum WsMessage {
         #[serde(rename = "subscribe")]
-        Subscribe { params: SubscribeParams },
+        Subscribe { params: Box<SubscribeParams> },
         #[serde(rename = "unsubscribe")]
         Unsubscribe { params: UnsubscribeParams },
+        #[serde(rename = "decision")]
+        Decision { params: DecisionParams },
+        #[serde(rename = "authorize")]
+        Authorize { params: AuthorizeParams },
+    }
+
+    impl WsMessage {
+        /// Names of the methods.
+        pub const METHODS: [&'static str; 4] =
+            ["subscribe", "unsubscribe", "decision", "authorize"];
+
+        pub fn method(&self) -> &'static str {
+            match self {
+                Self::Subscribe { .. } => "subscribe",
+                Self::Unsubscribe { .. } => "unsubscribe",
+                Self::Decision { .. } => "decision",
+                Self::Authorize { .. } => "authorize",
+            }
+        }
+    }
+
+    /// Id of a request, echoed in its `ack` or `error` response.
+    #[derive(Deserialize, Serialize, Debug, Clone, PartialEq, Eq)]
+    #[serde(untagged)]
+    pub enum RequestId {
+        Number(i64),
+        String(String),
+    }
+
+    /// A message from the client, with its optional request id.
+    #[derive(Debug)]
+    pub struct Request {
+        pub id: Option<RequestId>,
+        pub message: WsMessage,
+    }
+
+    impl Request {
+        /// Parses a text message, returning the error to answer with when
+        /// it is not a valid request.
+        pub fn parse(text: &str) -> Result<Self, ErrorNotice> {
+            let value = serde_json::from_str(text).map_err(|e| ErrorNotice {
+                id: None,
+                method: None,
+                code: ErrorCode::ParseError,
+                message: format!("invalid JSON: {}", e),
+            })?;
+            Self::from_value(value)
+        }
+
+        /// Reads a request from a parsed message.
+        pub fn from_value(value: serde_json::Value) -> Result<Self, ErrorNotice> {
+            let error =
+                |id: Option<RequestId>, method: Option<&str>, code, message: String| ErrorNotice {
+                    id,
+                    method: method.map(str::to_string),
+                    code,
+                    message,
+                };
+
+            let id = match value.get("id") {
+                None | Some(serde_json::Value::Null) => None,
+                Some(id) => Some(RequestId::deserialize(id).map_err(|_| {
+                    let message = "id must be a string or an integer".to_string();
+                    error(None, None, ErrorCode::InvalidRequest, message)
+                })?),
+            };
+            let Some(method) = value.get("method").and_then(|method| method.as_str()) else {
+                let message = "missing method".to_string();
+                return Err(error(id, None, ErrorCode::InvalidRequest, message));
+            };
+            if !WsMessage::METHODS.contains(&method) {
+                let message = format!(
+                    "unknown method `{}`, expected one of {}",
+                    method,
+                    WsMessage::METHODS.join(", ")
+                );
+                return Err(error(id, Some(method), ErrorCode::UnknownMethod, message));
+            }
+
+            let method = method.to_string();
+            match WsMessage::deserialize(value) {
+                Ok(message) => Ok(Self { id, message }),
+                Err(e) => Err(error(
+                    id,
+                    Some(&method),
+                    ErrorCode::InvalidParams,
+                    e.to_string(),
+                )),
+            }
+        }
     }
 
     #[derive(Deserialize, Serialize, Debug)]
     pub struct SubscribeParams {
         pub channel: String,
+
+        /// Sequence number to resume the transactions channel from,
+        /// the retained transactions are replayed before the live ones.
+        #[serde(default)]
+        pub resume_from: Option<u64>,
+
+        /// Heartbeat interval of this client, in seconds, overriding the
+        /// server default when subscribing to the heartbeat channel.
+        #[serde(default)]
+        pub interval_secs: Option<u64>,
+
+        /// Batching of the transactions channel.
+        #[serde(default)]
+        pub batch: Option<BatchParams>,
+
+        /// Filter on the transactions channel.
+        #[serde(default)]
+        pub filter: Option<TransactionFilter>,
+
+        /// Filter expression on the transactions channel, see `filter::expr::Expr`.
+        #[serde(default)]
+        pub expr: Option<String>,
+
+        /// Fields of the transactions to send, all of them by default.
+        #[serde(default)]
+        pub fields: Option<Vec<String>>,
+    }
+
+    /// Batching requested when subscribing to the transactions channel.
+    ///
+    /// A batch is sent when it holds `max_size` transactions, or when its
+    /// first transaction has waited `max_linger_ms`.
+    #[derive(Deserialize, Serialize, Debug)]
+    pub struct BatchParams {
+        #[serde(default)]
+        pub max_size: Option<usize>,
+
+        #[serde(default)]
+        pub max_linger_ms: Option<u64>,
     }
     #[derive(Deserialize, Serialize, Debug)]
     pub struct UnsubscribeParams {
         pub channel: String,
     }
 
+    /// Decision of the client on a broadcast transaction, evaluated against
+    /// its label.
+    #[derive(Deserialize, Serialize, Debug)]
+    pub struct DecisionParams {
+        pub transaction_id: String,
+
+        /// Fraud score of the client's model, between 0 and 1
+        #[serde(default)]
+        pub score: Option<f64>,
+
+        pub decision: Decision,
+    }
+
+    /// Reply of the authorizing client to an authorization request.
+    #[derive(Deserialize, Serialize, Debug)]
+    pub struct AuthorizeParams {
+        pub transaction_id: String,
+        pub decision: AuthorizationDecision,
+    }
+
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Decision {
+        Fraud,
+        Legit,
+    }
+
     #[derive(Deserialize, Serialize, Debug)]
     #[serde(tag = "channel")]
     pub enum ChannelMsg {
+        /// `seq` is the sequence number of the last transaction in `data`.
         #[serde(rename = "transactions")]
-        Transactions { data: Vec<Transaction> },
+        Transactions {
+            seq: u64,
+            data: Vec<TransactionData>,
+        },
 
+        /// `seq` is omitted for the heartbeats sent at a client interval.
         #[serde(rename = "heartbeat")]
-        Heartbeat { data: Heartbeat },
+        Heartbeat {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            seq: Option<u64>,
+            data: Heartbeat,
+        },
+
+        #[serde(rename = "enriched_transactions")]
+        EnrichedTransactions {
+            seq: u64,
+            data: Vec<EnrichedTransaction>,
+        },
+
+        #[serde(rename = "scored_transactions")]
+        ScoredTransactions {
+            seq: u64,
+            data: Vec<ScoredTransaction>,
+        },
+
+        #[serde(rename = "alerts")]
+        Alerts { seq: u64, data: Alert },
+
+        #[serde(rename = "stats")]
+        Stats { seq: u64, data: Stats },
+
+        /// Sent to the authorizing client only, see `core::authorization`.
+        #[serde(rename = "authorization")]
+        Authorization { data: AuthorizationRequest },
+
+        #[serde(rename = "gap")]
+        Gap { data: GapNotice },
+
+        #[serde(rename = "lagged")]
+        Lagged { data: LagNotice },
+
+        #[serde(rename = "ack")]
+        Ack { data: AckNotice },
+
+        #[serde(rename = "error")]
+        Error { data: ErrorNotice },
+    }
+
+    /// A transaction sent to the client, whole or limited to the fields
+    /// requested on subscribe.
+    #[derive(Deserialize, Serialize, Debug)]
+    #[serde(untagged)]
+    pub enum TransactionData {
+        Full(Transaction),
+        Projected(serde_json::Map<String, serde_json::Value>),
+    }
+
+    /// Reply to a request that was fulfilled.
+    #[derive(Deserialize, Serialize, Debug)]
+    pub struct AckNotice {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub id: Option<RequestId>,
+
+        pub method: String,
+
+        /// Channel of the subscribe and unsubscribe requests
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub channel: Option<String>,
+    }
+
+    /// Reply to a request that could not be fulfilled.
+    #[derive(Deserialize, Serialize, Debug)]
+    pub struct ErrorNotice {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub id: Option<RequestId>,
+
+        /// Method of the request, unless it could not be read
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub method: Option<String>,
+
+        pub code: ErrorCode,
+        pub message: String,
+    }
+
+    /// Machine-readable reason of an error.
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum ErrorCode {
+        /// The message is not valid JSON
+        ParseError,
+        /// The message is not a request object, or its id is invalid
+        InvalidRequest,
+        UnknownMethod,
+        UnknownChannel,
+        /// The params of the request are missing or invalid
+        InvalidParams,
+        AlreadySubscribed,
+        NotSubscribed,
+        /// The transaction is unknown, or no longer awaits a reply
+        UnknownTransaction,
+        AlreadyDecided,
+        LimitExceeded,
+        /// The client is not allowed to make the request
+        Forbidden,
+        /// The feature is disabled on the server
+        Unavailable,
+    }
+
+    /// Notice sent when the client fell behind a channel and messages
+    /// were skipped.
+    #[derive(Deserialize, Serialize, Debug)]
+    pub struct LagNotice {
+        pub channel: String,
+        pub dropped: u64,
+    }
+
+    /// Notice sent when resuming from a sequence number that is no longer
+    /// retained, the replay starts at `available_from`.
+    #[derive(Deserialize, Serialize, Debug)]
+    pub struct GapNotice {
+        pub channel: String,
+        pub requested: u64,
+        pub available_from: u64,
     }
 }
 
@@ -202,7 +1469,38 @@ mod models {
 /// which handles the subscription and unsubscription to channels.
 ///
 pub mod client {
-    use std::collections::HashSet;
+    use std::{collections::HashSet, sync::Arc, time::Duration};
+    use tokio::sync::Notify;
+
+    use super::models::{BatchParams, TransactionData};
+    use crate::{
+        domain::prelude::*,
+        filter::{expr::Expr, TransactionFilter},
+    };
+
+    /// Bounds and defaults of the transactions batching.
+    const MAX_BATCH_SIZE: usize = 1000;
+    const DEFAULT_BATCH_SIZE: usize = 100;
+    const MAX_LINGER_MS: u64 = 10_000;
+    const DEFAULT_LINGER_MS: u64 = 100;
+
+    /// Batching of the transactions sent to the client.
+    #[derive(Debug, Clone, Copy)]
+    pub struct Batching {
+        pub max_size: usize,
+        pub max_linger: Duration,
+    }
+
+    impl From<&BatchParams> for Batching {
+        fn from(params: &BatchParams) -> Self {
+            let max_size = params.max_size.unwrap_or(DEFAULT_BATCH_SIZE);
+            let max_linger_ms = params.max_linger_ms.unwrap_or(DEFAULT_LINGER_MS);
+            Self {
+                max_size: max_size.clamp(1, MAX_BATCH_SIZE),
+                max_linger: Duration::from_millis(max_linger_ms.clamp(1, MAX_LINGER_MS)),
+            }
+        }
+    }
 
     /// Channel enum for the websocket client.
     ///
@@ -213,6 +1511,24 @@ pub mod client {
     pub enum Channel {
         Heartbeat,
         Transactions,
+        EnrichedTransactions,
+        ScoredTransactions,
+        Alerts,
+        Stats,
+    }
+
+    impl Channel {
+        /// Name of the channel in the messages.
+        pub fn name(&self) -> &'static str {
+            match self {
+                Self::Heartbeat => "heartbeat",
+                Self::Transactions => "transactions",
+                Self::EnrichedTransactions => "enriched_transactions",
+                Self::ScoredTransactions => "scored_transactions",
+                Self::Alerts => "alerts",
+                Self::Stats => "stats",
+            }
+        }
     }
 
     impl std::str::FromStr for Channel {
@@ -222,7 +1538,11 @@ pub mod client {
             match s {
                 "heartbeat" => Ok(Self::Heartbeat),
                 "transactions" => Ok(Self::Transactions),
-                _ => Err(format!("Invalid channel: {}", s)),
+                "enriched_transactions" => Ok(Self::EnrichedTransactions),
+                "scored_transactions" => Ok(Self::ScoredTransactions),
+                "alerts" => Ok(Self::Alerts),
+                "stats" => Ok(Self::Stats),
+                _ => Err(format!("unknown channel: {}", s)),
             }
         }
     }
@@ -233,7 +1553,31 @@ pub mod client {
     /// for a given websocket connection.
     #[derive(Debug, Default)]
     pub struct WsClient {
+        /// Id of the client, from the `client_id` query parameter.
+        pub id: String,
+
         pub channels: HashSet<Channel>,
+
+        /// Sequence number up to which transactions were replayed on resume.
+        pub replayed_until: Option<u64>,
+
+        /// Heartbeat interval requested by the client, if any.
+        pub heartbeat_interval: Option<Duration>,
+
+        /// Batching of the transactions requested by the client, if any.
+        pub batching: Option<Batching>,
+
+        /// Filter on the transactions requested by the client, if any.
+        pub filter: Option<TransactionFilter>,
+
+        /// Filter expression on the transactions requested by the client, if any.
+        pub expr: Option<Expr>,
+
+        /// Fields of the transactions requested by the client, if any.
+        pub fields: Option<Vec<String>>,
+
+        /// Notified when the client settings change, to wake up the write side.
+        pub updated: Arc<Notify>,
     }
 
     impl WsClient {
@@ -253,5 +1597,248 @@ pub mod client {
         pub fn is_subscribed(&self, channel: &Channel) -> bool {
             self.channels.contains(channel)
         }
+
+        /// Checks if a transaction passes the filter and the filter expression.
+        pub fn matches(&self, transaction: &Transaction) -> bool {
+            self.filter
+                .as_ref()
+                .is_none_or(|filter| filter.matches(transaction))
+                && self
+                    .expr
+                    .as_ref()
+                    .is_none_or(|expr| expr.matches(transaction))
+        }
+
+        /// Limits the transaction to the requested fields, if any.
+        pub fn project(&self, transaction: Transaction) -> TransactionData {
+            let Some(fields) = &self.fields else {
+                return TransactionData::Full(transaction);
+            };
+            match serde_json::to_value(&transaction) {
+                Ok(serde_json::Value::Object(mut map)) => {
+                    map.retain(|name, _| fields.contains(name));
+                    TransactionData::Projected(map)
+                }
+                _ => TransactionData::Full(transaction),
+            }
+        }
+
+        /// Checks if a transaction was already sent by a resume replay.
+        pub fn was_replayed(&self, seq: u64) -> bool {
+            self.replayed_until.is_some_and(|until| seq <= until)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Router};
+    use serde_json::{json, Value};
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::{tungstenite, MaybeTlsStream, WebSocketStream};
+
+    type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+    fn state(buffer_size: usize, ws_config: WsConfig) -> AppState {
+        AppState::for_tests(buffer_size, 0, ws_config)
+    }
+
+    /// Serves the websocket API and connects a client to it.
+    async fn connect(state: &AppState) -> Client {
+        let app = Router::new()
+            .route("/ws/v1", get(endpoint))
+            .with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/ws/v1", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        tokio_tungstenite::connect_async(url).await.unwrap().0
+    }
+
+    async fn request(client: &mut Client, request: Value) {
+        let text = request.to_string();
+        client.send(tungstenite::Message::text(text)).await.unwrap();
+    }
+
+    /// Receives the next message, skipping the pings.
+    async fn next(client: &mut Client) -> tungstenite::Message {
+        loop {
+            match client.next().await.unwrap().unwrap() {
+                tungstenite::Message::Ping(_) | tungstenite::Message::Pong(_) => {}
+                msg => return msg,
+            }
+        }
+    }
+
+    async fn next_json(client: &mut Client) -> Value {
+        match next(client).await {
+            tungstenite::Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+    }
+
+    /// Subscribes to a channel, once the connection is fully set up.
+    async fn subscribe(client: &mut Client, channel: &str) {
+        let params = json!({ "method": "subscribe", "params": { "channel": channel } });
+        request(client, params).await;
+        assert_eq!(next_json(client).await["channel"], "ack");
+    }
+
+    #[tokio::test]
+    async fn lagging_heartbeats_get_a_notice() {
+        let state = state(2, WsConfig::default());
+        let mut client = connect(&state).await;
+        subscribe(&mut client, "alerts").await;
+
+        for _ in 0..5 {
+            state.heartbeat_tx.send(state.status.heartbeat());
+        }
+        let notice = next_json(&mut client).await;
+        assert_eq!(notice["channel"], "lagged");
+        assert_eq!(
+            notice["data"],
+            json!({ "channel": "heartbeat", "dropped": 3 })
+        );
+        assert_eq!(next_json(&mut client).await["seq"], 4);
+        assert_eq!(next_json(&mut client).await["seq"], 5);
+        assert_eq!(state.metrics.ws_lag_events.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn lagging_on_any_channel_disconnects_slow_consumers() {
+        let ws_config = WsConfig {
+            max_lag_events: 1,
+            ..WsConfig::default()
+        };
+        let state = state(2, ws_config);
+        let mut client = connect(&state).await;
+        subscribe(&mut client, "alerts").await;
+
+        for _ in 0..5 {
+            state.heartbeat_tx.send(state.status.heartbeat());
+        }
+        for _ in 0..3 {
+            next_json(&mut client).await;
+        }
+        for _ in 0..5 {
+            state.heartbeat_tx.send(state.status.heartbeat());
+        }
+        assert_eq!(next_json(&mut client).await["channel"], "lagged");
+        match next(&mut client).await {
+            tungstenite::Message::Close(Some(frame)) => {
+                assert_eq!(u16::from(frame.code), close_code::POLICY);
+                assert_eq!(frame.reason.as_str(), "slow consumer");
+            }
+            msg => panic!("unexpected message: {:?}", msg),
+        }
+        assert_eq!(state.metrics.ws_slow_consumer_disconnects.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn unsubscribed_channels_do_not_lag() {
+        let state = state(2, WsConfig::default());
+        let mut client = connect(&state).await;
+        subscribe(&mut client, "alerts").await;
+
+        for _ in 0..5 {
+            state.transactions_tx.send(Transaction::simple_mock());
+        }
+        // let the writer go through the lagged transactions
+        time::sleep(Duration::from_millis(50)).await;
+        state.heartbeat_tx.send(state.status.heartbeat());
+
+        assert_eq!(next_json(&mut client).await["channel"], "heartbeat");
+        assert_eq!(state.metrics.ws_lag_events.get(), 0);
+        assert_eq!(state.metrics.ws_dropped_transactions.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn subscribing_without_an_interval_restores_the_server_heartbeats() {
+        let state = state(10, WsConfig::default());
+        let mut client = connect(&state).await;
+        let params = json!({ "channel": "heartbeat", "interval_secs": 3600 });
+        request(
+            &mut client,
+            json!({ "method": "subscribe", "params": params }),
+        )
+        .await;
+        assert_eq!(next_json(&mut client).await["channel"], "ack");
+        time::sleep(Duration::from_millis(50)).await;
+
+        // replaced by the client heartbeats
+        state.heartbeat_tx.send(state.status.heartbeat());
+        subscribe(&mut client, "heartbeat").await;
+        time::sleep(Duration::from_millis(50)).await;
+
+        state.heartbeat_tx.send(state.status.heartbeat());
+        let heartbeat = next_json(&mut client).await;
+        assert_eq!(heartbeat["channel"], "heartbeat");
+        assert_eq!(heartbeat["seq"], 2);
+    }
+
+    /// Reads until the close frame, returning its code and reason.
+    async fn close_frame(client: &mut Client) -> (u16, String) {
+        loop {
+            match client.next().await {
+                Some(Ok(tungstenite::Message::Close(Some(frame)))) => {
+                    return (frame.code.into(), frame.reason.to_string())
+                }
+                Some(Ok(_)) => {}
+                msg => panic!("unexpected message: {:?}", msg),
+            }
+        }
+    }
+
+    fn keepalive(ping_interval: Option<u64>, idle_timeout: Option<u64>) -> WsConfig {
+        WsConfig {
+            ping_interval: ping_interval.map(Duration::from_secs),
+            pong_timeout: Duration::from_secs(10),
+            idle_timeout: idle_timeout.map(Duration::from_secs),
+            ..WsConfig::default()
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn unanswered_pings_close_the_connection() {
+        let state = state(10, keepalive(Some(30), None));
+        let mut client = connect(&state).await;
+
+        // the client does not read, so the ping sent at 30s is not answered
+        time::sleep(Duration::from_secs(45)).await;
+        assert_eq!(
+            close_frame(&mut client).await,
+            (close_code::AWAY, "pong timeout".to_string())
+        );
+        assert_eq!(state.metrics.ws_keepalive_disconnects.get(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn answered_pings_keep_the_connection_open() {
+        let state = state(10, keepalive(Some(30), None));
+        let mut client = connect(&state).await;
+
+        // reading answers the pings
+        let read = time::timeout(Duration::from_secs(300), next(&mut client)).await;
+        assert!(read.is_err(), "unexpected message: {:?}", read);
+        assert_eq!(state.metrics.ws_keepalive_disconnects.get(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn idle_clients_are_disconnected() {
+        let state = state(10, keepalive(None, Some(60)));
+        let mut client = connect(&state).await;
+
+        // requests reset the idle deadline
+        time::sleep(Duration::from_secs(40)).await;
+        subscribe(&mut client, "alerts").await;
+        time::sleep(Duration::from_secs(40)).await;
+        assert_eq!(state.metrics.ws_keepalive_disconnects.get(), 0);
+
+        time::sleep(Duration::from_secs(30)).await;
+        assert_eq!(
+            close_frame(&mut client).await,
+            (close_code::AWAY, "idle timeout".to_string())
+        );
+        assert_eq!(state.metrics.ws_keepalive_disconnects.get(), 1);
     }
 }
@@ -0,0 +1,406 @@
+use serde::Serialize;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, oneshot};
+
+use super::metrics::{Latencies, Latency};
+use crate::{domain::prelude::*, stream::source::SourceRegistry};
+
+/// Transactions being authorized at once. Beyond it the transactions channel
+/// waits for the pending authorizations before taking new transactions.
+pub const MAX_IN_FLIGHT: usize = 1000;
+
+/// Settings of the authorization simulation.
+///
+/// - AUTHORIZATION_CLIENT_ID: id of the websocket client authorizing the
+///   transactions, enables the simulation
+/// - AUTHORIZATION_TOKEN: bearer token the authorizing client must connect
+///   with, required by the simulation
+/// - AUTHORIZATION_TIMEOUT_MS: time the client has to reply (default 200)
+/// - AUTHORIZATION_DEFAULT: decision applied when the client does not reply
+///   in time or is not connected, `approve` or `decline` (default approve)
+///
+#[derive(Debug, Clone)]
+pub struct AuthorizationConfig {
+    pub client_id: String,
+    pub token: String,
+    pub timeout: Duration,
+    pub default: AuthorizationDecision,
+}
+
+impl AuthorizationConfig {
+    pub fn from_env() -> Option<Self> {
+        let client_id = std::env::var("AUTHORIZATION_CLIENT_ID")
+            .ok()
+            .filter(|id| !id.is_empty())?;
+        let Some(token) = std::env::var("AUTHORIZATION_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+        else {
+            tracing::warn!(
+                "AUTHORIZATION_TOKEN is not set, the authorization simulation is disabled"
+            );
+            return None;
+        };
+        let timeout_ms = std::env::var("AUTHORIZATION_TIMEOUT_MS")
+            .ok()
+            .and_then(|s| s.parse::<u64>().ok())
+            .unwrap_or(200)
+            .max(1);
+        let default = match std::env::var("AUTHORIZATION_DEFAULT") {
+            Ok(s) => s.parse().unwrap_or_else(|e| {
+                tracing::warn!("{}, approving by default", e);
+                AuthorizationDecision::Approve
+            }),
+            Err(_) => AuthorizationDecision::Approve,
+        };
+
+        Some(Self {
+            client_id,
+            token,
+            timeout: Duration::from_millis(timeout_ms),
+            default,
+        })
+    }
+}
+
+/// Outcomes of the authorization requests.
+#[derive(Debug, Default)]
+struct Tally {
+    requests: u64,
+    approved: u64,
+    declined: u64,
+    timed_out: u64,
+    unavailable: u64,
+    fraud: Outcomes,
+    legit: Outcomes,
+    latencies: Latencies,
+}
+
+/// Decisions on the transactions with a given label.
+#[derive(Serialize, Debug, Default, Clone, Copy)]
+pub struct Outcomes {
+    pub approved: u64,
+    pub declined: u64,
+}
+
+/// Simulates a model in the authorization path.
+///
+/// Every transaction is sent as an authorization request to the designated
+/// client before it is broadcast, and the client must approve or decline it
+/// within the timeout. Unanswered requests get the default decision. The
+/// transaction is then broadcast with the outcome attached. Declined
+/// transactions are reported to their source, so that the mock generator
+/// stops the fraud scenario of the card.
+///
+/// Requests are keyed by transaction id: a transaction whose id is already
+/// pending is not sent and gets the default decision.
+pub struct Authorizer {
+    config: AuthorizationConfig,
+    sources: SourceRegistry,
+    /// Requests queue of the connected client, if any
+    client: Mutex<Option<mpsc::Sender<AuthorizationRequest>>>,
+    pending: Mutex<HashMap<String, oneshot::Sender<AuthorizationDecision>>>,
+    tally: Mutex<Tally>,
+}
+
+impl Authorizer {
+    pub fn new(config: AuthorizationConfig, sources: SourceRegistry) -> Self {
+        Self {
+            config,
+            sources,
+            client: Mutex::default(),
+            pending: Mutex::default(),
+            tally: Mutex::default(),
+        }
+    }
+
+    pub fn config(&self) -> &AuthorizationConfig {
+        &self.config
+    }
+
+    /// Connects the authorizing client, returning the queue of its requests.
+    /// A new connection of the client replaces the previous one.
+    pub fn connect(&self) -> mpsc::Receiver<AuthorizationRequest> {
+        let (tx, rx) = mpsc::channel(MAX_IN_FLIGHT);
+        *self.client.lock().unwrap() = Some(tx);
+        rx
+    }
+
+    /// Whether the transaction is being authorized.
+    pub fn is_pending(&self, transaction_id: &str) -> bool {
+        self.pending.lock().unwrap().contains_key(transaction_id)
+    }
+
+    /// Records the reply of the client to a pending request.
+    pub fn reply(
+        &self,
+        transaction_id: &str,
+        decision: AuthorizationDecision,
+    ) -> Result<(), String> {
+        let pending = self.pending.lock().unwrap().remove(transaction_id);
+        match pending.map(|reply| reply.send(decision)) {
+            Some(Ok(())) => Ok(()),
+            _ => Err(format!(
+                "no pending authorization for transaction {}, it may have timed out",
+                transaction_id
+            )),
+        }
+    }
+
+    /// Sends the transaction to the client and waits for its decision, or
+    /// the timeout. Returns the transaction with the outcome attached.
+    pub async fn authorize(&self, mut transaction: Transaction) -> Transaction {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let registered = match self.pending.lock().unwrap().entry(transaction.id.clone()) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(reply_tx);
+                true
+            }
+        };
+        if !registered {
+            tracing::warn!(
+                "Transaction {} is already being authorized, applying the default decision",
+                transaction.id
+            );
+        }
+
+        // the client must not see the label it is evaluated against
+        let request = AuthorizationRequest {
+            timeout_ms: self.config.timeout.as_millis() as u64,
+            transaction: Transaction {
+                is_fraud: None,
+                ..transaction.clone()
+            },
+        };
+        let sent = registered && {
+            let mut client = self.client.lock().unwrap();
+            match client.as_ref() {
+                None => false,
+                Some(queue) => match queue.try_send(request) {
+                    Ok(()) => true,
+                    Err(mpsc::error::TrySendError::Full(_)) => false,
+                    // forget the disconnected client
+                    Err(mpsc::error::TrySendError::Closed(_)) => {
+                        *client = None;
+                        false
+                    }
+                },
+            }
+        };
+
+        let (decision, latency) = if sent {
+            let sent_at = Instant::now();
+            let decision = tokio::time::timeout(self.config.timeout, reply_rx)
+                .await
+                .ok()
+                .and_then(Result::ok);
+            (decision, sent_at.elapsed())
+        } else {
+            (None, Duration::ZERO)
+        };
+        if registered && decision.is_none() {
+            self.pending.lock().unwrap().remove(&transaction.id);
+        }
+
+        transaction.authorization = Some(self.record(&transaction, decision, sent, latency));
+        transaction
+    }
+
+    /// Records the outcome of a request, `decision` being the reply of the
+    /// client if it replied in time.
+    fn record(
+        &self,
+        transaction: &Transaction,
+        decision: Option<AuthorizationDecision>,
+        sent: bool,
+        latency: Duration,
+    ) -> Authorization {
+        let outcome = decision.unwrap_or(self.config.default);
+        let declined = outcome == AuthorizationDecision::Decline;
+
+        let mut tally = self.tally.lock().unwrap();
+        tally.requests += 1;
+        let status = match (decision, sent) {
+            (Some(_), _) => {
+                tally.latencies.record(latency);
+                AuthorizationStatus::Replied
+            }
+            (None, true) => {
+                tally.timed_out += 1;
+                AuthorizationStatus::TimedOut
+            }
+            (None, false) => {
+                tally.unavailable += 1;
+                AuthorizationStatus::Unavailable
+            }
+        };
+        let labelled = match transaction.is_fraud {
+            Some(true) => Some(&mut tally.fraud),
+            Some(false) => Some(&mut tally.legit),
+            None => None,
+        };
+        if let Some(outcomes) = labelled {
+            outcomes.approved += !declined as u64;
+            outcomes.declined += declined as u64;
+        }
+        tally.approved += !declined as u64;
+        tally.declined += declined as u64;
+        drop(tally);
+
+        if declined {
+            self.sources.declined(transaction);
+        }
+        Authorization {
+            decision: outcome,
+            status,
+        }
+    }
+
+    /// Returns the outcomes of the authorization requests so far.
+    pub fn summary(&self) -> AuthorizationSummary {
+        let tally = self.tally.lock().unwrap();
+        AuthorizationSummary {
+            client_id: self.config.client_id.clone(),
+            connected: self
+                .client
+                .lock()
+                .unwrap()
+                .as_ref()
+                .is_some_and(|client| !client.is_closed()),
+            timeout_ms: self.config.timeout.as_millis() as u64,
+            default: self.config.default,
+            requests: tally.requests,
+            approved: tally.approved,
+            declined: tally.declined,
+            timed_out: tally.timed_out,
+            unavailable: tally.unavailable,
+            fraud: tally.fraud,
+            legit: tally.legit,
+            latency_ms: tally.latencies.summary(),
+        }
+    }
+}
+
+/// Outcomes of the authorization requests.
+#[derive(Serialize, Debug)]
+pub struct AuthorizationSummary {
+    pub client_id: String,
+    /// Whether the authorizing client is connected
+    pub connected: bool,
+    pub timeout_ms: u64,
+    pub default: AuthorizationDecision,
+    pub requests: u64,
+    /// Requests approved or declined, by the client or by default
+    pub approved: u64,
+    pub declined: u64,
+    /// Requests the client did not reply to in time
+    pub timed_out: u64,
+    /// Requests not sent, the client being disconnected or too slow to
+    /// receive them, or the transaction id being already pending
+    pub unavailable: u64,
+    /// Outcomes of the labelled transactions
+    pub fraud: Outcomes,
+    pub legit: Outcomes,
+    /// Time from the request to the reply of the client
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<Latency>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn authorizer(default: AuthorizationDecision) -> Authorizer {
+        let config = AuthorizationConfig {
+            client_id: "model".to_string(),
+            token: "secret".to_string(),
+            timeout: Duration::from_millis(200),
+            default,
+        };
+        Authorizer::new(config, SourceRegistry::new())
+    }
+
+    fn transaction() -> Transaction {
+        Transaction {
+            is_fraud: Some(true),
+            ..Transaction::simple_mock()
+        }
+    }
+
+    #[tokio::test]
+    async fn replies_are_applied_without_the_label() {
+        let authorizer = Arc::new(authorizer(AuthorizationDecision::Approve));
+        let mut requests = authorizer.connect();
+
+        let pending = tokio::spawn({
+            let authorizer = authorizer.clone();
+            async move { authorizer.authorize(transaction()).await }
+        });
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request.transaction.is_fraud, None);
+        authorizer
+            .reply(&request.transaction.id, AuthorizationDecision::Decline)
+            .unwrap();
+
+        let authorized = pending.await.unwrap();
+        assert_eq!(authorized.is_fraud, Some(true));
+        let authorization = authorized.authorization.unwrap();
+        assert_eq!(authorization.decision, AuthorizationDecision::Decline);
+        assert_eq!(authorization.status, AuthorizationStatus::Replied);
+        let summary = authorizer.summary();
+        assert_eq!((summary.fraud.approved, summary.fraud.declined), (0, 1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn timed_out_requests_get_the_default_decision() {
+        let authorizer = authorizer(AuthorizationDecision::Decline);
+        let mut requests = authorizer.connect();
+
+        let authorized = authorizer.authorize(transaction()).await;
+        let authorization = authorized.authorization.unwrap();
+        assert_eq!(authorization.decision, AuthorizationDecision::Decline);
+        assert_eq!(authorization.status, AuthorizationStatus::TimedOut);
+
+        // the late reply is rejected
+        let request = requests.recv().await.unwrap();
+        assert!(authorizer
+            .reply(&request.transaction.id, AuthorizationDecision::Approve)
+            .is_err());
+        let summary = authorizer.summary();
+        assert_eq!((summary.requests, summary.timed_out), (1, 1));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn pending_ids_are_not_sent_twice() {
+        let authorizer = Arc::new(authorizer(AuthorizationDecision::Approve));
+        let mut requests = authorizer.connect();
+        let first = transaction();
+        let duplicate = first.clone();
+
+        let pending = tokio::spawn({
+            let authorizer = authorizer.clone();
+            async move { authorizer.authorize(first).await }
+        });
+        let request = requests.recv().await.unwrap();
+
+        let authorized = authorizer.authorize(duplicate).await;
+        let authorization = authorized.authorization.unwrap();
+        assert_eq!(authorization.decision, AuthorizationDecision::Approve);
+        assert_eq!(authorization.status, AuthorizationStatus::Unavailable);
+        assert!(requests.try_recv().is_err());
+
+        // the first request is still pending
+        authorizer
+            .reply(&request.transaction.id, AuthorizationDecision::Decline)
+            .unwrap();
+        let authorization = pending.await.unwrap().authorization.unwrap();
+        assert_eq!(authorization.status, AuthorizationStatus::Replied);
+        assert_eq!(authorization.decision, AuthorizationDecision::Decline);
+    }
+}
@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+/// Settings of the websocket connections.
+///
+/// - WS_MAX_LAG_EVENTS: disconnect clients lagging more than this many
+///   times within the window (default 0, never disconnect)
+/// - WS_LAG_WINDOW_SECS: window for counting lag events (default 60)
+/// - WS_PING_INTERVAL_SECS: interval between pings (default 30, 0 disables)
+/// - WS_PONG_TIMEOUT_SECS: delay for the client to answer a ping (default 10)
+/// - WS_IDLE_TIMEOUT_SECS: close connections the client sent nothing on
+///   for this long (default 0, never close)
+///
+#[derive(Debug, Clone)]
+pub struct WsConfig {
+    pub max_lag_events: usize,
+    pub lag_window: Duration,
+    pub ping_interval: Option<Duration>,
+    pub pong_timeout: Duration,
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for WsConfig {
+    fn default() -> Self {
+        Self {
+            max_lag_events: 0,
+            lag_window: Duration::from_secs(60),
+            ping_interval: Some(Duration::from_secs(30)),
+            pong_timeout: Duration::from_secs(10),
+            idle_timeout: None,
+        }
+    }
+}
+
+impl WsConfig {
+    /// Reads the websocket settings from the environment, falling back
+    /// to the defaults for missing or invalid values.
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            max_lag_events: env_or("WS_MAX_LAG_EVENTS", default.max_lag_events),
+            lag_window: Duration::from_secs(env_or(
+                "WS_LAG_WINDOW_SECS",
+                default.lag_window.as_secs(),
+            )),
+            ping_interval: secs_or(
+                "WS_PING_INTERVAL_SECS",
+                default.ping_interval.map_or(0, |d| d.as_secs()),
+            ),
+            pong_timeout: Duration::from_secs(
+                env_or("WS_PONG_TIMEOUT_SECS", default.pong_timeout.as_secs()).max(1),
+            ),
+            idle_timeout: secs_or(
+                "WS_IDLE_TIMEOUT_SECS",
+                default.idle_timeout.map_or(0, |d| d.as_secs()),
+            ),
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Reads an optional duration in seconds, 0 meaning disabled.
+fn secs_or(name: &str, default: u64) -> Option<Duration> {
+    Some(Duration::from_secs(env_or(name, default))).filter(|d| !d.is_zero())
+}
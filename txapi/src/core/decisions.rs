@@ -0,0 +1,413 @@
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+use super::metrics::{Latencies, Latency};
+use crate::{domain::prelude::*, stream::sequenced::Sequenced};
+
+/// Number of broadcast transactions clients can send a decision for, the
+/// oldest ones are forgotten beyond it.
+const MAX_TRACKED_TRANSACTIONS: usize = 100_000;
+
+/// Time clients have to send a decision for a broadcast transaction.
+const DECISION_WINDOW: Duration = Duration::from_secs(300);
+
+/// Number of clients tracked, the evaluation of the least recently active
+/// one is forgotten beyond it.
+const MAX_CLIENTS: usize = 1000;
+
+/// Reasons a decision is not counted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecisionError {
+    /// Never broadcast, or forgotten since
+    UnknownTransaction(String),
+    /// The client already sent a decision for the transaction
+    AlreadyDecided(String),
+}
+
+impl std::fmt::Display for DecisionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownTransaction(id) => write!(f, "unknown transaction {}", id),
+            Self::AlreadyDecided(id) => write!(f, "transaction {} already decided", id),
+        }
+    }
+}
+
+/// A broadcast transaction awaiting decisions.
+struct Pending {
+    is_fraud: Option<bool>,
+    broadcast_at: Instant,
+    /// Clients that sent a decision
+    decided_by: Vec<String>,
+}
+
+/// Confusion matrix and latencies of the decisions of a client.
+#[derive(Debug, Default)]
+struct Tally {
+    true_positives: u64,
+    false_positives: u64,
+    true_negatives: u64,
+    false_negatives: u64,
+    unlabelled: u64,
+    /// Labelled fraud that expired without a decision of the client
+    missed: u64,
+    rejected: u64,
+    latencies: Latencies,
+    /// Time of the first decision, accepted or rejected
+    first_active: Option<Instant>,
+    /// Time of the last decision, accepted or rejected
+    last_active: Option<Instant>,
+}
+
+#[derive(Default)]
+struct Inner {
+    pending: HashMap<String, Pending>,
+    order: VecDeque<String>,
+    clients: BTreeMap<String, Tally>,
+}
+
+/// Evaluates the fraud decisions sent back by the websocket clients against
+/// the labels of the transactions.
+///
+/// Decisions are accepted for the last broadcast transactions, once per
+/// client, for the `DECISION_WINDOW`. Their latency is measured from the
+/// broadcast of the transaction. Labelled fraud that expires without a
+/// decision of a client active at the time counts as a false negative of
+/// the client.
+///
+/// The evaluations of the last `MAX_CLIENTS` active clients are kept, so
+/// that the random ids of the anonymous connections do not accumulate.
+#[derive(Default)]
+pub struct DecisionTracker {
+    inner: Mutex<Inner>,
+}
+
+impl DecisionTracker {
+    /// Spawns a task recording the broadcast transactions, until the
+    /// cancellation token is cancelled.
+    pub fn track(
+        self: &Arc<Self>,
+        mut transactions_rx: broadcast::Receiver<Sequenced<Transaction>>,
+        cancellation_token: CancellationToken,
+    ) {
+        let tracker = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let transaction = tokio::select! {
+                    _ = cancellation_token.cancelled() => break,
+                    transaction = transactions_rx.recv() => transaction,
+                };
+
+                match transaction {
+                    Ok(transaction) => tracker.broadcast(&transaction.data),
+                    Err(RecvError::Lagged(n)) => {
+                        tracing::warn!("Decision tracking lagged, {} transactions missed", n)
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// Records a broadcast transaction, with its label if any.
+    pub fn broadcast(&self, transaction: &Transaction) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.expire(Instant::now());
+        inner.order.push_back(transaction.id.clone());
+        inner.pending.insert(
+            transaction.id.clone(),
+            Pending {
+                is_fraud: transaction.is_fraud,
+                broadcast_at: Instant::now(),
+                decided_by: Vec::new(),
+            },
+        );
+    }
+
+    /// Whether the transaction was broadcast and still accepts decisions.
+    pub fn is_pending(&self, transaction_id: &str) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        inner.expire(Instant::now());
+        inner.pending.contains_key(transaction_id)
+    }
+
+    /// Records the decision of a client on a transaction, `fraud` being
+    /// whether the client flagged it.
+    pub fn decide(
+        &self,
+        client_id: &str,
+        transaction_id: &str,
+        fraud: bool,
+    ) -> Result<(), DecisionError> {
+        let mut inner = self.inner.lock().unwrap();
+        let Inner {
+            pending, clients, ..
+        } = &mut *inner;
+
+        if !clients.contains_key(client_id) && clients.len() >= MAX_CLIENTS {
+            let least_recent = clients
+                .iter()
+                .min_by_key(|(_, tally)| tally.last_active)
+                .map(|(id, _)| id.clone());
+            if let Some(id) = least_recent {
+                clients.remove(&id);
+            }
+        }
+        let tally = clients.entry(client_id.to_string()).or_default();
+        let now = Instant::now();
+        tally.first_active.get_or_insert(now);
+        tally.last_active = Some(now);
+
+        let Some(transaction) = pending.get_mut(transaction_id) else {
+            tally.rejected += 1;
+            return Err(DecisionError::UnknownTransaction(
+                transaction_id.to_string(),
+            ));
+        };
+        if transaction.decided_by.iter().any(|id| id == client_id) {
+            tally.rejected += 1;
+            return Err(DecisionError::AlreadyDecided(transaction_id.to_string()));
+        }
+        transaction.decided_by.push(client_id.to_string());
+
+        match (fraud, transaction.is_fraud) {
+            (true, Some(true)) => tally.true_positives += 1,
+            (true, Some(false)) => tally.false_positives += 1,
+            (false, Some(false)) => tally.true_negatives += 1,
+            (false, Some(true)) => tally.false_negatives += 1,
+            (_, None) => tally.unlabelled += 1,
+        }
+
+        tally.latencies.record(transaction.broadcast_at.elapsed());
+        Ok(())
+    }
+
+    /// Returns the evaluation of every client that sent decisions.
+    pub fn evaluations(&self) -> BTreeMap<String, ClientEvaluation> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .clients
+            .iter()
+            .map(|(id, tally)| (id.clone(), tally.evaluation()))
+            .collect()
+    }
+}
+
+impl Inner {
+    /// Forgets the transactions broadcast before the decision window, and
+    /// the oldest ones beyond `MAX_TRACKED_TRANSACTIONS`, making room for
+    /// one more. Expired fraud counts as missed by the clients that were
+    /// active when it was broadcast, i.e. sent decisions before and after
+    /// it, and did not decide it.
+    fn expire(&mut self, now: Instant) {
+        while let Some(id) = self.order.front() {
+            let expired = self.pending.get(id).is_none_or(|transaction| {
+                now.saturating_duration_since(transaction.broadcast_at) >= DECISION_WINDOW
+            });
+            if !expired && self.order.len() < MAX_TRACKED_TRANSACTIONS {
+                break;
+            }
+            let Some(transaction) = self
+                .order
+                .pop_front()
+                .and_then(|id| self.pending.remove(&id))
+            else {
+                continue;
+            };
+            if transaction.is_fraud != Some(true) {
+                continue;
+            }
+            for (id, tally) in self.clients.iter_mut() {
+                let active = tally.first_active <= Some(transaction.broadcast_at)
+                    && tally.last_active >= Some(transaction.broadcast_at);
+                if active && !transaction.decided_by.contains(id) {
+                    tally.missed += 1;
+                }
+            }
+        }
+    }
+}
+
+impl Tally {
+    fn evaluation(&self) -> ClientEvaluation {
+        let ratio = |n: u64, d: u64| (d > 0).then(|| n as f64 / d as f64);
+        let decisions = self.true_positives
+            + self.false_positives
+            + self.true_negatives
+            + self.false_negatives
+            + self.unlabelled;
+
+        ClientEvaluation {
+            decisions,
+            rejected: self.rejected,
+            true_positives: self.true_positives,
+            false_positives: self.false_positives,
+            true_negatives: self.true_negatives,
+            false_negatives: self.false_negatives + self.missed,
+            unlabelled: self.unlabelled,
+            missed: self.missed,
+            precision: ratio(
+                self.true_positives,
+                self.true_positives + self.false_positives,
+            ),
+            recall: ratio(
+                self.true_positives,
+                self.true_positives + self.false_negatives + self.missed,
+            ),
+            false_positive_rate: ratio(
+                self.false_positives,
+                self.false_positives + self.true_negatives,
+            ),
+            latency_ms: self.latencies.summary(),
+        }
+    }
+}
+
+/// Evaluation of the decisions of a client against the labels.
+///
+/// The rates are omitted until they are defined, e.g. the precision until
+/// the client flags a labelled transaction as fraud.
+#[derive(Serialize, Debug)]
+pub struct ClientEvaluation {
+    /// Decisions counted, on labelled and unlabelled transactions
+    pub decisions: u64,
+    /// Decisions for unknown or already decided transactions
+    pub rejected: u64,
+    pub true_positives: u64,
+    pub false_positives: u64,
+    pub true_negatives: u64,
+    /// Labelled fraud decided as legit or missed
+    pub false_negatives: u64,
+    /// Decisions on transactions without a label
+    pub unlabelled: u64,
+    /// Labelled fraud that expired without a decision, counted in the
+    /// false negatives
+    pub missed: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub precision: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recall: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub false_positive_rate: Option<f64>,
+    /// Time from the broadcast of the transactions to the decisions, the
+    /// percentiles are over the recent decisions
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<Latency>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn broadcast(tracker: &DecisionTracker, is_fraud: Option<bool>) -> String {
+        let transaction = Transaction {
+            is_fraud,
+            ..Transaction::simple_mock()
+        };
+        tracker.broadcast(&transaction);
+        transaction.id
+    }
+
+    fn pause() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+
+    #[test]
+    fn decisions_are_evaluated_against_the_labels() {
+        let tracker = DecisionTracker::default();
+        let first = broadcast(&tracker, Some(true));
+        tracker.decide("a", &first, true).unwrap();
+        pause();
+        let legit = broadcast(&tracker, Some(false));
+        // missed by "a"
+        broadcast(&tracker, Some(true));
+        let unlabelled = broadcast(&tracker, None);
+        pause();
+        tracker.decide("a", &legit, true).unwrap();
+        tracker.decide("a", &unlabelled, false).unwrap();
+        // not active when the missed fraud was broadcast
+        tracker.decide("b", &legit, false).unwrap();
+
+        tracker
+            .inner
+            .lock()
+            .unwrap()
+            .expire(Instant::now() + DECISION_WINDOW);
+        let evaluations = tracker.evaluations();
+
+        let a = &evaluations["a"];
+        assert_eq!(a.decisions, 3);
+        assert_eq!((a.true_positives, a.false_positives), (1, 1));
+        assert_eq!((a.true_negatives, a.false_negatives), (0, 1));
+        assert_eq!((a.unlabelled, a.missed), (1, 1));
+        assert_eq!(a.precision, Some(0.5));
+        assert_eq!(a.recall, Some(0.5));
+        assert_eq!(a.false_positive_rate, Some(1.0));
+        assert!(a.latency_ms.is_some());
+
+        let b = &evaluations["b"];
+        assert_eq!((b.decisions, b.true_negatives), (1, 1));
+        assert_eq!((b.false_negatives, b.missed), (0, 0));
+        assert_eq!(b.precision, None);
+        assert_eq!(b.recall, None);
+    }
+
+    #[test]
+    fn duplicate_and_unknown_decisions_are_rejected() {
+        let tracker = DecisionTracker::default();
+        let id = broadcast(&tracker, Some(false));
+        tracker.decide("a", &id, false).unwrap();
+        assert_eq!(
+            tracker.decide("a", &id, true),
+            Err(DecisionError::AlreadyDecided(id.clone()))
+        );
+        assert_eq!(
+            tracker.decide("a", "unknown", true),
+            Err(DecisionError::UnknownTransaction("unknown".to_string()))
+        );
+        // other clients can still decide it
+        tracker.decide("b", &id, true).unwrap();
+
+        tracker
+            .inner
+            .lock()
+            .unwrap()
+            .expire(Instant::now() + DECISION_WINDOW);
+        assert_eq!(
+            tracker.decide("b", &id, true),
+            Err(DecisionError::UnknownTransaction(id.clone()))
+        );
+
+        let evaluations = tracker.evaluations();
+        assert_eq!(evaluations["a"].decisions, 1);
+        assert_eq!(evaluations["a"].rejected, 2);
+        assert_eq!(evaluations["b"].false_positives, 1);
+        assert_eq!(evaluations["b"].rejected, 1);
+    }
+
+    #[test]
+    fn least_recently_active_clients_are_forgotten() {
+        let tracker = DecisionTracker::default();
+        let id = broadcast(&tracker, None);
+        for client in 0..MAX_CLIENTS {
+            tracker
+                .decide(&format!("{:04}", client), &id, false)
+                .unwrap();
+        }
+        pause();
+        tracker.decide("0000", "unknown", false).unwrap_err();
+        tracker.decide("new", &id, false).unwrap();
+
+        let evaluations = tracker.evaluations();
+        assert_eq!(evaluations.len(), MAX_CLIENTS);
+        assert!(evaluations.contains_key("0000"));
+        assert!(evaluations.contains_key("new"));
+        assert!(!evaluations.contains_key("0001"));
+    }
+}
@@ -0,0 +1,292 @@
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    sync::atomic::{AtomicI64, AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Number of recent latencies the percentiles are computed over.
+const LATENCY_SAMPLES: usize = 1000;
+
+/// A monotonically increasing counter.
+#[derive(Debug, Default)]
+pub struct Counter(AtomicU64);
+
+impl Counter {
+    pub fn inc(&self) {
+        self.add(1);
+    }
+
+    pub fn add(&self, n: u64) {
+        self.0.fetch_add(n, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A value that can go up and down.
+#[derive(Debug, Default)]
+pub struct Gauge(AtomicI64);
+
+impl Gauge {
+    pub fn inc(&self) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn dec(&self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+/// A latency histogram, with cumulative buckets as in Prometheus.
+#[derive(Debug, Default)]
+pub struct Histogram {
+    /// Observations per bucket, the last one being `+Inf`
+    buckets: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    /// Sum of the observations, in microseconds
+    sum_micros: AtomicU64,
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        let idx = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| secs <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.buckets[idx].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+/// Latencies of the replies of a client, with the percentiles over the
+/// recent ones.
+#[derive(Debug, Default)]
+pub struct Latencies {
+    count: u64,
+    sum: Duration,
+    max: Duration,
+    recent: VecDeque<Duration>,
+}
+
+impl Latencies {
+    pub fn record(&mut self, latency: Duration) {
+        self.count += 1;
+        self.sum += latency;
+        self.max = self.max.max(latency);
+        if self.recent.len() >= LATENCY_SAMPLES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(latency);
+    }
+
+    /// Returns the summary of the latencies, in milliseconds, if any.
+    pub fn summary(&self) -> Option<Latency> {
+        if self.count == 0 {
+            return None;
+        }
+        let mut recent: Vec<_> = self.recent.iter().copied().collect();
+        recent.sort();
+        let percentile = |p: f64| {
+            let idx = ((recent.len() as f64 * p).ceil() as usize).saturating_sub(1);
+            recent.get(idx).copied().map(millis).unwrap_or_default()
+        };
+
+        Some(Latency {
+            mean: millis(self.sum) / self.count as f64,
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            max: millis(self.max),
+        })
+    }
+}
+
+/// Summary of latencies, in milliseconds.
+#[derive(Serialize, Debug)]
+pub struct Latency {
+    pub mean: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub max: f64,
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Server metrics, shared through the app state and exposed by the
+/// `/metrics` endpoint in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Number of open websocket connections.
+    pub ws_connections: Gauge,
+
+    /// Number of messages sent to websocket clients.
+    pub ws_messages_sent: Counter,
+
+    /// Number of times a websocket client fell behind the broadcast buffer.
+    pub ws_lag_events: Counter,
+
+    /// Number of transactions skipped by lagging websocket clients.
+    pub ws_dropped_transactions: Counter,
+
+    /// Number of websocket clients disconnected for being chronically slow.
+    pub ws_slow_consumer_disconnects: Counter,
+
+    /// Number of websocket clients disconnected for not answering pings or being idle.
+    pub ws_keepalive_disconnects: Counter,
+
+    /// Number of open Server-Sent Events streams.
+    pub sse_connections: Gauge,
+
+    /// Number of requests made to the scoring webhook, including the retries.
+    pub scoring_requests: Counter,
+
+    /// Number of retried requests to the scoring webhook.
+    pub scoring_retries: Counter,
+
+    /// Number of transactions that could not be scored.
+    pub scoring_failures: Counter,
+
+    /// Latency of the requests to the scoring webhook.
+    pub scoring_latency: Histogram,
+
+    /// Number of transactions dropped by the event log writer.
+    pub event_log_dropped: Counter,
+
+    /// Number of Kafka messages skipped for not being valid transactions.
+    pub kafka_rejected: Counter,
+}
+
+impl Metrics {
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        gauge(
+            &mut out,
+            "txapi_ws_connections",
+            "Open websocket connections",
+            self.ws_connections.get(),
+        );
+        counter(
+            &mut out,
+            "txapi_ws_messages_sent_total",
+            "Messages sent to websocket clients",
+            self.ws_messages_sent.get(),
+        );
+        counter(
+            &mut out,
+            "txapi_ws_lag_events_total",
+            "Times a websocket client fell behind the broadcast buffer",
+            self.ws_lag_events.get(),
+        );
+        counter(
+            &mut out,
+            "txapi_ws_dropped_transactions_total",
+            "Transactions skipped by lagging websocket clients",
+            self.ws_dropped_transactions.get(),
+        );
+        counter(
+            &mut out,
+            "txapi_ws_slow_consumer_disconnects_total",
+            "Websocket clients disconnected for being chronically slow",
+            self.ws_slow_consumer_disconnects.get(),
+        );
+        counter(
+            &mut out,
+            "txapi_ws_keepalive_disconnects_total",
+            "Websocket clients disconnected for not answering pings or being idle",
+            self.ws_keepalive_disconnects.get(),
+        );
+        gauge(
+            &mut out,
+            "txapi_sse_connections",
+            "Open Server-Sent Events streams",
+            self.sse_connections.get(),
+        );
+        counter(
+            &mut out,
+            "txapi_scoring_requests_total",
+            "Requests made to the scoring webhook, including the retries",
+            self.scoring_requests.get(),
+        );
+        counter(
+            &mut out,
+            "txapi_scoring_retries_total",
+            "Retried requests to the scoring webhook",
+            self.scoring_retries.get(),
+        );
+        counter(
+            &mut out,
+            "txapi_scoring_failures_total",
+            "Transactions that could not be scored",
+            self.scoring_failures.get(),
+        );
+        histogram(
+            &mut out,
+            "txapi_scoring_latency_seconds",
+            "Latency of the requests to the scoring webhook",
+            &self.scoring_latency,
+        );
+        counter(
+            &mut out,
+            "txapi_event_log_dropped_total",
+            "Transactions dropped by the event log writer",
+            self.event_log_dropped.get(),
+        );
+        counter(
+            &mut out,
+            "txapi_kafka_rejected_total",
+            "Kafka messages skipped for not being valid transactions",
+            self.kafka_rejected.get(),
+        );
+
+        out
+    }
+}
+
+fn counter(out: &mut String, name: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} counter", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn gauge(out: &mut String, name: &str, help: &str, value: i64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn histogram(out: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} histogram", name);
+    let mut count = 0;
+    for (idx, bucket) in histogram.buckets.iter().enumerate() {
+        count += bucket.load(Ordering::Relaxed);
+        match LATENCY_BUCKETS.get(idx) {
+            Some(bound) => {
+                let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+            }
+            None => {
+                let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+            }
+        }
+    }
+    let sum = histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+    let _ = writeln!(out, "{}_sum {}", name, sum);
+    let _ = writeln!(out, "{}_count {}", name, count);
+}
@@ -1,5 +1,11 @@
+pub mod authorization;
+pub mod config;
+pub mod decisions;
+pub mod metrics;
 pub mod state;
 
 pub mod prelude {
-    pub use super::state::AppState;
+    pub use super::{
+        authorization::Authorizer, decisions::DecisionTracker, metrics::Metrics, state::AppState,
+    };
 }
@@ -1,17 +1,69 @@
-// use crate::{api::ws, domain::prelude::*};
-use crate::domain::prelude::*;
-use tokio::sync::broadcast;
+use super::{
+    authorization::Authorizer, config::WsConfig, decisions::DecisionTracker, metrics::Metrics,
+};
+use crate::{
+    domain::prelude::*,
+    stream::{
+        eventlog::EventLog, heartbeat::ServerStatus, sequenced::SequencedSender,
+        source::SourceRegistry,
+    },
+};
+use std::sync::Arc;
 use tokio_util::sync::CancellationToken;
 
 #[derive(Clone)]
 pub struct AppState {
     /// The sender for the heartbeat channel.
     /// Used to broadcast heartbeats to the websocket clients.
-    pub heartbeat_tx: broadcast::Sender<Heartbeat>,
+    pub heartbeat_tx: SequencedSender<Heartbeat>,
 
     /// The sender for the transactions channel.
     /// Used to broadcast transactions to the websocket clients.
-    pub transactions_tx: broadcast::Sender<Transaction>,
+    pub transactions_tx: SequencedSender<Transaction>,
+
+    /// The sender for the enriched transactions channel.
+    /// Used to broadcast the transactions with their per-card features.
+    pub enriched_tx: SequencedSender<EnrichedTransaction>,
+
+    /// The sender for the alerts channel.
+    /// Used to broadcast the transactions matching the rules.
+    pub alerts_tx: SequencedSender<Alert>,
+
+    /// The sender for the scored transactions channel.
+    /// Used to broadcast the transactions with the score of the webhook.
+    pub scored_tx: SequencedSender<ScoredTransaction>,
+
+    /// The sender for the stats channel.
+    /// Used to broadcast the transactions aggregates to the websocket clients.
+    pub stats_tx: SequencedSender<Stats>,
+
+    /// The server state reported in the heartbeats.
+    /// Used by the clients overriding the heartbeat interval.
+    pub status: ServerStatus,
+
+    /// The sources feeding the transactions channel.
+    /// Used to report the health of the sources.
+    pub sources: SourceRegistry,
+
+    /// The bearer token required by the ingest endpoint.
+    /// Ingestion is disabled when not set.
+    pub ingest_token: Option<String>,
+
+    /// The durable log of the transactions channel, if enabled.
+    /// Used for historical queries and resuming beyond the retention.
+    pub event_log: Option<EventLog>,
+
+    /// The evaluation of the fraud decisions sent by the clients.
+    pub decisions: Arc<DecisionTracker>,
+
+    /// The authorization simulation, if enabled.
+    pub authorizer: Option<Arc<Authorizer>>,
+
+    /// The websocket connection settings.
+    pub ws_config: WsConfig,
+
+    /// The server metrics.
+    pub metrics: Arc<Metrics>,
 
     /// The cancellation token for graceful shutdown.
     /// Used to signal background tasks to stop.
@@ -19,15 +71,68 @@ pub struct AppState {
 }
 
 impl AppState {
+    #[deprecated(note = "build the state with a struct literal, its fields are public")]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        heartbeat_tx: broadcast::Sender<Heartbeat>,
-        transactions_tx: broadcast::Sender<Transaction>,
+        heartbeat_tx: SequencedSender<Heartbeat>,
+        transactions_tx: SequencedSender<Transaction>,
+        enriched_tx: SequencedSender<EnrichedTransaction>,
+        alerts_tx: SequencedSender<Alert>,
+        scored_tx: SequencedSender<ScoredTransaction>,
+        stats_tx: SequencedSender<Stats>,
+        status: ServerStatus,
+        sources: SourceRegistry,
+        ingest_token: Option<String>,
+        event_log: Option<EventLog>,
+        decisions: Arc<DecisionTracker>,
+        authorizer: Option<Arc<Authorizer>>,
+        ws_config: WsConfig,
+        metrics: Arc<Metrics>,
         cancellation_token: CancellationToken,
     ) -> Self {
         Self {
-            transactions_tx,
             heartbeat_tx,
+            transactions_tx,
+            enriched_tx,
+            alerts_tx,
+            scored_tx,
+            stats_tx,
+            status,
+            sources,
+            ingest_token,
+            event_log,
+            decisions,
+            authorizer,
+            ws_config,
+            metrics,
             cancellation_token,
         }
     }
 }
+
+#[cfg(test)]
+impl AppState {
+    /// State with broadcast buffers of `buffer_size` messages, retaining the
+    /// last `retention` transactions, and every optional feature disabled.
+    pub(crate) fn for_tests(buffer_size: usize, retention: usize, ws_config: WsConfig) -> Self {
+        let sources = SourceRegistry::new();
+        let (transactions_tx, _) = SequencedSender::new(buffer_size, retention, 1);
+        Self {
+            heartbeat_tx: SequencedSender::new(buffer_size, 0, 1).0,
+            transactions_tx: transactions_tx.clone(),
+            enriched_tx: SequencedSender::new(buffer_size, 0, 1).0,
+            alerts_tx: SequencedSender::new(buffer_size, 0, 1).0,
+            scored_tx: SequencedSender::new(buffer_size, 0, 1).0,
+            stats_tx: SequencedSender::new(buffer_size, 0, 1).0,
+            status: ServerStatus::new(transactions_tx, sources.clone()),
+            sources,
+            ingest_token: None,
+            event_log: None,
+            decisions: Arc::default(),
+            authorizer: None,
+            ws_config,
+            metrics: Arc::default(),
+            cancellation_token: CancellationToken::new(),
+        }
+    }
+}
@@ -1,7 +1,14 @@
 pub mod prelude {
     pub use super::{
+        alerts::Alert,
+        authorization::{
+            Authorization, AuthorizationDecision, AuthorizationRequest, AuthorizationStatus,
+        },
+        enrichment::{CardFeatures, EnrichedTransaction},
         heartbeat::Heartbeat,
-        transactions::{Transaction, TransactionCategory},
+        scoring::{Score, ScoredTransaction},
+        stats::{Aggregate, FraudRate, Stats},
+        transactions::{CardNetwork, Transaction, TransactionCategory},
     };
 }
 
@@ -9,9 +16,11 @@ pub mod transactions {
     use rand::Rng;
     use serde::{Deserialize, Serialize};
 
+    use super::authorization::Authorization;
+
     /// Category of merchant for a transaction.
     ///
-    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
     pub enum TransactionCategory {
         #[serde(rename = "grocery")]
         Grocery,
@@ -79,6 +88,41 @@ pub mod transactions {
         }
     }
 
+    /// Card network, derived from the card number prefix.
+    ///
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum CardNetwork {
+        Visa,
+        Mastercard,
+        Amex,
+        Discover,
+        Jcb,
+        DinersClub,
+        Unknown,
+    }
+
+    impl CardNetwork {
+        /// Returns the network of a card number, based on its IIN prefix.
+        pub fn from_cc_number(cc_number: &str) -> Self {
+            let prefix = |len: usize| {
+                cc_number
+                    .get(..len)
+                    .and_then(|prefix| prefix.parse::<u32>().ok())
+            };
+
+            match (prefix(1), prefix(2), prefix(3), prefix(4)) {
+                (Some(4), ..) => Self::Visa,
+                (_, Some(34 | 37), ..) => Self::Amex,
+                (_, Some(51..=55), ..) | (.., Some(2221..=2720)) => Self::Mastercard,
+                (_, Some(65), ..) | (_, _, Some(644..=649), _) | (.., Some(6011)) => Self::Discover,
+                (.., Some(3528..=3589)) => Self::Jcb,
+                (_, Some(36 | 38), ..) | (_, _, Some(300..=305), _) => Self::DinersClub,
+                _ => Self::Unknown,
+            }
+        }
+    }
+
     /// Geographic location data for a transaction.
     ///
     #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -149,9 +193,40 @@ pub mod transactions {
 
         /// Whether the transaction was made online
         pub is_online: bool,
+
+        /// Fraud label, only present when the transaction comes from a
+        /// labelled dataset. It is read but never serialized, so that the
+        /// clients whose decisions are evaluated against it cannot see it.
+        #[serde(default, skip_serializing)]
+        pub is_fraud: Option<bool>,
+
+        /// Name of the source that emitted the transaction
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub source: Option<String>,
+
+        /// Outcome of the authorization, only present when the authorization
+        /// simulation is enabled
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub authorization: Option<Authorization>,
     }
 
     impl Transaction {
+        /// Names of the serialized fields, including the flattened location ones.
+        pub const FIELDS: [&'static str; 12] = [
+            "id",
+            "timestamp",
+            "cc_number",
+            "category",
+            "amount_usd_cents",
+            "city",
+            "country_iso",
+            "latitude",
+            "longitude",
+            "is_online",
+            "source",
+            "authorization",
+        ];
+
         /// Creates a realistic mock transaction with randomized values.
         ///
         /// Generates transactions with:
@@ -177,6 +252,81 @@ pub mod transactions {
                 amount_usd_cents,
                 location,
                 is_online,
+                is_fraud: None,
+                source: None,
+                authorization: None,
+            }
+        }
+
+        /// The transaction of the API examples, shared by the tests.
+        #[cfg(test)]
+        pub(crate) fn fixture() -> Self {
+            Self {
+                id: "11df919988c134d97bbff2678eb68e22".to_string(),
+                timestamp: "2024-01-01T00:00:00Z".to_string(),
+                cc_number: "4473593503484549".to_string(),
+                category: TransactionCategory::Grocery,
+                amount_usd_cents: 10000,
+                location: Location {
+                    city: "San Francisco".to_string(),
+                    country_iso: "US".to_string(),
+                    latitude: 37.774929,
+                    longitude: -122.419418,
+                },
+                is_online: false,
+                is_fraud: None,
+                source: None,
+                authorization: None,
+            }
+        }
+
+        /// Returns the network of the card.
+        pub fn card_network(&self) -> CardNetwork {
+            CardNetwork::from_cc_number(&self.cc_number)
+        }
+
+        /// Validates the invariants documented on the fields.
+        ///
+        /// Returns the list of violations, so that a caller injecting
+        /// transactions can fix them all at once. The Luhn checksum is not
+        /// checked, invalid card numbers are legitimate edge cases.
+        ///
+        pub fn validate(&self) -> Result<(), Vec<String>> {
+            let mut errors = Vec::new();
+
+            if self.id.is_empty() {
+                errors.push("id must not be empty".to_string());
+            }
+            if chrono::DateTime::parse_from_rfc3339(&self.timestamp).is_err() {
+                errors.push(format!("timestamp is not RFC3339: {}", self.timestamp));
+            }
+            if self.cc_number.is_empty() || !self.cc_number.chars().all(|c| c.is_ascii_digit()) {
+                errors.push(format!(
+                    "cc_number must only contain digits: {}",
+                    self.cc_number
+                ));
+            }
+            let country = &self.location.country_iso;
+            if country.len() != 2 || !country.chars().all(|c| c.is_ascii_uppercase()) {
+                errors.push(format!(
+                    "country_iso is not ISO 3166-1 alpha-2: {}",
+                    country
+                ));
+            }
+            if !(-90.0..=90.0).contains(&self.location.latitude) {
+                errors.push(format!("latitude out of range: {}", self.location.latitude));
+            }
+            if !(-180.0..=180.0).contains(&self.location.longitude) {
+                errors.push(format!(
+                    "longitude out of range: {}",
+                    self.location.longitude
+                ));
+            }
+
+            if errors.is_empty() {
+                Ok(())
+            } else {
+                Err(errors)
             }
         }
 
@@ -184,7 +334,7 @@ pub mod transactions {
         ///
         /// Uses the Visa prefix (4) and generates 15 random digits
         /// plus a valid checksum digit.
-        fn generate_valid_cc_number() -> String {
+        pub fn generate_valid_cc_number() -> String {
             let mut rng = rand::rng();
             let mut digits: Vec<u8> = vec![4]; // Visa prefix
 
@@ -225,10 +375,295 @@ pub mod transactions {
 }
 
 pub mod heartbeat {
+    use chrono::{DateTime, Utc};
     use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
 
+    /// Heartbeat sent to the websocket clients.
+    ///
+    /// Carries enough of the server state for clients to detect stalls
+    /// (the transactions sequence stops increasing) and to measure the
+    /// clock skew with the server.
     #[derive(Deserialize, Serialize, Debug, Clone)]
     pub struct Heartbeat {
+        /// Overall status: `ok`, `degraded` or `idle`
         pub status: String,
+
+        /// Server time when the heartbeat was sent
+        pub server_time: DateTime<Utc>,
+
+        /// Seconds since the server started
+        pub uptime_secs: u64,
+
+        /// Sequence number of the last broadcast transaction
+        pub transactions_seq: Option<u64>,
+
+        /// Status of each transaction source, by name
+        pub sources: BTreeMap<String, String>,
+    }
+}
+
+pub mod stats {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    use super::transactions::{Transaction, TransactionCategory};
+
+    /// Aggregates of the transactions broadcast over a time window.
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct Stats {
+        /// Window name, such as `tumbling_60s` or `sliding_300s`
+        pub window: String,
+
+        /// Start of the window, inclusive
+        pub start: DateTime<Utc>,
+
+        /// End of the window, exclusive
+        pub end: DateTime<Utc>,
+
+        /// All the transactions of the window
+        pub total: Aggregate,
+
+        /// Transactions by merchant category
+        pub by_category: BTreeMap<TransactionCategory, Aggregate>,
+
+        /// Transactions by country
+        pub by_country: BTreeMap<String, Aggregate>,
+
+        /// Online transactions
+        pub online: Aggregate,
+
+        /// In-person transactions
+        pub offline: Aggregate,
+
+        /// Fraud rate, only present when some transactions are labelled
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub fraud: Option<FraudRate>,
+    }
+
+    /// Count and amount of a set of transactions.
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, Default)]
+    pub struct Aggregate {
+        pub count: u64,
+        pub amount_usd_cents: u64,
+    }
+
+    impl Aggregate {
+        pub fn add(&mut self, transaction: &Transaction) {
+            self.count += 1;
+            self.amount_usd_cents += transaction.amount_usd_cents;
+        }
+
+        pub fn merge(&mut self, other: &Self) {
+            self.count += other.count;
+            self.amount_usd_cents += other.amount_usd_cents;
+        }
+    }
+
+    /// Share of the labelled transactions that are fraudulent.
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, Default)]
+    pub struct FraudRate {
+        pub labelled: u64,
+        pub fraudulent: u64,
+        pub rate: f64,
+    }
+}
+
+pub mod enrichment {
+    use serde::{Deserialize, Serialize};
+
+    use super::transactions::Transaction;
+
+    /// A transaction with the features computed from the history of its card.
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct EnrichedTransaction {
+        #[serde(flatten)]
+        pub transaction: Transaction,
+
+        pub features: CardFeatures,
+    }
+
+    /// Per-card velocity and behaviour features.
+    ///
+    /// The counts and amounts include the transaction itself, the other
+    /// features describe the history before it.
+    #[derive(Deserialize, Serialize, Debug, Clone, Default)]
+    pub struct CardFeatures {
+        /// Transactions of the card over the last hour
+        pub count_1h: u64,
+        /// Amount of the transactions of the card over the last hour, in USD cents
+        pub amount_1h_usd_cents: u64,
+
+        /// Transactions of the card over the last 24 hours
+        pub count_24h: u64,
+        /// Amount of the transactions of the card over the last 24 hours, in USD cents
+        pub amount_24h_usd_cents: u64,
+
+        /// Transactions of the card over the last 7 days
+        pub count_7d: u64,
+        /// Amount of the transactions of the card over the last 7 days, in USD cents
+        pub amount_7d_usd_cents: u64,
+
+        /// Seconds since the previous transaction of the card
+        pub secs_since_previous: Option<f64>,
+
+        /// Distance from the previous transaction of the card, in kilometers
+        pub km_from_previous: Option<f64>,
+
+        /// Country of the previous transaction of the card
+        pub previous_country_iso: Option<String>,
+
+        /// First transaction of the card in this merchant category, standing
+        /// in for the first transaction at the merchant, which the
+        /// transactions do not identify
+        pub first_in_category: bool,
+
+        /// First transaction of the card in this country
+        pub first_in_country: bool,
+
+        /// Average amount of the previous transactions of the card, in USD cents
+        pub avg_amount_usd_cents: Option<f64>,
+    }
+}
+
+pub mod alerts {
+    use serde::{Deserialize, Serialize};
+
+    use super::enrichment::EnrichedTransaction;
+
+    /// A transaction matching a rule.
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct Alert {
+        /// Identifier of the matching rule
+        pub rule_id: String,
+
+        /// Score of the rule, between 0 and 1
+        pub score: f64,
+
+        /// Why the transaction matched, such as `6 transactions in 600s`
+        pub reason: String,
+
+        /// The offending transaction, with its card features
+        pub transaction: EnrichedTransaction,
+    }
+}
+
+pub mod authorization {
+    use serde::{Deserialize, Serialize};
+
+    use super::transactions::Transaction;
+
+    /// A transaction awaiting the decision of the authorizing client.
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct AuthorizationRequest {
+        /// Time left to reply, after which the default decision applies
+        pub timeout_ms: u64,
+
+        pub transaction: Transaction,
+    }
+
+    /// Outcome of an authorization request.
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum AuthorizationDecision {
+        Approve,
+        Decline,
+    }
+
+    /// Authorization of a transaction, attached to it before it is broadcast.
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Authorization {
+        pub decision: AuthorizationDecision,
+
+        /// Whether the decision is the reply of the client or the default one
+        pub status: AuthorizationStatus,
+    }
+
+    /// How an authorization decision was made.
+    #[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+    #[serde(rename_all = "snake_case")]
+    pub enum AuthorizationStatus {
+        /// Replied by the authorizing client in time
+        Replied,
+
+        /// The client did not reply in time, the default decision applies
+        TimedOut,
+
+        /// The request could not be sent, the client being disconnected or
+        /// too far behind, the default decision applies
+        Unavailable,
+    }
+
+    impl std::str::FromStr for AuthorizationDecision {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s {
+                "approve" => Ok(Self::Approve),
+                "decline" => Ok(Self::Decline),
+                _ => Err(format!("invalid authorization decision: {}", s)),
+            }
+        }
+    }
+}
+
+pub mod scoring {
+    use serde::{Deserialize, Serialize};
+
+    use super::transactions::Transaction;
+
+    /// A transaction with the score returned by the scoring webhook.
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct ScoredTransaction {
+        #[serde(flatten)]
+        pub transaction: Transaction,
+
+        pub scoring: Score,
+    }
+
+    /// Outcome of the scoring of a transaction.
+    #[derive(Deserialize, Serialize, Debug, Clone)]
+    pub struct Score {
+        /// Score returned by the webhook, absent when the scoring failed
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub score: Option<f64>,
+
+        /// Why the scoring failed, after the retries
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub error: Option<String>,
+
+        /// Number of requests made, including the retries
+        pub attempts: u32,
+
+        /// Time spent scoring, including the retries
+        pub latency_ms: f64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::prelude::*;
+
+    #[test]
+    fn fields_are_the_serialized_ones() {
+        let transaction = Transaction {
+            is_fraud: Some(true),
+            source: Some("mock".to_string()),
+            authorization: Some(Authorization {
+                decision: AuthorizationDecision::Approve,
+                status: AuthorizationStatus::Replied,
+            }),
+            ..Transaction::fixture()
+        };
+        let serde_json::Value::Object(object) = serde_json::to_value(&transaction).unwrap() else {
+            panic!("transactions serialize to objects");
+        };
+
+        let mut serialized: Vec<&str> = object.keys().map(String::as_str).collect();
+        let mut fields = Transaction::FIELDS.to_vec();
+        serialized.sort_unstable();
+        fields.sort_unstable();
+        assert_eq!(serialized, fields);
     }
 }
@@ -0,0 +1,826 @@
+use std::{fmt, str::FromStr};
+
+use crate::domain::prelude::*;
+
+/// Maximum length of an expression, in bytes.
+const MAX_EXPR_LEN: usize = 4096;
+
+/// Maximum nesting depth of an expression.
+const MAX_DEPTH: usize = 32;
+
+/// A filter expression over the transaction fields, such as
+/// `amount_usd_cents > 50000 && category in ["travel", "online_retail"]`.
+///
+/// The expression is parsed and type-checked once, when the subscription is
+/// made, and then evaluated against every transaction.
+///
+/// Grammar, from the lowest to the highest precedence:
+///
+/// - `a || b`, `a && b`, `!a` and parentheses
+/// - `field == value`, `!=`, `<`, `<=`, `>`, `>=` (ordering on numbers only)
+/// - `field in [values]`, `field not in [values]`
+/// - `field` alone, for boolean fields
+///
+/// Values are numbers, double-quoted strings, `true` and `false`. Categories
+/// and card networks are given as strings and checked against their names.
+/// The optional `source` only equals a value when present, so `!=` matches
+/// it when absent. The fraud label cannot be filtered on, as it would then
+/// be revealed to the clients.
+///
+#[derive(Debug, Clone)]
+pub struct Expr(Node);
+
+impl Expr {
+    /// Checks whether the transaction satisfies the expression.
+    pub fn matches(&self, transaction: &Transaction) -> bool {
+        self.0.eval(transaction)
+    }
+}
+
+impl FromStr for Expr {
+    type Err = ExprError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() > MAX_EXPR_LEN {
+            return Err(ExprError {
+                position: MAX_EXPR_LEN,
+                message: format!("expression is longer than {} bytes", MAX_EXPR_LEN),
+            });
+        }
+
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+            end: s.len(),
+            depth: 0,
+        };
+        let node = parser.or()?;
+        match parser.peek() {
+            None => Ok(Self(node)),
+            Some(token) => Err(ExprError::at(
+                token.position,
+                format!("unexpected {}", token.kind),
+            )),
+        }
+    }
+}
+
+/// An invalid expression, with the byte offset of the problem.
+#[derive(Debug, Clone)]
+pub struct ExprError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl ExprError {
+    fn at(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            position,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+impl<'de> serde::Deserialize<'de> for Expr {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Type of a field or value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Number,
+    String,
+    Bool,
+    Category,
+    CardNetwork,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Number => "number",
+            Self::String => "string",
+            Self::Bool => "boolean",
+            Self::Category => "category",
+            Self::CardNetwork => "card network",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The transaction fields available in expressions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Id,
+    Timestamp,
+    CcNumber,
+    Category,
+    AmountUsdCents,
+    City,
+    CountryIso,
+    Latitude,
+    Longitude,
+    IsOnline,
+    Source,
+    CardNetwork,
+}
+
+impl Field {
+    const ALL: [(&'static str, Self); 12] = [
+        ("id", Self::Id),
+        ("timestamp", Self::Timestamp),
+        ("cc_number", Self::CcNumber),
+        ("category", Self::Category),
+        ("amount_usd_cents", Self::AmountUsdCents),
+        ("city", Self::City),
+        ("country_iso", Self::CountryIso),
+        ("latitude", Self::Latitude),
+        ("longitude", Self::Longitude),
+        ("is_online", Self::IsOnline),
+        ("source", Self::Source),
+        ("card_network", Self::CardNetwork),
+    ];
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .find(|(field_name, _)| *field_name == name)
+            .map(|(_, field)| *field)
+    }
+
+    fn ty(&self) -> Type {
+        match self {
+            Self::Id | Self::Timestamp | Self::CcNumber | Self::City => Type::String,
+            Self::CountryIso | Self::Source => Type::String,
+            Self::AmountUsdCents | Self::Latitude | Self::Longitude => Type::Number,
+            Self::IsOnline => Type::Bool,
+            Self::Category => Type::Category,
+            Self::CardNetwork => Type::CardNetwork,
+        }
+    }
+
+    /// Returns the value of the field, `None` for absent optional fields.
+    fn value<'a>(&self, transaction: &'a Transaction) -> Option<Value<'a>> {
+        let value = match self {
+            Self::Id => Value::String(&transaction.id),
+            Self::Timestamp => Value::String(&transaction.timestamp),
+            Self::CcNumber => Value::String(&transaction.cc_number),
+            Self::Category => Value::Category(transaction.category),
+            Self::AmountUsdCents => Value::Number(transaction.amount_usd_cents as f64),
+            Self::City => Value::String(&transaction.location.city),
+            Self::CountryIso => Value::String(&transaction.location.country_iso),
+            Self::Latitude => Value::Number(transaction.location.latitude),
+            Self::Longitude => Value::Number(transaction.location.longitude),
+            Self::IsOnline => Value::Bool(transaction.is_online),
+            Self::Source => Value::String(transaction.source.as_deref()?),
+            Self::CardNetwork => Value::CardNetwork(transaction.card_network()),
+        };
+        Some(value)
+    }
+}
+
+/// A typed value, borrowed from the transaction or owned by the expression.
+#[derive(Debug, Clone, PartialEq)]
+enum Value<'a> {
+    Number(f64),
+    String(&'a str),
+    Bool(bool),
+    Category(TransactionCategory),
+    CardNetwork(CardNetwork),
+}
+
+/// A literal of the expression, checked against the type of its field.
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Number(f64),
+    String(String),
+    Bool(bool),
+    Category(TransactionCategory),
+    CardNetwork(CardNetwork),
+}
+
+impl Literal {
+    fn as_value(&self) -> Value<'_> {
+        match self {
+            Self::Number(n) => Value::Number(*n),
+            Self::String(s) => Value::String(s),
+            Self::Bool(b) => Value::Bool(*b),
+            Self::Category(c) => Value::Category(*c),
+            Self::CardNetwork(n) => Value::CardNetwork(*n),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+    Is(Field),
+    Compare(Field, CmpOp, Literal),
+    In(Field, Vec<Literal>),
+}
+
+impl Node {
+    fn eval(&self, transaction: &Transaction) -> bool {
+        match self {
+            Self::And(a, b) => a.eval(transaction) && b.eval(transaction),
+            Self::Or(a, b) => a.eval(transaction) || b.eval(transaction),
+            Self::Not(a) => !a.eval(transaction),
+            Self::Is(field) => field.value(transaction) == Some(Value::Bool(true)),
+            Self::Compare(field, op, literal) => {
+                let Some(value) = field.value(transaction) else {
+                    return *op == CmpOp::Ne;
+                };
+                let literal = literal.as_value();
+                match (op, &value, &literal) {
+                    (CmpOp::Eq, ..) => value == literal,
+                    (CmpOp::Ne, ..) => value != literal,
+                    (op, Value::Number(a), Value::Number(b)) => match op {
+                        CmpOp::Lt => a < b,
+                        CmpOp::Le => a <= b,
+                        CmpOp::Gt => a > b,
+                        _ => a >= b,
+                    },
+                    _ => false,
+                }
+            }
+            Self::In(field, literals) => field
+                .value(transaction)
+                .is_some_and(|value| literals.iter().any(|l| l.as_value() == value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    Ident(String),
+    String(String),
+    Number(f64),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    And,
+    Or,
+    Not,
+    Cmp(CmpOp),
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Ident(name) => write!(f, "`{}`", name),
+            Self::String(s) => write!(f, "string \"{}\"", s),
+            Self::Number(n) => write!(f, "number {}", n),
+            Self::LParen => f.write_str("`(`"),
+            Self::RParen => f.write_str("`)`"),
+            Self::LBracket => f.write_str("`[`"),
+            Self::RBracket => f.write_str("`]`"),
+            Self::Comma => f.write_str("`,`"),
+            Self::And => f.write_str("`&&`"),
+            Self::Or => f.write_str("`||`"),
+            Self::Not => f.write_str("`!`"),
+            Self::Cmp(op) => {
+                let op = match op {
+                    CmpOp::Eq => "==",
+                    CmpOp::Ne => "!=",
+                    CmpOp::Lt => "<",
+                    CmpOp::Le => "<=",
+                    CmpOp::Gt => ">",
+                    CmpOp::Ge => ">=",
+                };
+                write!(f, "`{}`", op)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    position: usize,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, ExprError> {
+    let bytes = s.as_bytes();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let start = pos;
+        let next = bytes.get(pos + 1).copied();
+        let kind = match bytes[pos] {
+            c if c.is_ascii_whitespace() => {
+                pos += 1;
+                continue;
+            }
+            b'(' => TokenKind::LParen,
+            b')' => TokenKind::RParen,
+            b'[' => TokenKind::LBracket,
+            b']' => TokenKind::RBracket,
+            b',' => TokenKind::Comma,
+            b'&' if next == Some(b'&') => TokenKind::And,
+            b'|' if next == Some(b'|') => TokenKind::Or,
+            b'=' if next == Some(b'=') => TokenKind::Cmp(CmpOp::Eq),
+            b'!' if next == Some(b'=') => TokenKind::Cmp(CmpOp::Ne),
+            b'<' if next == Some(b'=') => TokenKind::Cmp(CmpOp::Le),
+            b'>' if next == Some(b'=') => TokenKind::Cmp(CmpOp::Ge),
+            b'!' => TokenKind::Not,
+            b'<' => TokenKind::Cmp(CmpOp::Lt),
+            b'>' => TokenKind::Cmp(CmpOp::Gt),
+            b'"' => {
+                let mut value = String::new();
+                let mut chars = s[pos + 1..].char_indices();
+                loop {
+                    match chars.next() {
+                        None => return Err(ExprError::at(start, "unterminated string")),
+                        Some((i, '"')) => {
+                            pos += i + 2;
+                            break;
+                        }
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c @ ('"' | '\\'))) => value.push(c),
+                            _ => return Err(ExprError::at(start, "invalid escape in string")),
+                        },
+                        Some((_, c)) => value.push(c),
+                    }
+                }
+                tokens.push(Token {
+                    kind: TokenKind::String(value),
+                    position: start,
+                });
+                continue;
+            }
+            c if c.is_ascii_digit() || (c == b'-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                pos += 1;
+                while pos < bytes.len() && (bytes[pos].is_ascii_digit() || bytes[pos] == b'.') {
+                    pos += 1;
+                }
+                let number = &s[start..pos];
+                let number = number
+                    .parse()
+                    .map_err(|_| ExprError::at(start, format!("invalid number {}", number)))?;
+                tokens.push(Token {
+                    kind: TokenKind::Number(number),
+                    position: start,
+                });
+                continue;
+            }
+            c if c.is_ascii_alphabetic() || c == b'_' => {
+                while pos < bytes.len()
+                    && (bytes[pos].is_ascii_alphanumeric() || bytes[pos] == b'_')
+                {
+                    pos += 1;
+                }
+                tokens.push(Token {
+                    kind: TokenKind::Ident(s[start..pos].to_string()),
+                    position: start,
+                });
+                continue;
+            }
+            _ => {
+                let c = s[pos..].chars().next().unwrap_or_default();
+                return Err(ExprError::at(
+                    start,
+                    format!("unexpected character `{}`", c),
+                ));
+            }
+        };
+
+        pos += match kind {
+            TokenKind::And | TokenKind::Or => 2,
+            TokenKind::Cmp(op) if !matches!(op, CmpOp::Lt | CmpOp::Gt) => 2,
+            _ => 1,
+        };
+        tokens.push(Token {
+            kind,
+            position: start,
+        });
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser, type-checking the predicates as they are parsed.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    end: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token, ExprError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| ExprError::at(self.end, "unexpected end of expression"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, kind: &TokenKind) -> bool {
+        if self.peek().is_some_and(|token| token.kind == *kind) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind) -> Result<(), ExprError> {
+        let token = self.next()?;
+        if token.kind == kind {
+            Ok(())
+        } else {
+            Err(ExprError::at(
+                token.position,
+                format!("expected {}, found {}", kind, token.kind),
+            ))
+        }
+    }
+
+    fn or(&mut self) -> Result<Node, ExprError> {
+        let mut node = self.and()?;
+        while self.eat(&TokenKind::Or) {
+            node = Node::Or(Box::new(node), Box::new(self.and()?));
+        }
+        Ok(node)
+    }
+
+    fn and(&mut self) -> Result<Node, ExprError> {
+        let mut node = self.unary()?;
+        while self.eat(&TokenKind::And) {
+            node = Node::And(Box::new(node), Box::new(self.unary()?));
+        }
+        Ok(node)
+    }
+
+    fn unary(&mut self) -> Result<Node, ExprError> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            let position = self.peek().map_or(self.end, |token| token.position);
+            return Err(ExprError::at(position, "expression is nested too deeply"));
+        }
+
+        let node = if self.eat(&TokenKind::Not) {
+            Node::Not(Box::new(self.unary()?))
+        } else if self.eat(&TokenKind::LParen) {
+            let node = self.or()?;
+            self.expect(TokenKind::RParen)?;
+            node
+        } else {
+            self.predicate()?
+        };
+
+        self.depth -= 1;
+        Ok(node)
+    }
+
+    fn predicate(&mut self) -> Result<Node, ExprError> {
+        let token = self.next()?;
+        let TokenKind::Ident(name) = &token.kind else {
+            return Err(ExprError::at(
+                token.position,
+                format!("expected a field, found {}", token.kind),
+            ));
+        };
+        let field = Field::from_name(name).ok_or_else(|| {
+            let fields: Vec<_> = Field::ALL.iter().map(|(name, _)| *name).collect();
+            ExprError::at(
+                token.position,
+                format!(
+                    "unknown field `{}`, expected one of {}",
+                    name,
+                    fields.join(", ")
+                ),
+            )
+        })?;
+
+        match self.peek().map(|token| token.kind.clone()) {
+            Some(TokenKind::Cmp(op)) => {
+                let op_position = self.next()?.position;
+                if !matches!(op, CmpOp::Eq | CmpOp::Ne) && field.ty() != Type::Number {
+                    return Err(ExprError::at(
+                        op_position,
+                        format!(
+                            "{} only applies to numbers, `{}` is a {}",
+                            TokenKind::Cmp(op),
+                            name,
+                            field.ty()
+                        ),
+                    ));
+                }
+                let literal = self.literal(field, name)?;
+                Ok(Node::Compare(field, op, literal))
+            }
+            Some(TokenKind::Ident(keyword)) if keyword == "in" => {
+                self.pos += 1;
+                Ok(Node::In(field, self.list(field, name)?))
+            }
+            Some(TokenKind::Ident(keyword)) if keyword == "not" => {
+                self.pos += 1;
+                self.expect(TokenKind::Ident("in".to_string()))?;
+                Ok(Node::Not(Box::new(Node::In(
+                    field,
+                    self.list(field, name)?,
+                ))))
+            }
+            _ if field.ty() == Type::Bool => Ok(Node::Is(field)),
+            _ => Err(ExprError::at(
+                token.position,
+                format!(
+                    "`{}` is a {}, expected a comparison or `in` after it",
+                    name,
+                    field.ty()
+                ),
+            )),
+        }
+    }
+
+    fn list(&mut self, field: Field, name: &str) -> Result<Vec<Literal>, ExprError> {
+        self.expect(TokenKind::LBracket)?;
+        let mut literals = Vec::new();
+        if self.eat(&TokenKind::RBracket) {
+            return Ok(literals);
+        }
+        loop {
+            literals.push(self.literal(field, name)?);
+            if self.eat(&TokenKind::RBracket) {
+                return Ok(literals);
+            }
+            self.expect(TokenKind::Comma)?;
+        }
+    }
+
+    /// Parses a literal and checks it against the type of the field.
+    fn literal(&mut self, field: Field, name: &str) -> Result<Literal, ExprError> {
+        let token = self.next()?;
+        let mismatch = || {
+            ExprError::at(
+                token.position,
+                format!(
+                    "`{}` is a {}, it cannot be compared with {}",
+                    name,
+                    field.ty(),
+                    token.kind
+                ),
+            )
+        };
+
+        match (field.ty(), &token.kind) {
+            (Type::Number, TokenKind::Number(n)) => Ok(Literal::Number(*n)),
+            (Type::String, TokenKind::String(s)) => Ok(Literal::String(s.clone())),
+            (Type::Bool, TokenKind::Ident(b)) if b == "true" || b == "false" => {
+                Ok(Literal::Bool(b == "true"))
+            }
+            (Type::Category, TokenKind::String(s)) => named(s)
+                .map(Literal::Category)
+                .ok_or_else(|| unknown_name(token.position, "category", s)),
+            (Type::CardNetwork, TokenKind::String(s)) => named(s)
+                .map(Literal::CardNetwork)
+                .ok_or_else(|| unknown_name(token.position, "card network", s)),
+            _ => Err(mismatch()),
+        }
+    }
+}
+
+/// Parses an enum from its serialized name.
+fn named<T: serde::de::DeserializeOwned>(name: &str) -> Option<T> {
+    serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+}
+
+fn unknown_name(position: usize, what: &str, name: &str) -> ExprError {
+    ExprError::at(position, format!("unknown {} \"{}\"", what, name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::transactions::Location;
+
+    /// An online travel purchase in Paris.
+    fn transaction() -> Transaction {
+        Transaction {
+            cc_number: "4111111111111111".to_string(),
+            category: TransactionCategory::Travel,
+            amount_usd_cents: 12_345,
+            location: Location {
+                city: "Paris".to_string(),
+                country_iso: "FR".to_string(),
+                latitude: 48.856613,
+                longitude: 2.352222,
+            },
+            is_online: true,
+            ..Transaction::fixture()
+        }
+    }
+
+    fn matches(expr: &str, transaction: &Transaction) -> bool {
+        match expr.parse::<Expr>() {
+            Ok(expr) => expr.matches(transaction),
+            Err(e) => panic!("{}: {}", expr, e),
+        }
+    }
+
+    fn error(expr: &str) -> ExprError {
+        match expr.parse::<Expr>() {
+            Ok(_) => panic!("{} should not parse", expr),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let transaction = transaction();
+        assert!(matches(
+            "is_online || amount_usd_cents > 100000 && country_iso == \"US\"",
+            &transaction
+        ));
+        assert!(!matches(
+            "(is_online || amount_usd_cents > 100000) && country_iso == \"US\"",
+            &transaction
+        ));
+    }
+
+    #[test]
+    fn not_binds_tighter_than_and_or() {
+        let transaction = transaction();
+        assert!(matches("!is_online || is_online", &transaction));
+        assert!(!matches("!(is_online || is_online)", &transaction));
+        assert!(!matches("!is_online && is_online", &transaction));
+        assert!(matches("!!is_online", &transaction));
+    }
+
+    #[test]
+    fn in_and_not_in() {
+        let transaction = transaction();
+        assert!(matches(
+            "category in [\"grocery\", \"travel\"]",
+            &transaction
+        ));
+        assert!(!matches(
+            "category not in [\"grocery\", \"travel\"]",
+            &transaction
+        ));
+        assert!(matches("card_network in [\"visa\"]", &transaction));
+        assert!(matches("amount_usd_cents in [1, 12345]", &transaction));
+        assert!(!matches("country_iso in []", &transaction));
+        assert!(matches("country_iso not in []", &transaction));
+    }
+
+    #[test]
+    fn in_checks_the_values() {
+        assert!(error("category in [\"travel\", \"shopping\"]")
+            .message
+            .contains("unknown category \"shopping\""));
+        assert!(error("country_iso in [\"FR\", 1]")
+            .message
+            .contains("cannot be compared with number 1"));
+        assert!(error("country_iso not [\"FR\"]")
+            .message
+            .contains("expected `in`"));
+    }
+
+    #[test]
+    fn string_escapes() {
+        let mut transaction = transaction();
+        transaction.location.city = "a\"b\\c".to_string();
+        assert!(matches(r#"city == "a\"b\\c""#, &transaction));
+
+        let e = error(r#"city == "a\nb""#);
+        assert_eq!(e.message, "invalid escape in string");
+        assert_eq!(e.position, 8);
+        assert_eq!(error(r#"city == "Paris"#).message, "unterminated string");
+    }
+
+    #[test]
+    fn negative_and_decimal_numbers() {
+        let transaction = transaction();
+        assert!(matches("latitude > 48.5 && latitude < 48.9", &transaction));
+        assert!(matches("longitude > -2.5", &transaction));
+        assert!(matches("amount_usd_cents >= -1", &transaction));
+        assert!(!matches("longitude <= -0.5", &transaction));
+
+        let e = error("amount_usd_cents > 1.2.3");
+        assert_eq!(e.message, "invalid number 1.2.3");
+        assert_eq!(e.position, 19);
+    }
+
+    #[test]
+    fn unknown_field() {
+        let e = error("is_online && amount > 100");
+        assert_eq!(e.position, 13);
+        assert_eq!(
+            e.message,
+            "unknown field `amount`, expected one of id, timestamp, cc_number, category, \
+             amount_usd_cents, city, country_iso, latitude, longitude, is_online, source, \
+             card_network"
+        );
+    }
+
+    #[test]
+    fn ordering_only_applies_to_numbers() {
+        for expr in [
+            "city < \"Paris\"",
+            "category >= \"travel\"",
+            "is_online > true",
+            "card_network <= \"visa\"",
+        ] {
+            assert!(
+                error(expr).message.contains("only applies to numbers"),
+                "{}",
+                expr
+            );
+        }
+        assert_eq!(
+            error("city < \"Paris\"").message,
+            "`<` only applies to numbers, `city` is a string"
+        );
+        assert!(matches("city != \"London\"", &transaction()));
+    }
+
+    #[test]
+    fn depth_limit() {
+        let nested = |depth: usize| {
+            format!(
+                "{}is_online{}",
+                "(".repeat(depth - 1),
+                ")".repeat(depth - 1)
+            )
+        };
+        assert!(matches(&nested(MAX_DEPTH), &transaction()));
+        assert_eq!(
+            error(&nested(MAX_DEPTH + 1)).message,
+            "expression is nested too deeply"
+        );
+
+        let negated = format!("{}is_online", "!".repeat(MAX_DEPTH));
+        assert_eq!(error(&negated).message, "expression is nested too deeply");
+    }
+
+    #[test]
+    fn absent_optional_fields() {
+        let mut transaction = transaction();
+        assert!(matches("source != \"mock\"", &transaction));
+        assert!(!matches("source == \"mock\"", &transaction));
+        assert!(!matches("source in [\"mock\"]", &transaction));
+        assert!(matches("source not in [\"mock\"]", &transaction));
+
+        transaction.source = Some("mock".to_string());
+        assert!(matches("source == \"mock\"", &transaction));
+        assert!(!matches("source != \"mock\"", &transaction));
+    }
+
+    #[test]
+    fn fraud_label_is_not_filterable() {
+        for expr in ["is_fraud", "is_fraud == false", "!is_fraud"] {
+            let e = error(expr);
+            assert!(
+                e.message.starts_with("unknown field `is_fraud`"),
+                "{}",
+                expr
+            );
+        }
+    }
+
+    #[test]
+    fn trailing_tokens() {
+        assert_eq!(
+            error("is_online is_online").message,
+            "unexpected `is_online`"
+        );
+        assert_eq!(
+            error("is_online &&").message,
+            "unexpected end of expression"
+        );
+    }
+}
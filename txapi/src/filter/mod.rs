@@ -0,0 +1,65 @@
+pub mod expr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::domain::prelude::*;
+
+/// Filter on the transactions sent to a subscriber.
+///
+/// Every criterion is optional, a transaction matches when it satisfies all
+/// of the given ones. List criteria match any of their values.
+///
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TransactionFilter {
+    /// Merchant categories
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub categories: Option<Vec<TransactionCategory>>,
+
+    /// ISO 3166-1 alpha-2 country codes, case insensitive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub countries: Option<Vec<String>>,
+
+    /// Minimum amount in USD cents, inclusive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_amount_usd_cents: Option<u64>,
+
+    /// Maximum amount in USD cents, inclusive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_amount_usd_cents: Option<u64>,
+
+    /// Online or in-person transactions only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub is_online: Option<bool>,
+
+    /// Card networks
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub card_networks: Option<Vec<CardNetwork>>,
+}
+
+impl TransactionFilter {
+    /// Checks whether the transaction satisfies all the criteria.
+    pub fn matches(&self, transaction: &Transaction) -> bool {
+        self.categories
+            .as_ref()
+            .is_none_or(|categories| categories.contains(&transaction.category))
+            && self.countries.as_ref().is_none_or(|countries| {
+                countries
+                    .iter()
+                    .any(|country| country.eq_ignore_ascii_case(&transaction.location.country_iso))
+            })
+            && self
+                .min_amount_usd_cents
+                .is_none_or(|min| transaction.amount_usd_cents >= min)
+            && self
+                .max_amount_usd_cents
+                .is_none_or(|max| transaction.amount_usd_cents <= max)
+            && self
+                .is_online
+                .is_none_or(|is_online| transaction.is_online == is_online)
+            && self
+                .card_networks
+                .as_ref()
+                .is_none_or(|networks| networks.contains(&transaction.card_network()))
+    }
+}
@@ -1,4 +1,5 @@
 pub mod api;
 pub mod core;
 pub mod domain;
+pub mod filter;
 pub mod stream;
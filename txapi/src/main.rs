@@ -1,6 +1,20 @@
-use axum::{routing::get, Router};
+use axum::{
+    routing::{get, post},
+    Router,
+};
+use std::sync::Arc;
 use tokio_util::sync::CancellationToken;
-use txapi::{api, core::prelude::*, stream};
+use txapi::{
+    api,
+    core::{authorization::AuthorizationConfig, config::WsConfig, prelude::*},
+    stream,
+    stream::{
+        eventlog::{EventLog, EventLogConfig},
+        heartbeat::ServerStatus,
+        sequenced::SequencedSender,
+        source::SourceRegistry,
+    },
+};
 
 /// Check if health check mode is requested
 fn is_health_check() -> bool {
@@ -15,13 +29,100 @@ fn is_health_check() -> bool {
 /// The main dependencies are the websocket channel senders, which are used to broadcast
 /// messages to the websocket clients.
 ///
+/// Library users can register their own transaction sources in the registry
+/// before the transactions channel is started.
+///
 async fn init_app_state(cancellation_token: CancellationToken) -> AppState {
-    let (transactions_tx, _) = stream::transactions::channel(cancellation_token.clone()).await;
-    let (heartbeat_tx, _) = stream::heartbeat::channel(cancellation_token.clone()).await;
+    let metrics = Arc::new(Metrics::default());
+    let sources = SourceRegistry::from_env(&metrics);
+
+    // the transactions numbering continues the event log across restarts
+    let event_log = EventLogConfig::from_env().and_then(|config| {
+        EventLog::open(config)
+            .map_err(|e| tracing::error!("Failed to open the event log: {}", e))
+            .ok()
+    });
+    let first_seq = event_log
+        .as_ref()
+        .and_then(|log| log.last_seq().ok().flatten())
+        .map_or(1, |seq| seq + 1);
+
+    let authorizer = AuthorizationConfig::from_env().map(|config| {
+        tracing::info!(
+            "Authorizing the transactions with client {}",
+            config.client_id
+        );
+        Arc::new(Authorizer::new(config, sources.clone()))
+    });
+    let (transactions_tx, transactions_rx) = stream::transactions::channel(
+        sources.clone(),
+        first_seq,
+        authorizer.clone(),
+        cancellation_token.clone(),
+    )
+    .await;
+    if let Some(event_log) = &event_log {
+        event_log.writer(transactions_rx, metrics.clone(), cancellation_token.clone());
+    }
+    let rules = stream::rules::RuleSet::from_env();
+    if !rules.is_empty() {
+        let ids: Vec<_> = rules.ids().collect();
+        tracing::info!("Evaluating rules: {}", ids.join(", "));
+    }
+    let (alerts_tx, _) = SequencedSender::new(100, 0, 1);
+    let (enriched_tx, _) = stream::enrichment::channel(
+        stream::enrichment::EnrichmentConfig::from_env(),
+        rules,
+        alerts_tx.clone(),
+        &transactions_tx,
+        cancellation_token.clone(),
+    )
+    .await;
+    let (scored_tx, _) = stream::scoring::channel(
+        stream::scoring::ScoringConfig::from_env(),
+        &transactions_tx,
+        metrics.clone(),
+        cancellation_token.clone(),
+    )
+    .await;
+    let (stats_tx, _) = stream::stats::channel(
+        stream::stats::windows_from_env(),
+        &transactions_tx,
+        cancellation_token.clone(),
+    )
+    .await;
+    let decisions = Arc::new(DecisionTracker::default());
+    decisions.track(transactions_tx.subscribe(), cancellation_token.clone());
+    let status = ServerStatus::new(transactions_tx.clone(), sources.clone());
+    let (heartbeat_tx, _) =
+        stream::heartbeat::channel(status.clone(), cancellation_token.clone()).await;
+
+    #[cfg(feature = "kafka")]
+    if let Some(config) = stream::kafka::KafkaConfig::from_env() {
+        if config.sink_topic.is_some() {
+            let transactions_rx = transactions_tx.subscribe();
+            if let Err(e) = stream::kafka::sink(config, transactions_rx, cancellation_token.clone())
+            {
+                tracing::error!("Failed to start Kafka sink: {}", e);
+            }
+        }
+    }
 
     AppState {
         heartbeat_tx,
         transactions_tx,
+        enriched_tx,
+        alerts_tx,
+        scored_tx,
+        stats_tx,
+        status,
+        sources,
+        ingest_token: std::env::var("INGEST_TOKEN").ok().filter(|t| !t.is_empty()),
+        event_log,
+        decisions,
+        authorizer,
+        ws_config: WsConfig::from_env(),
+        metrics,
         cancellation_token,
     }
 }
@@ -67,7 +168,14 @@ async fn main() {
 
     let app = Router::new()
         .route("/health", get(api::health::endpoint))
+        .route("/metrics", get(api::metrics::endpoint))
         .route("/ws/v1", get(api::ws::endpoint))
+        .route("/ws/v2", get(api::ws::endpoint_v2))
+        .route("/sse/v1/{channel}", get(api::sse::endpoint))
+        .route("/transactions", post(api::ingest::endpoint))
+        .route("/transactions/log", get(api::eventlog::endpoint))
+        .route("/decisions", get(api::decisions::endpoint))
+        .route("/authorizations", get(api::authorizations::endpoint))
         .with_state(app_state);
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:9999")
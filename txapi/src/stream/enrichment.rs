@@ -0,0 +1,362 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    rules::RuleSet,
+    sequenced::{Sequenced, SequencedSender},
+};
+use crate::domain::prelude::*;
+
+/// Maximum number of countries remembered per card.
+const MAX_COUNTRIES_PER_CARD: usize = 64;
+
+/// Mean radius of the Earth, in kilometers.
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Bounds of the per-card state.
+///
+/// - ENRICHMENT_MAX_CARDS: cards tracked, the least recently seen ones are
+///   forgotten beyond it (default 100000)
+/// - ENRICHMENT_MAX_EVENTS_PER_CARD: transactions kept per card for the
+///   windowed features, the counts saturate beyond it (default 1000)
+///
+#[derive(Debug, Clone)]
+pub struct EnrichmentConfig {
+    pub max_cards: usize,
+    pub max_events_per_card: usize,
+}
+
+impl EnrichmentConfig {
+    pub fn from_env() -> Self {
+        let env_or = |name: &str, default: usize| {
+            std::env::var(name)
+                .ok()
+                .and_then(|s| s.parse::<usize>().ok())
+                .unwrap_or(default)
+                .max(1)
+        };
+
+        Self {
+            max_cards: env_or("ENRICHMENT_MAX_CARDS", 100_000),
+            max_events_per_card: env_or("ENRICHMENT_MAX_EVENTS_PER_CARD", 1000),
+        }
+    }
+}
+
+/// Initialize the enriched transactions channel.
+/// This channel is used to broadcast the transactions with their per-card
+/// features to the websocket clients.
+///
+/// The features are computed by a background task subscribed to the
+/// transactions channel, in the order of the transactions. The rules are
+/// evaluated against every enriched transaction, and the matches are sent
+/// to the alerts channel.
+///
+/// The cancellation_token parameter allows for graceful shutdown of the background task.
+///
+pub async fn channel(
+    config: EnrichmentConfig,
+    rules: RuleSet,
+    alerts_tx: SequencedSender<Alert>,
+    transactions_tx: &SequencedSender<Transaction>,
+    cancellation_token: CancellationToken,
+) -> (
+    SequencedSender<EnrichedTransaction>,
+    broadcast::Receiver<Sequenced<EnrichedTransaction>>,
+) {
+    let (tx, rx) = SequencedSender::new(100, 0, 1);
+
+    let mut transactions_rx = transactions_tx.subscribe();
+    let tx_clone = tx.clone();
+    tokio::spawn(async move {
+        let mut enricher = Enricher::new(config);
+        loop {
+            let transaction = tokio::select! {
+                _ = cancellation_token.cancelled() => break,
+                transaction = transactions_rx.recv() => transaction,
+            };
+
+            match transaction {
+                Ok(transaction) => {
+                    let enriched = enricher.enrich(transaction.data);
+                    for alert in rules.evaluate(&enriched, &enricher) {
+                        tracing::debug!("Rule {} matched: {}", alert.rule_id, alert.reason);
+                        alerts_tx.send(alert);
+                    }
+                    tx_clone.send(enriched);
+                }
+                Err(RecvError::Lagged(n)) => {
+                    tracing::warn!("Enrichment lagged, {} transactions not enriched", n)
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+        tracing::info!("Enrichment shutting down gracefully");
+    });
+    (tx, rx)
+}
+
+/// History of a card.
+#[derive(Debug, Default)]
+struct CardState {
+    /// Time and amount of the recent transactions, oldest first
+    events: VecDeque<(DateTime<Utc>, u64)>,
+    /// Time, position and country of the last transaction
+    previous: Option<(DateTime<Utc>, f64, f64, String)>,
+    categories: HashSet<TransactionCategory>,
+    countries: Vec<String>,
+    count: u64,
+    amount_usd_cents: u64,
+    /// Recency rank, for the eviction of the least recently seen cards
+    touched: u64,
+}
+
+/// Computes the per-card features, keeping a bounded history per card.
+///
+/// Features are based on the timestamps of the transactions, so that
+/// replayed datasets get the same features as in real time.
+pub struct Enricher {
+    config: EnrichmentConfig,
+    cards: HashMap<String, CardState>,
+    recency: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl Enricher {
+    pub fn new(config: EnrichmentConfig) -> Self {
+        Self {
+            config,
+            cards: HashMap::new(),
+            recency: BTreeMap::new(),
+            clock: 0,
+        }
+    }
+
+    /// Computes the features of the transaction and records it in the
+    /// history of its card.
+    pub fn enrich(&mut self, transaction: Transaction) -> EnrichedTransaction {
+        // the sources emit valid timestamps, see `Transaction::validate`
+        let at = match DateTime::parse_from_rfc3339(&transaction.timestamp) {
+            Ok(at) => at.with_timezone(&Utc),
+            Err(e) => {
+                tracing::warn!(
+                    "Invalid timestamp {:?} of transaction {}, enriching it as of now: {}",
+                    transaction.timestamp,
+                    transaction.id,
+                    e
+                );
+                Utc::now()
+            }
+        };
+        let features = self.record(&transaction, at);
+        EnrichedTransaction {
+            transaction,
+            features,
+        }
+    }
+
+    fn record(&mut self, transaction: &Transaction, at: DateTime<Utc>) -> CardFeatures {
+        self.clock += 1;
+        let clock = self.clock;
+
+        match self.cards.get(&transaction.cc_number) {
+            Some(card) => {
+                self.recency.remove(&card.touched);
+            }
+            None if self.cards.len() >= self.config.max_cards => {
+                if let Some((_, evicted)) = self.recency.pop_first() {
+                    self.cards.remove(&evicted);
+                }
+            }
+            None => {}
+        }
+        let card = self.cards.entry(transaction.cc_number.clone()).or_default();
+        card.touched = clock;
+        self.recency.insert(clock, transaction.cc_number.clone());
+
+        let location = &transaction.location;
+        let mut features = CardFeatures {
+            first_in_category: !card.categories.contains(&transaction.category),
+            first_in_country: !card.countries.contains(&location.country_iso),
+            avg_amount_usd_cents: (card.count > 0)
+                .then(|| card.amount_usd_cents as f64 / card.count as f64),
+            ..Default::default()
+        };
+        if let Some((previous_at, latitude, longitude, country)) = &card.previous {
+            features.secs_since_previous =
+                Some((at - *previous_at).num_milliseconds() as f64 / 1000.0);
+            features.km_from_previous = Some(haversine_km(
+                (*latitude, *longitude),
+                (location.latitude, location.longitude),
+            ));
+            features.previous_country_iso = Some(country.clone());
+        }
+
+        // windowed features, over the history kept for the last 7 days
+        card.events.push_back((at, transaction.amount_usd_cents));
+        if card.events.len() > self.config.max_events_per_card {
+            card.events.pop_front();
+        }
+        while card
+            .events
+            .front()
+            .is_some_and(|(event_at, _)| at - *event_at > TimeDelta::days(7))
+        {
+            card.events.pop_front();
+        }
+        for (event_at, amount) in &card.events {
+            let age = at - *event_at;
+            if age <= TimeDelta::hours(1) {
+                features.count_1h += 1;
+                features.amount_1h_usd_cents += amount;
+            }
+            if age <= TimeDelta::hours(24) {
+                features.count_24h += 1;
+                features.amount_24h_usd_cents += amount;
+            }
+            features.count_7d += 1;
+            features.amount_7d_usd_cents += amount;
+        }
+
+        card.previous = Some((
+            at,
+            location.latitude,
+            location.longitude,
+            location.country_iso.clone(),
+        ));
+        card.categories.insert(transaction.category);
+        if features.first_in_country && card.countries.len() < MAX_COUNTRIES_PER_CARD {
+            card.countries.push(location.country_iso.clone());
+        }
+        card.count += 1;
+        card.amount_usd_cents += transaction.amount_usd_cents;
+
+        features
+    }
+
+    /// Returns the number of transactions of the card since the given time,
+    /// within the kept history.
+    pub fn count_since(&self, cc_number: &str, since: DateTime<Utc>) -> u64 {
+        self.cards.get(cc_number).map_or(0, |card| {
+            card.events.iter().filter(|(at, _)| *at >= since).count() as u64
+        })
+    }
+
+    /// Returns the number of transactions recorded for the card.
+    pub fn card_count(&self, cc_number: &str) -> u64 {
+        self.cards.get(cc_number).map_or(0, |card| card.count)
+    }
+}
+
+/// Great-circle distance between two (latitude, longitude) points.
+fn haversine_km(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (lat1, lon1) = (from.0.to_radians(), from.1.to_radians());
+    let (lat2, lon2) = (to.0.to_radians(), to.1.to_radians());
+
+    let a = ((lat2 - lat1) / 2.0).sin().powi(2)
+        + lat1.cos() * lat2.cos() * ((lon2 - lon1) / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::transactions::Location;
+
+    /// A transaction of the card in the city, `secs` after a fixed time.
+    fn transaction(card: &str, city: (&str, &str, f64, f64), secs: i64) -> Transaction {
+        let start = DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z").unwrap();
+        let (city, country_iso, latitude, longitude) = city;
+        Transaction {
+            cc_number: card.to_string(),
+            amount_usd_cents: 1000,
+            category: TransactionCategory::Grocery,
+            timestamp: (start + TimeDelta::seconds(secs)).to_rfc3339(),
+            location: Location {
+                city: city.to_string(),
+                country_iso: country_iso.to_string(),
+                latitude,
+                longitude,
+            },
+            ..Transaction::simple_mock()
+        }
+    }
+
+    const PARIS: (&str, &str, f64, f64) = ("Paris", "FR", 48.8566, 2.3522);
+    const LONDON: (&str, &str, f64, f64) = ("London", "GB", 51.5074, -0.1278);
+
+    fn enricher(max_cards: usize) -> Enricher {
+        Enricher::new(EnrichmentConfig {
+            max_cards,
+            max_events_per_card: 100,
+        })
+    }
+
+    #[test]
+    fn windows_include_the_transaction_and_its_recent_history() {
+        let mut enricher = enricher(10);
+        let hour = 3600;
+        // 8 days, 2 days, 30 minutes and 1 second before the last one
+        for secs in [0, 6 * 24 * hour, 8 * 24 * hour - 1800, 8 * 24 * hour - 1] {
+            enricher.enrich(transaction("a", PARIS, secs));
+        }
+        let features = enricher
+            .enrich(transaction("a", PARIS, 8 * 24 * hour))
+            .features;
+
+        assert_eq!(features.count_1h, 3);
+        assert_eq!(features.amount_1h_usd_cents, 3000);
+        assert_eq!(features.count_24h, 3);
+        assert_eq!(features.count_7d, 4);
+        assert_eq!(features.amount_7d_usd_cents, 4000);
+        assert_eq!(features.avg_amount_usd_cents, Some(1000.0));
+    }
+
+    #[test]
+    fn previous_transaction_features() {
+        let mut enricher = enricher(10);
+        let first = enricher.enrich(transaction("a", PARIS, 0)).features;
+        assert_eq!(first.secs_since_previous, None);
+        assert_eq!(first.km_from_previous, None);
+        assert_eq!(first.previous_country_iso, None);
+        assert!(first.first_in_country && first.first_in_category);
+        assert_eq!(first.avg_amount_usd_cents, None);
+
+        let second = enricher.enrich(transaction("a", LONDON, 90)).features;
+        assert_eq!(second.secs_since_previous, Some(90.0));
+        let km = second.km_from_previous.unwrap();
+        assert!((km - 343.5).abs() < 1.0, "{} km", km);
+        assert_eq!(second.previous_country_iso.as_deref(), Some("FR"));
+        assert!(second.first_in_country);
+        assert!(!second.first_in_category);
+
+        let back = enricher.enrich(transaction("a", PARIS, 100)).features;
+        assert!(!back.first_in_country);
+        assert_eq!(back.previous_country_iso.as_deref(), Some("GB"));
+
+        // other cards have their own history
+        let other = enricher.enrich(transaction("b", PARIS, 100)).features;
+        assert!(other.first_in_country);
+        assert_eq!(other.count_7d, 1);
+    }
+
+    #[test]
+    fn least_recently_seen_cards_are_forgotten() {
+        let mut enricher = enricher(2);
+        enricher.enrich(transaction("a", PARIS, 0));
+        enricher.enrich(transaction("b", PARIS, 1));
+        enricher.enrich(transaction("a", PARIS, 2));
+        // evicts b, the least recently seen
+        enricher.enrich(transaction("c", PARIS, 3));
+
+        assert_eq!(enricher.card_count("a"), 2);
+        assert_eq!(enricher.card_count("b"), 0);
+        assert_eq!(enricher.card_count("c"), 1);
+        let features = enricher.enrich(transaction("b", PARIS, 4)).features;
+        assert_eq!(features.count_7d, 1);
+        assert_eq!(features.secs_since_previous, None);
+        assert_eq!(enricher.card_count("a"), 0);
+    }
+}
@@ -0,0 +1,611 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+use super::sequenced::Sequenced;
+use crate::{core::metrics::Metrics, domain::prelude::*};
+
+/// File extension of the log segments.
+const SEGMENT_EXTENSION: &str = "ndjson";
+
+/// Interval at which the retention is enforced while the log is idle.
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Configuration of the durable event log.
+///
+/// The event log is enabled by setting the EVENT_LOG_DIR environment
+/// variable, the remaining settings are optional:
+///
+/// - EVENT_LOG_SEGMENT_BYTES: size after which a new segment is started (default 64 MiB)
+/// - EVENT_LOG_MAX_BYTES: total size after which the oldest segments are deleted (default 1 GiB)
+/// - EVENT_LOG_MAX_AGE_SECS: age after which segments are deleted (default 7 days, 0 disables)
+///
+#[derive(Debug, Clone)]
+pub struct EventLogConfig {
+    pub dir: PathBuf,
+    pub segment_bytes: u64,
+    pub max_bytes: u64,
+    pub max_age: Option<Duration>,
+}
+
+impl EventLogConfig {
+    /// Reads the event log configuration from the environment.
+    ///
+    /// Returns `None` when EVENT_LOG_DIR is not set.
+    pub fn from_env() -> Option<Self> {
+        let env_or = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+
+        let max_age_secs = env_or("EVENT_LOG_MAX_AGE_SECS", 7 * 24 * 3600);
+        Some(Self {
+            dir: PathBuf::from(std::env::var("EVENT_LOG_DIR").ok()?),
+            segment_bytes: env_or("EVENT_LOG_SEGMENT_BYTES", 64 * 1024 * 1024),
+            max_bytes: env_or("EVENT_LOG_MAX_BYTES", 1024 * 1024 * 1024),
+            max_age: Some(Duration::from_secs(max_age_secs)).filter(|age| !age.is_zero()),
+        })
+    }
+}
+
+/// A transaction as stored in the event log.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LogRecord {
+    pub seq: u64,
+    pub logged_at: DateTime<Utc>,
+    pub data: Transaction,
+}
+
+/// A range of transactions that could not be logged, because the writer
+/// fell behind the broadcast channel.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct LogGap {
+    pub gap: SeqRange,
+    pub logged_at: DateTime<Utc>,
+}
+
+/// An inclusive range of sequence numbers.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy)]
+pub struct SeqRange {
+    pub from_seq: u64,
+    pub to_seq: u64,
+}
+
+/// An entry of the event log: a transaction, or a gap marker recording the
+/// transactions missing from the log.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum LogEntry {
+    Record(LogRecord),
+    Gap(LogGap),
+}
+
+impl LogEntry {
+    /// Returns the first sequence number covered by the entry.
+    pub fn first_seq(&self) -> u64 {
+        match self {
+            LogEntry::Record(record) => record.seq,
+            LogEntry::Gap(gap) => gap.gap.from_seq,
+        }
+    }
+
+    /// Returns the last sequence number covered by the entry.
+    pub fn last_seq(&self) -> u64 {
+        match self {
+            LogEntry::Record(record) => record.seq,
+            LogEntry::Gap(gap) => gap.gap.to_seq,
+        }
+    }
+
+    fn logged_at(&self) -> DateTime<Utc> {
+        match self {
+            LogEntry::Record(record) => record.logged_at,
+            LogEntry::Gap(gap) => gap.logged_at,
+        }
+    }
+}
+
+/// Query over the event log, all bounds are inclusive.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct LogQuery {
+    pub from_seq: Option<u64>,
+    pub to_seq: Option<u64>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl LogQuery {
+    /// Gap markers match when they overlap the requested sequence range.
+    fn matches(&self, entry: &LogEntry) -> bool {
+        self.from_seq.is_none_or(|from| entry.last_seq() >= from)
+            && self.to_seq.is_none_or(|to| entry.first_seq() <= to)
+            && self.since.is_none_or(|since| entry.logged_at() >= since)
+            && self.until.is_none_or(|until| entry.logged_at() <= until)
+    }
+}
+
+/// Append-only, segment-rotated log of the broadcast transactions.
+///
+/// Records are stored as newline delimited JSON, in segment files named
+/// after the sequence number of their first entry, so that the segments
+/// holding a sequence range can be found without opening them. The
+/// transactions missed by the writer are recorded as gap markers.
+///
+/// The log is cheap to clone, clones only share the configuration: there
+/// must be a single writer, started with `EventLog::writer`, while any
+/// number of clones can be queried.
+///
+#[derive(Debug, Clone)]
+pub struct EventLog {
+    config: EventLogConfig,
+}
+
+impl EventLog {
+    /// Opens the log directory, creating it if needed.
+    pub fn open(config: EventLogConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        Ok(Self { config })
+    }
+
+    /// Returns the sequence number of the last logged transaction.
+    ///
+    /// Used to continue the numbering after a restart. Segments left empty,
+    /// e.g. by a crash right after a rotation, are skipped.
+    pub fn last_seq(&self) -> io::Result<Option<u64>> {
+        for (_, path) in self.segments()?.into_iter().rev() {
+            let last = BufReader::new(File::open(path)?)
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|line| serde_json::from_str::<LogEntry>(&line).ok())
+                .last();
+            if let Some(entry) = last {
+                return Ok(Some(entry.last_seq()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the entries matching the query, in sequence order, up to `limit`.
+    pub fn query(&self, query: &LogQuery, limit: usize) -> io::Result<Vec<LogEntry>> {
+        let segments = self.segments()?;
+        let mut entries = Vec::new();
+
+        for (idx, (first_seq, path)) in segments.iter().enumerate() {
+            // skip the segments entirely before or after the requested range
+            let next_first_seq = segments.get(idx + 1).map(|(seq, _)| *seq);
+            if query
+                .from_seq
+                .is_some_and(|from| next_first_seq.is_some_and(|next| next <= from))
+            {
+                continue;
+            }
+            if query.to_seq.is_some_and(|to| *first_seq > to) {
+                break;
+            }
+
+            let file = match File::open(path) {
+                Ok(file) => file,
+                // deleted by the retention in the meantime
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                // a partially written last line is skipped
+                let Ok(entry) = serde_json::from_str::<LogEntry>(&line) else {
+                    continue;
+                };
+                if query.to_seq.is_some_and(|to| entry.first_seq() > to) {
+                    return Ok(entries);
+                }
+                if query.matches(&entry) {
+                    entries.push(entry);
+                    if entries.len() >= limit {
+                        return Ok(entries);
+                    }
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Spawns the writer, which appends every transaction received from the
+    /// channel to the log.
+    ///
+    /// The files are written on a dedicated thread, fed by a background task
+    /// that stops on cancellation. The transactions dropped when the task
+    /// falls behind the channel are counted, and a gap marker is logged in
+    /// their place.
+    ///
+    /// The retention is enforced when the writer starts, on rotation, and
+    /// every `RETENTION_INTERVAL`, so that an idle log still ages out. The
+    /// segments are synced to disk on rotation and when the writer stops.
+    pub fn writer(
+        &self,
+        mut transactions_rx: broadcast::Receiver<Sequenced<Transaction>>,
+        metrics: Arc<Metrics>,
+        cancellation_token: CancellationToken,
+    ) {
+        let (tx, rx) = mpsc::channel::<LogEntry>();
+
+        let config = self.config.clone();
+        std::thread::spawn(move || {
+            let mut writer = SegmentWriter::new(config);
+            let mut retained_at = None;
+            loop {
+                if retained_at.is_none_or(|at: Instant| at.elapsed() >= RETENTION_INTERVAL) {
+                    if let Err(e) = writer.enforce_retention() {
+                        tracing::error!("Failed to enforce the event log retention: {}", e);
+                    }
+                    retained_at = Some(Instant::now());
+                }
+
+                let entry = match rx.recv_timeout(RETENTION_INTERVAL) {
+                    Ok(entry) => entry,
+                    Err(RecvTimeoutError::Timeout) => continue,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                };
+                if let Err(e) = writer.append(&entry) {
+                    tracing::error!(
+                        "Failed to append sequence number {} to the event log: {}",
+                        entry.first_seq(),
+                        e
+                    );
+                }
+            }
+            if let Err(e) = writer.sync() {
+                tracing::error!("Failed to sync the event log: {}", e);
+            }
+            tracing::info!("Event log writer shutting down gracefully");
+        });
+
+        tokio::spawn(async move {
+            // transactions dropped since the last one received
+            let mut dropped = 0;
+            loop {
+                let transaction = tokio::select! {
+                    _ = cancellation_token.cancelled() => break,
+                    transaction = transactions_rx.recv() => transaction,
+                };
+
+                match transaction {
+                    Ok(transaction) => {
+                        if dropped > 0 {
+                            let gap = LogGap {
+                                gap: SeqRange {
+                                    from_seq: transaction.seq.saturating_sub(dropped),
+                                    to_seq: transaction.seq - 1,
+                                },
+                                logged_at: Utc::now(),
+                            };
+                            dropped = 0;
+                            if tx.send(LogEntry::Gap(gap)).is_err() {
+                                break;
+                            }
+                        }
+                        let record = LogRecord {
+                            seq: transaction.seq,
+                            logged_at: Utc::now(),
+                            data: transaction.data,
+                        };
+                        if tx.send(LogEntry::Record(record)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(RecvError::Lagged(n)) => {
+                        tracing::error!("Event log lagged, {} transactions not logged", n);
+                        metrics.event_log_dropped.add(n);
+                        dropped += n;
+                    }
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// Returns the segments sorted by first sequence number.
+    fn segments(&self) -> io::Result<Vec<(u64, PathBuf)>> {
+        list_segments(&self.config.dir)
+    }
+}
+
+/// Writes the records to the active segment, rotating and enforcing the
+/// retention as the log grows.
+struct SegmentWriter {
+    config: EventLogConfig,
+    active: Option<(File, u64)>,
+}
+
+impl SegmentWriter {
+    fn new(config: EventLogConfig) -> Self {
+        Self {
+            config,
+            active: None,
+        }
+    }
+
+    fn append(&mut self, entry: &LogEntry) -> io::Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+
+        let rotate = match &self.active {
+            None => true,
+            Some((_, size)) => size + line.len() as u64 > self.config.segment_bytes,
+        };
+        if rotate {
+            self.sync()?;
+            let path =
+                self.config
+                    .dir
+                    .join(format!("{:020}.{}", entry.first_seq(), SEGMENT_EXTENSION));
+            let file = OpenOptions::new().create(true).append(true).open(&path)?;
+            let size = file.metadata()?.len();
+            tracing::debug!("Writing event log segment {}", path.display());
+            self.active = Some((file, size));
+            self.enforce_retention()?;
+        }
+
+        let (file, size) = self.active.as_mut().expect("active segment");
+        file.write_all(&line)?;
+        *size += line.len() as u64;
+        Ok(())
+    }
+
+    /// Flushes the active segment to disk.
+    fn sync(&self) -> io::Result<()> {
+        match &self.active {
+            Some((file, _)) => file.sync_data(),
+            None => Ok(()),
+        }
+    }
+
+    /// Deletes the oldest segments exceeding the size or age limits,
+    /// the most recent segment is always kept.
+    fn enforce_retention(&self) -> io::Result<()> {
+        let mut segments = Vec::new();
+        for (_, path) in list_segments(&self.config.dir)? {
+            let metadata = fs::metadata(&path)?;
+            segments.push((path, metadata.len(), metadata.modified()?));
+        }
+
+        let mut total: u64 = segments.iter().map(|(_, size, _)| size).sum();
+        let now = SystemTime::now();
+        let deletable = segments.len().saturating_sub(1);
+
+        for (path, size, modified) in segments.into_iter().take(deletable) {
+            let expired = self
+                .config
+                .max_age
+                .is_some_and(|max_age| now.duration_since(modified).unwrap_or_default() > max_age);
+            if total <= self.config.max_bytes && !expired {
+                continue;
+            }
+
+            tracing::info!("Deleting event log segment {}", path.display());
+            fs::remove_file(&path)?;
+            total -= size;
+        }
+        Ok(())
+    }
+}
+
+/// Lists the segment files of the directory, sorted by first sequence number.
+fn list_segments(dir: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(SEGMENT_EXTENSION) {
+            continue;
+        }
+        if let Some(first_seq) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.parse::<u64>().ok())
+        {
+            segments.push((first_seq, path));
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::mock::CardPool;
+
+    /// Configuration of a fresh log directory named after the test.
+    fn config(name: &str, segment_bytes: u64, max_bytes: u64) -> EventLogConfig {
+        let dir =
+            std::env::temp_dir().join(format!("txapi-eventlog-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        EventLogConfig {
+            dir,
+            segment_bytes,
+            max_bytes,
+            max_age: None,
+        }
+    }
+
+    /// Records of the same transaction, one minute apart from `start`.
+    fn records(seqs: std::ops::RangeInclusive<u64>, start: DateTime<Utc>) -> Vec<LogEntry> {
+        let data = CardPool::new(10, 0.0, Default::default()).transaction();
+        seqs.map(|seq| {
+            LogEntry::Record(LogRecord {
+                seq,
+                logged_at: start + chrono::Duration::minutes(seq as i64),
+                data: data.clone(),
+            })
+        })
+        .collect()
+    }
+
+    fn seqs(entries: &[LogEntry]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|entry| match entry {
+                LogEntry::Record(record) => format!("{}", record.seq),
+                LogEntry::Gap(gap) => format!("{}-{}", gap.gap.from_seq, gap.gap.to_seq),
+            })
+            .collect()
+    }
+
+    fn segment_seqs(config: &EventLogConfig) -> Vec<u64> {
+        let segments = list_segments(&config.dir).unwrap();
+        segments.into_iter().map(|(seq, _)| seq).collect()
+    }
+
+    fn backdate(path: &Path, age: Duration) {
+        let file = OpenOptions::new().write(true).open(path).unwrap();
+        file.set_modified(SystemTime::now() - age).unwrap();
+    }
+
+    #[test]
+    fn rotated_segments_are_read_by_seq_and_time() {
+        // every record starts a new segment
+        let config = config("rotation", 1, u64::MAX);
+        let start = Utc::now();
+        let mut writer = SegmentWriter::new(config.clone());
+        for entry in records(1..=6, start) {
+            writer.append(&entry).unwrap();
+        }
+        assert_eq!(segment_seqs(&config), [1, 2, 3, 4, 5, 6]);
+
+        let log = EventLog::open(config.clone()).unwrap();
+        let by_seq = LogQuery {
+            from_seq: Some(3),
+            to_seq: Some(4),
+            ..Default::default()
+        };
+        assert_eq!(seqs(&log.query(&by_seq, usize::MAX).unwrap()), ["3", "4"]);
+        let by_time = LogQuery {
+            since: Some(start + chrono::Duration::minutes(2)),
+            until: Some(start + chrono::Duration::minutes(4)),
+            ..Default::default()
+        };
+        assert_eq!(
+            seqs(&log.query(&by_time, usize::MAX).unwrap()),
+            ["2", "3", "4"]
+        );
+        let limited = LogQuery {
+            from_seq: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(seqs(&log.query(&limited, 2).unwrap()), ["2", "3"]);
+
+        // an empty last segment falls back to the previous one
+        assert_eq!(log.last_seq().unwrap(), Some(6));
+        let empty = config.dir.join(format!("{:020}.{}", 7, SEGMENT_EXTENSION));
+        File::create(empty).unwrap();
+        assert_eq!(log.last_seq().unwrap(), Some(6));
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[test]
+    fn oldest_segments_are_deleted_beyond_the_limits() {
+        let entries = records(1..=5, Utc::now());
+        let line_len = serde_json::to_vec(&entries[0]).unwrap().len() as u64 + 1;
+        let mut config = config("retention", 1, 2 * line_len);
+        let mut writer = SegmentWriter::new(config.clone());
+        for entry in &entries {
+            writer.append(entry).unwrap();
+        }
+        // enforced when the last segment was started
+        assert_eq!(segment_seqs(&config), [3, 4, 5]);
+        let log = EventLog::open(config.clone()).unwrap();
+        assert_eq!(
+            seqs(&log.query(&LogQuery::default(), usize::MAX).unwrap()),
+            ["3", "4", "5"]
+        );
+
+        config.max_age = Some(Duration::from_secs(3600));
+        let writer = SegmentWriter::new(config.clone());
+        let segments = list_segments(&config.dir).unwrap();
+        backdate(&segments[0].1, Duration::from_secs(7200));
+        writer.enforce_retention().unwrap();
+        assert_eq!(segment_seqs(&config), [4, 5]);
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn retention_is_enforced_when_the_writer_starts() {
+        let mut config = config("startup", 1, u64::MAX);
+        let mut writer = SegmentWriter::new(config.clone());
+        for entry in records(1..=2, Utc::now()) {
+            writer.append(&entry).unwrap();
+        }
+        let segments = list_segments(&config.dir).unwrap();
+        backdate(&segments[0].1, Duration::from_secs(7200));
+
+        config.max_age = Some(Duration::from_secs(3600));
+        let log = EventLog::open(config.clone()).unwrap();
+        let (_tx, rx) = broadcast::channel(2);
+        log.writer(rx, Arc::default(), CancellationToken::new());
+
+        for _ in 0..100 {
+            if segment_seqs(&config) == [2] {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(segment_seqs(&config), [2]);
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn lagging_writer_logs_a_gap_marker() {
+        let config = config("gap", 64 * 1024, 1024 * 1024);
+        let log = EventLog::open(config.clone()).unwrap();
+
+        // the writer falls behind the first 3 of the 5 transactions
+        let (tx, rx) = broadcast::channel(2);
+        let mut pool = CardPool::new(10, 0.0, Default::default());
+        for seq in 1..=5 {
+            let data = pool.transaction();
+            tx.send(Sequenced { seq, data }).unwrap();
+        }
+        let metrics = Arc::new(Metrics::default());
+        log.writer(rx, metrics.clone(), CancellationToken::new());
+        drop(tx);
+
+        let mut entries = Vec::new();
+        for _ in 0..100 {
+            entries = log.query(&LogQuery::default(), usize::MAX).unwrap();
+            if entries.len() == 3 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(seqs(&entries), ["1-3", "4", "5"]);
+        assert_eq!(metrics.event_log_dropped.get(), 3);
+        assert_eq!(log.last_seq().unwrap(), Some(5));
+
+        // the gap marker matches the queries overlapping it
+        let query = LogQuery {
+            from_seq: Some(2),
+            to_seq: Some(2),
+            ..Default::default()
+        };
+        let entries = log.query(&query, usize::MAX).unwrap();
+        assert!(matches!(entries[..], [LogEntry::Gap(_)]));
+
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+}
@@ -1,25 +1,42 @@
 use futures::{Stream, StreamExt};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::broadcast;
 use tokio_util::sync::CancellationToken;
 
+use super::{
+    sequenced::{Sequenced, SequencedSender},
+    source::{SourceHealth, SourceRegistry},
+};
 use crate::domain::prelude::*;
 
 /// Initialize the heartbeat channel.
 /// This channel is used to broadcast heartbeats to the websocket clients.
 ///
+/// Heartbeats are sent every 10 seconds by default, but this can be
+/// overridden by the HEARTBEAT_INTERVAL_SECS environment variable.
+///
 /// This initializer is meant to be used to create a broadcaster at App State level,
 /// in order to make it available to the websocket handler.
 ///
 /// The cancellation_token parameter allows for graceful shutdown of the background task.
 ///
 pub async fn channel(
+    status: ServerStatus,
     cancellation_token: CancellationToken,
-) -> (broadcast::Sender<Heartbeat>, broadcast::Receiver<Heartbeat>) {
-    let (tx, rx) = broadcast::channel(16);
+) -> (
+    SequencedSender<Heartbeat>,
+    broadcast::Receiver<Sequenced<Heartbeat>>,
+) {
+    let interval_secs = 10;
+    let interval_secs = std::env::var("HEARTBEAT_INTERVAL_SECS")
+        .map(|s| s.parse::<u64>().unwrap_or(interval_secs))
+        .unwrap_or(interval_secs)
+        .max(1);
+
+    let (tx, rx) = SequencedSender::new(16, 16, 1);
     let tx_clone = tx.clone();
 
-    let mut stream = stream_heartbeats_every_10_secs();
+    let mut stream = stream_heartbeats(Duration::from_secs(interval_secs), status);
     tokio::spawn(async move {
         loop {
             tokio::select! {
@@ -32,7 +49,7 @@ pub async fn channel(
                 heartbeat = stream.next() => {
                     if let Some(heartbeat) = heartbeat {
                         tracing::info!("Broadcasting heartbeat: {:?}", heartbeat);
-                        let _ = tx_clone.send(heartbeat);
+                        tx_clone.send(heartbeat);
                     }
                 }
             }
@@ -41,15 +58,72 @@ pub async fn channel(
     (tx, rx)
 }
 
-/// A stream that generates heartbeats every 10 seconds
+/// The server state reported in the heartbeats.
 ///
-fn stream_heartbeats_every_10_secs() -> impl Stream<Item = Heartbeat> + Send {
-    let stream = futures::stream::unfold((), |()| async {
-        tokio::time::sleep(Duration::from_secs(10)).await;
-        let heartbeat = Heartbeat {
-            status: "ok".to_string(),
+/// Used by the heartbeat channel, and by the websocket clients that
+/// override the heartbeat interval.
+#[derive(Clone)]
+pub struct ServerStatus {
+    started_at: Instant,
+    transactions_tx: SequencedSender<Transaction>,
+    sources: SourceRegistry,
+}
+
+impl ServerStatus {
+    pub fn new(transactions_tx: SequencedSender<Transaction>, sources: SourceRegistry) -> Self {
+        Self {
+            started_at: Instant::now(),
+            transactions_tx,
+            sources,
+        }
+    }
+
+    /// Builds a heartbeat from the current server state.
+    ///
+    /// The status is `ok` while at least one source is running, `degraded`
+    /// if a source is degraded or failed, and `idle` otherwise.
+    pub fn heartbeat(&self) -> Heartbeat {
+        let sources = self.sources.health();
+
+        let status = if sources
+            .iter()
+            .any(|(_, health)| *health == SourceHealth::Running)
+        {
+            "ok"
+        } else if sources.iter().any(|(_, health)| {
+            matches!(
+                health,
+                SourceHealth::Degraded { .. } | SourceHealth::Failed { .. }
+            )
+        }) {
+            "degraded"
+        } else {
+            "idle"
         };
-        Some((heartbeat, ()))
+
+        Heartbeat {
+            status: status.to_string(),
+            server_time: chrono::Utc::now(),
+            uptime_secs: self.started_at.elapsed().as_secs(),
+            transactions_seq: self.transactions_tx.last_seq(),
+            sources: sources
+                .into_iter()
+                .map(|(name, health)| (name, health.status().to_string()))
+                .collect(),
+        }
+    }
+}
+
+/// A stream that generates heartbeats at the given interval
+///
+fn stream_heartbeats(
+    interval: Duration,
+    status: ServerStatus,
+) -> impl Stream<Item = Heartbeat> + Send {
+    let stream = futures::stream::unfold(status, move |status| async move {
+        tokio::time::sleep(interval).await;
+        let heartbeat = status.heartbeat();
+        Some((heartbeat, status))
     });
 
     Box::pin(stream)
@@ -0,0 +1,343 @@
+use futures::{
+    channel::oneshot::Canceled,
+    future::BoxFuture,
+    stream::{BoxStream, FuturesUnordered},
+    StreamExt,
+};
+use rdkafka::{
+    config::ClientConfig,
+    consumer::{Consumer, StreamConsumer},
+    producer::{future_producer::OwnedDeliveryResult, FutureProducer, FutureRecord, Producer},
+    util::Timeout,
+    Message,
+};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    sequenced::Sequenced,
+    source::{HealthCell, SourceHealth, TransactionSource},
+};
+use crate::{core::metrics::Metrics, domain::prelude::*};
+
+/// Maximum number of transactions produced to the sink and not yet delivered.
+const MAX_IN_FLIGHT: usize = 1000;
+
+/// Configuration of the Kafka integration.
+///
+/// The integration is enabled by building with the `kafka` feature and
+/// setting KAFKA_BROKERS, along with at least one of the topics:
+///
+/// - KAFKA_SOURCE_TOPIC: topic consumed into the transactions channel
+/// - KAFKA_SINK_TOPIC: topic every broadcast transaction is produced to
+/// - KAFKA_GROUP_ID: consumer group of the source (default `txapi`)
+///
+/// Transactions are exchanged as JSON, keyed by card number on the sink.
+///
+#[derive(Debug, Clone)]
+pub struct KafkaConfig {
+    pub brokers: String,
+    pub source_topic: Option<String>,
+    pub sink_topic: Option<String>,
+    pub group_id: String,
+}
+
+impl KafkaConfig {
+    /// Reads the Kafka configuration from the environment.
+    ///
+    /// Returns `None` when KAFKA_BROKERS is not set.
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            brokers: std::env::var("KAFKA_BROKERS").ok()?,
+            source_topic: std::env::var("KAFKA_SOURCE_TOPIC").ok(),
+            sink_topic: std::env::var("KAFKA_SINK_TOPIC").ok(),
+            group_id: std::env::var("KAFKA_GROUP_ID").unwrap_or_else(|_| "txapi".to_string()),
+        })
+    }
+}
+
+/// A source that consumes transactions from a Kafka topic.
+///
+/// Messages that are not valid transactions are skipped, and counted in the
+/// `kafka_rejected` metric.
+///
+pub struct KafkaSource {
+    config: KafkaConfig,
+    consumer: std::sync::Mutex<Option<Arc<StreamConsumer>>>,
+    health: HealthCell,
+    metrics: Arc<Metrics>,
+}
+
+impl KafkaSource {
+    pub fn new(config: KafkaConfig, metrics: Arc<Metrics>) -> Self {
+        Self {
+            config,
+            consumer: std::sync::Mutex::new(None),
+            health: HealthCell::default(),
+            metrics,
+        }
+    }
+}
+
+impl TransactionSource for KafkaSource {
+    fn name(&self) -> &str {
+        "kafka"
+    }
+
+    fn start(&self) -> Result<BoxStream<'static, Transaction>, String> {
+        let topic = self
+            .config
+            .source_topic
+            .as_deref()
+            .ok_or("KAFKA_SOURCE_TOPIC is not set")?;
+
+        let consumer: StreamConsumer = ClientConfig::new()
+            .set("bootstrap.servers", &self.config.brokers)
+            .set("group.id", &self.config.group_id)
+            .set("enable.auto.commit", "true")
+            .set("auto.offset.reset", "latest")
+            .create()
+            .map_err(|e| e.to_string())?;
+        consumer.subscribe(&[topic]).map_err(|e| e.to_string())?;
+
+        let consumer = Arc::new(consumer);
+        *self.consumer.lock().unwrap() = Some(consumer.clone());
+        self.health.set(SourceHealth::Running);
+
+        let health = self.health.clone();
+        let metrics = self.metrics.clone();
+        let stream = futures::stream::unfold(consumer, move |consumer| {
+            let health = health.clone();
+            let metrics = metrics.clone();
+            async move {
+                loop {
+                    let payload = match consumer.recv().await {
+                        Ok(message) => message.payload().map(<[u8]>::to_vec),
+                        Err(e) => {
+                            tracing::warn!("Kafka consumer error: {}", e);
+                            health.set(SourceHealth::Degraded {
+                                reason: e.to_string(),
+                            });
+                            tokio::time::sleep(Duration::from_secs(1)).await;
+                            continue;
+                        }
+                    };
+                    health.set(SourceHealth::Running);
+
+                    match payload.map(|p| parse(&p)) {
+                        Some(Ok(transaction)) => return Some((transaction, consumer)),
+                        Some(Err(e)) => tracing::warn!("Skipping invalid Kafka message: {}", e),
+                        None => tracing::warn!("Skipping empty Kafka message"),
+                    }
+                    metrics.kafka_rejected.inc();
+                }
+            }
+        });
+
+        Ok(stream.boxed())
+    }
+
+    fn health(&self) -> SourceHealth {
+        self.health.get()
+    }
+
+    fn shutdown(&self) -> BoxFuture<'_, ()> {
+        if let Some(consumer) = self.consumer.lock().unwrap().take() {
+            consumer.unsubscribe();
+        }
+        self.health.set(SourceHealth::Stopped);
+        Box::pin(async {})
+    }
+}
+
+/// Parses and validates the payload of a message.
+fn parse(payload: &[u8]) -> Result<Transaction, String> {
+    let transaction: Transaction = serde_json::from_slice(payload).map_err(|e| e.to_string())?;
+    transaction.validate().map_err(|errors| errors.join(", "))?;
+    Ok(transaction)
+}
+
+/// Produces every broadcast transaction to the sink topic.
+///
+/// Transactions consumed from the same topic by the Kafka source are not
+/// produced back, to avoid feedback loops. Up to `MAX_IN_FLIGHT`
+/// transactions are awaiting their delivery at once.
+///
+/// The cancellation_token parameter allows for graceful shutdown of the background task,
+/// pending messages are flushed before the task exits.
+///
+pub fn sink(
+    config: KafkaConfig,
+    mut transactions_rx: broadcast::Receiver<Sequenced<Transaction>>,
+    cancellation_token: CancellationToken,
+) -> Result<(), String> {
+    let topic = config.sink_topic.ok_or("KAFKA_SINK_TOPIC is not set")?;
+    let skip_own_source = config.source_topic.as_deref() == Some(topic.as_str());
+
+    let producer: FutureProducer = ClientConfig::new()
+        .set("bootstrap.servers", &config.brokers)
+        .set("message.timeout.ms", "5000")
+        .create()
+        .map_err(|e| e.to_string())?;
+
+    tokio::spawn(async move {
+        let mut in_flight = FuturesUnordered::new();
+        loop {
+            let transaction = tokio::select! {
+                _ = cancellation_token.cancelled() => break,
+                Some((id, delivery)) = in_flight.next() => {
+                    delivered(id, delivery);
+                    continue;
+                }
+                // stop receiving while too many deliveries are pending
+                transaction = transactions_rx.recv(), if in_flight.len() < MAX_IN_FLIGHT => {
+                    transaction
+                }
+            };
+
+            let transaction = match transaction {
+                Ok(transaction) => transaction.data,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!("Kafka sink lagged, {} transactions not produced", n);
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            if skip_own_source && transaction.source.as_deref() == Some("kafka") {
+                continue;
+            }
+
+            let payload = match serde_json::to_vec(&transaction) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    tracing::error!("Failed to serialize transaction: {}", e);
+                    continue;
+                }
+            };
+            let record = FutureRecord::to(&topic)
+                .key(&transaction.cc_number)
+                .payload(&payload);
+            match producer.send_result(record) {
+                Ok(delivery) => in_flight.push(async move { (transaction.id, delivery.await) }),
+                Err((e, _)) => {
+                    tracing::error!("Failed to produce transaction {}: {}", transaction.id, e)
+                }
+            }
+        }
+
+        tracing::info!("Kafka sink shutting down gracefully");
+        // flushing blocks until the pending messages are delivered
+        let flushed = tokio::task::spawn_blocking(move || {
+            producer.flush(Timeout::After(Duration::from_secs(5)))
+        })
+        .await;
+        match flushed {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => tracing::error!("Failed to flush Kafka producer: {}", e),
+            Err(e) => tracing::error!("Kafka producer flush did not complete: {}", e),
+        }
+        while let Some((id, delivery)) = in_flight.next().await {
+            delivered(id, delivery);
+        }
+    });
+
+    Ok(())
+}
+
+/// Logs the failed delivery of a transaction to the sink.
+fn delivered(id: String, delivery: Result<OwnedDeliveryResult, Canceled>) {
+    match delivery {
+        Ok(Ok(_)) => {}
+        Ok(Err((e, _))) => tracing::error!("Failed to produce transaction {}: {}", id, e),
+        Err(Canceled) => tracing::error!("Delivery of transaction {} was canceled", id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_valid_transactions_are_parsed() {
+        let transaction = Transaction::simple_mock();
+        let payload = serde_json::to_vec(&transaction).unwrap();
+        assert_eq!(parse(&payload).unwrap().id, transaction.id);
+
+        let mut invalid = transaction;
+        invalid.timestamp = "yesterday".to_string();
+        invalid.location.country_iso = "France".to_string();
+        let payload = serde_json::to_vec(&invalid).unwrap();
+        assert_eq!(
+            parse(&payload).unwrap_err(),
+            "timestamp is not RFC3339: yesterday, country_iso is not ISO 3166-1 alpha-2: France"
+        );
+        assert!(parse(b"{\"id\": \"t1\"}").is_err());
+    }
+
+    /// Produces transactions with the sink and consumes them with the source,
+    /// against the broker set in KAFKA_TEST_BROKERS (skipped when unset).
+    #[tokio::test]
+    async fn transactions_round_trip_through_a_topic() {
+        let Ok(brokers) = std::env::var("KAFKA_TEST_BROKERS") else {
+            return;
+        };
+        let topic = format!("txapi-test-{}", uuid::Uuid::new_v4().simple());
+        let config = KafkaConfig {
+            brokers,
+            source_topic: Some(topic.clone()),
+            sink_topic: Some(topic),
+            group_id: format!("txapi-test-{}", uuid::Uuid::new_v4().simple()),
+        };
+        let metrics = Arc::new(Metrics::default());
+        let source = KafkaSource::new(config.clone(), metrics.clone());
+        let mut consumed = source.start().unwrap();
+
+        let (tx, rx) = broadcast::channel(100);
+        let cancellation_token = CancellationToken::new();
+        sink(config, rx, cancellation_token.clone()).unwrap();
+        let mut seq = 0;
+        let mut produce = |transaction: &Transaction| {
+            seq += 1;
+            let data = transaction.clone();
+            tx.send(Sequenced { seq, data }).unwrap();
+        };
+
+        // the consumer starts from the latest offset once it is assigned
+        // the topic, so transactions are produced until one gets through
+        let first = Transaction::simple_mock();
+        let received = tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                produce(&first);
+                let next = tokio::time::timeout(Duration::from_secs(1), consumed.next());
+                if let Ok(Some(transaction)) = next.await {
+                    return transaction;
+                }
+            }
+        })
+        .await
+        .expect("no transaction consumed");
+        assert_eq!(received.id, first.id);
+
+        // with the same key, the invalid transaction is consumed first
+        let mut invalid = Transaction::simple_mock();
+        invalid.timestamp = "yesterday".to_string();
+        let mut valid = Transaction::simple_mock();
+        valid.cc_number = invalid.cc_number.clone();
+        produce(&invalid);
+        produce(&valid);
+        let received = loop {
+            let next = tokio::time::timeout(Duration::from_secs(10), consumed.next());
+            let transaction = next.await.expect("no transaction consumed").unwrap();
+            // duplicates of the first one may still be in flight
+            if transaction.id != first.id {
+                break transaction;
+            }
+        };
+        assert_eq!(received.id, valid.id);
+        assert_eq!(metrics.kafka_rejected.get(), 1);
+
+        cancellation_token.cancel();
+        source.shutdown().await;
+    }
+}
@@ -0,0 +1,261 @@
+use futures::{future::BoxFuture, stream::BoxStream, Stream, StreamExt};
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use super::source::{HealthCell, SourceHealth, TransactionSource};
+use crate::domain::{prelude::*, transactions::Location};
+
+/// Share of the transactions of a pooled card made in its home city.
+const HOME_CITY_RATIO: f64 = 0.85;
+
+/// Share of the transactions made by the compromised cards, while there are some.
+const FRAUD_SHARE: f64 = 0.3;
+
+/// Categories and amount multiplier of the fraudulent transactions.
+const FRAUD_CATEGORIES: [TransactionCategory; 3] = [
+    TransactionCategory::OnlineRetail,
+    TransactionCategory::Travel,
+    TransactionCategory::Entertainment,
+];
+const FRAUD_AMOUNT_FACTOR: (u64, u64) = (2, 6);
+
+/// The mock transaction generator source.
+///
+/// Transactions are made with a pool of MOCK_CARD_POOL_SIZE cards (default
+/// 1000), so that cards come back and have a history, mostly in their home
+/// city. A pool size of 0 makes every transaction use a new card.
+///
+/// Pooled cards get compromised at the MOCK_FRAUD_RATE per transaction
+/// (default 0.005): a fraud scenario then makes a burst of 3 to 8 large
+/// transactions abroad, labelled with `is_fraud`. Declining one of them
+/// in the authorization simulation ends the scenario, as the card is blocked.
+pub struct MockSource {
+    health: HealthCell,
+    pool_size: usize,
+    fraud_rate: f64,
+    /// Cards whose fraud scenario was stopped, not yet applied to the pool
+    blocked: Arc<Mutex<Vec<String>>>,
+}
+
+impl Default for MockSource {
+    fn default() -> Self {
+        let pool_size = std::env::var("MOCK_CARD_POOL_SIZE")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(1000);
+        let fraud_rate = std::env::var("MOCK_FRAUD_RATE")
+            .ok()
+            .and_then(|s| s.parse::<f64>().ok())
+            .unwrap_or(0.005)
+            .clamp(0.0, 1.0);
+        Self {
+            health: HealthCell::default(),
+            pool_size,
+            fraud_rate,
+            blocked: Arc::default(),
+        }
+    }
+}
+
+impl TransactionSource for MockSource {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn start(&self) -> Result<BoxStream<'static, Transaction>, String> {
+        self.health.set(SourceHealth::Running);
+        let pool = CardPool::new(self.pool_size, self.fraud_rate, self.blocked.clone());
+        Ok(stream_from_mocks(pool).boxed())
+    }
+
+    fn health(&self) -> SourceHealth {
+        self.health.get()
+    }
+
+    fn shutdown(&self) -> BoxFuture<'_, ()> {
+        self.health.set(SourceHealth::Stopped);
+        Box::pin(async {})
+    }
+
+    fn declined(&self, transaction: &Transaction) {
+        if transaction.is_fraud == Some(true) {
+            let mut blocked = self.blocked.lock().unwrap();
+            blocked.push(transaction.cc_number.clone());
+        }
+    }
+}
+
+/// A stream that generates mock transactions
+///
+/// This stream is used to generate mock transactions for testing purposes.
+/// It is used to simulate a stream of transactions that are being processed
+/// by the backend.
+///
+fn stream_from_mocks(pool: CardPool) -> impl Stream<Item = Transaction> + Send {
+    let stream = futures::stream::unfold(pool, |mut pool| async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let transaction = pool.transaction();
+        Some((transaction, pool))
+    });
+
+    Box::pin(stream)
+}
+
+/// A card of the pool, with its home city.
+struct Card {
+    cc_number: String,
+    home: Location,
+}
+
+/// The cards used by the mock transactions.
+pub(super) struct CardPool {
+    cards: Vec<Card>,
+    fraud_rate: f64,
+    /// Remaining fraudulent transactions of the compromised cards, by index
+    compromised: HashMap<usize, u32>,
+    /// Cards whose fraud scenario must stop, shared with the source
+    blocked: Arc<Mutex<Vec<String>>>,
+}
+
+impl CardPool {
+    pub(super) fn new(size: usize, fraud_rate: f64, blocked: Arc<Mutex<Vec<String>>>) -> Self {
+        let cards = (0..size)
+            .map(|_| Card {
+                cc_number: Transaction::generate_valid_cc_number(),
+                home: Location::random(),
+            })
+            .collect();
+        Self {
+            cards,
+            fraud_rate,
+            compromised: HashMap::new(),
+            blocked,
+        }
+    }
+
+    /// Generates a mock transaction made with a random card of the pool,
+    /// or the next transaction of a fraud scenario.
+    pub(super) fn transaction(&mut self) -> Transaction {
+        let mut transaction = Transaction::simple_mock();
+        if self.cards.is_empty() {
+            return transaction;
+        }
+
+        self.stop_blocked();
+
+        let mut rng = rand::rng();
+        if rng.random_bool(self.fraud_rate) {
+            let idx = rng.random_range(0..self.cards.len());
+            self.compromised.insert(idx, rng.random_range(3..=8));
+        }
+
+        if !self.compromised.is_empty() && rng.random_bool(FRAUD_SHARE) {
+            let idx = *self
+                .compromised
+                .keys()
+                .nth(rng.random_range(0..self.compromised.len()))
+                .expect("compromised card");
+            if let Some(remaining) = self.compromised.get_mut(&idx) {
+                *remaining -= 1;
+                if *remaining == 0 {
+                    self.compromised.remove(&idx);
+                }
+            }
+            return self.fraudulent(&self.cards[idx], transaction);
+        }
+
+        let card = &self.cards[rng.random_range(0..self.cards.len())];
+        transaction.cc_number = card.cc_number.clone();
+        if rng.random_bool(HOME_CITY_RATIO) {
+            transaction.location = card.home.clone();
+        }
+        transaction.is_fraud = Some(false);
+        transaction
+    }
+
+    /// Ends the fraud scenarios of the blocked cards.
+    fn stop_blocked(&mut self) {
+        let blocked: Vec<_> = self.blocked.lock().unwrap().drain(..).collect();
+        if blocked.is_empty() {
+            return;
+        }
+        let cards = &self.cards;
+        self.compromised
+            .retain(|idx, _| !blocked.contains(&cards[*idx].cc_number));
+    }
+
+    /// Turns the transaction into a fraudulent one: large, mostly online,
+    /// away from the home country of the card.
+    fn fraudulent(&self, card: &Card, mut transaction: Transaction) -> Transaction {
+        let mut rng = rand::rng();
+        let category = FRAUD_CATEGORIES[rng.random_range(0..FRAUD_CATEGORIES.len())];
+        let (_, max_amount) = category.typical_amount_range();
+        let (min_factor, max_factor) = FRAUD_AMOUNT_FACTOR;
+
+        transaction.cc_number = card.cc_number.clone();
+        transaction.category = category;
+        transaction.amount_usd_cents =
+            rng.random_range(max_amount * min_factor..=max_amount * max_factor);
+        transaction.is_online = rng.random_bool(0.7);
+        transaction.location = loop {
+            let location = Location::random();
+            if location.country_iso != card.home.country_iso {
+                break location;
+            }
+        };
+        transaction.is_fraud = Some(true);
+        transaction
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::authorization::{AuthorizationConfig, Authorizer};
+    use crate::stream::source::SourceRegistry;
+
+    #[tokio::test]
+    async fn declined_fraud_stops_the_scenario() {
+        let source = MockSource {
+            health: HealthCell::default(),
+            pool_size: 1,
+            fraud_rate: 1.0,
+            blocked: Arc::default(),
+        };
+        let mut pool = CardPool::new(1, 1.0, source.blocked.clone());
+        let sources = SourceRegistry::new();
+        sources.register(source);
+        let config = AuthorizationConfig {
+            client_id: "model".to_string(),
+            token: "secret".to_string(),
+            timeout: Duration::from_millis(200),
+            default: AuthorizationDecision::Decline,
+        };
+        let authorizer = Authorizer::new(config, sources);
+
+        let fraudulent = std::iter::repeat_with(|| pool.transaction())
+            .take(1000)
+            .find(|transaction| transaction.is_fraud == Some(true))
+            .expect("fraudulent transaction");
+        pool.fraud_rate = 0.0;
+
+        // no client is connected, the default decision declines it
+        let declined = authorizer
+            .authorize(Transaction {
+                source: Some("mock".to_string()),
+                ..fraudulent
+            })
+            .await;
+        assert_eq!(
+            declined.authorization.unwrap().decision,
+            AuthorizationDecision::Decline
+        );
+        assert!((0..100).all(|_| pool.transaction().is_fraud == Some(false)));
+        assert!(pool.compromised.is_empty());
+    }
+}
@@ -1,2 +1,13 @@
+pub mod enrichment;
+pub mod eventlog;
 pub mod heartbeat;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod mock;
+pub mod replay;
+pub mod rules;
+pub mod scoring;
+pub mod sequenced;
+pub mod source;
+pub mod stats;
 pub mod transactions;
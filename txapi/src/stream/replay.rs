@@ -0,0 +1,860 @@
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+use futures::{future::BoxFuture, stream::BoxStream, Stream, StreamExt};
+use std::{
+    collections::HashMap, fs::File, io::BufRead, io::BufReader, path::PathBuf, time::Duration,
+};
+use tokio::sync::mpsc;
+
+use super::source::{HealthCell, SourceHealth, TransactionSource};
+use crate::domain::{prelude::*, transactions::Location};
+
+/// Configuration of the file replay source.
+///
+/// The replay source is enabled by setting the REPLAY_FILE environment
+/// variable, the remaining settings are optional:
+///
+/// - REPLAY_FORMAT: `csv` or `ndjson` (inferred from the file extension)
+/// - REPLAY_SCHEMA: column mapping preset, `native` (default), `sparkov` or `ulb`
+/// - REPLAY_COLUMNS: per-field overrides, ex. `cc_number=card,amount=amt`
+/// - REPLAY_PACE: `original` (default), `accelerated:<factor>` or `fixed:<millis>`
+/// - REPLAY_RESTAMP: rewrite timestamps to the emission time (default true)
+/// - REPLAY_TIME_ORIGIN: RFC3339 time the numeric timestamps are seconds
+///   since (default unix epoch, 2013-09-01 for the `ulb` schema)
+/// - REPLAY_LOOP: start over when the end of the file is reached (default false)
+///
+#[derive(Debug, Clone)]
+pub struct ReplayConfig {
+    pub path: PathBuf,
+    pub format: ReplayFormat,
+    pub mapping: ColumnMapping,
+    pub pace: ReplayPace,
+    pub restamp: bool,
+    pub loop_forever: bool,
+}
+
+impl ReplayConfig {
+    /// Reads the replay configuration from the environment.
+    ///
+    /// Returns `None` when REPLAY_FILE is not set.
+    pub fn from_env() -> Option<Self> {
+        let path = PathBuf::from(std::env::var("REPLAY_FILE").ok()?);
+
+        let format = std::env::var("REPLAY_FORMAT")
+            .ok()
+            .and_then(|s| parse_or_warn("REPLAY_FORMAT", &s))
+            .unwrap_or_else(|| ReplayFormat::from_path(&path));
+
+        let mut mapping: ColumnMapping = std::env::var("REPLAY_SCHEMA")
+            .ok()
+            .and_then(|s| parse_or_warn("REPLAY_SCHEMA", &s))
+            .unwrap_or_default();
+        if let Ok(overrides) = std::env::var("REPLAY_COLUMNS") {
+            if let Err(e) = mapping.apply_overrides(&overrides) {
+                tracing::warn!("Ignoring REPLAY_COLUMNS: {}", e);
+            }
+        }
+        if let Ok(origin) = std::env::var("REPLAY_TIME_ORIGIN") {
+            match DateTime::parse_from_rfc3339(&origin) {
+                Ok(origin) => mapping.time_origin = Some(origin.with_timezone(&Utc)),
+                Err(e) => tracing::warn!("Ignoring REPLAY_TIME_ORIGIN: {}", e),
+            }
+        }
+
+        let pace = std::env::var("REPLAY_PACE")
+            .ok()
+            .and_then(|s| parse_or_warn("REPLAY_PACE", &s))
+            .unwrap_or(ReplayPace::Original);
+
+        let restamp = std::env::var("REPLAY_RESTAMP")
+            .map(|s| parse_bool(&s).unwrap_or(true))
+            .unwrap_or(true);
+        let loop_forever = std::env::var("REPLAY_LOOP")
+            .map(|s| parse_bool(&s).unwrap_or(false))
+            .unwrap_or(false);
+
+        Some(Self {
+            path,
+            format,
+            mapping,
+            pace,
+            restamp,
+            loop_forever,
+        })
+    }
+}
+
+/// File format of a replay dataset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayFormat {
+    Csv,
+    Ndjson,
+}
+
+impl ReplayFormat {
+    /// Infers the format from the file extension, defaulting to CSV.
+    pub fn from_path(path: &std::path::Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("ndjson" | "jsonl" | "json") => Self::Ndjson,
+            _ => Self::Csv,
+        }
+    }
+}
+
+impl std::str::FromStr for ReplayFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "ndjson" | "jsonl" => Ok(Self::Ndjson),
+            _ => Err(format!("Invalid replay format: {}", s)),
+        }
+    }
+}
+
+/// Pace at which the replayed transactions are emitted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayPace {
+    /// Keep the original spacing between the recorded timestamps.
+    Original,
+    /// Original spacing divided by the given factor.
+    Accelerated(f64),
+    /// Fixed delay between transactions, regardless of the timestamps.
+    Fixed(Duration),
+}
+
+impl ReplayPace {
+    /// Returns the delay to wait before emitting a record recorded at
+    /// `current`, given the time of the previously emitted record.
+    fn delay(&self, previous: Option<DateTime<Utc>>, current: Option<DateTime<Utc>>) -> Duration {
+        let factor = match self {
+            Self::Fixed(delay) => return *delay,
+            Self::Original => 1.0,
+            Self::Accelerated(factor) => *factor,
+        };
+
+        match (previous, current) {
+            (Some(previous), Some(current)) => (current - previous)
+                .to_std()
+                .map(|gap| gap.div_f64(factor))
+                .unwrap_or_default(),
+            _ => Duration::ZERO,
+        }
+    }
+}
+
+impl std::str::FromStr for ReplayPace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, value) = s.split_once(':').unwrap_or((s, ""));
+        match kind {
+            "original" => Ok(Self::Original),
+            "accelerated" => match value.parse::<f64>() {
+                Ok(factor) if factor > 0.0 => Ok(Self::Accelerated(factor)),
+                _ => Err(format!("Invalid acceleration factor: {}", value)),
+            },
+            "fixed" => value
+                .parse::<u64>()
+                .map(|millis| Self::Fixed(Duration::from_millis(millis)))
+                .map_err(|_| format!("Invalid fixed delay: {}", value)),
+            _ => Err(format!("Invalid replay pace: {}", s)),
+        }
+    }
+}
+
+/// Unit of the amount column of a dataset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmountUnit {
+    Cents,
+    Dollars,
+}
+
+/// Mapping between the `Transaction` fields and the dataset columns.
+///
+/// Fields without a column (or with an empty value in a record) are filled
+/// with mock values, so that datasets without card numbers or locations can
+/// still be replayed.
+///
+#[derive(Debug, Clone)]
+pub struct ColumnMapping {
+    pub id: Option<String>,
+    pub timestamp: Option<String>,
+    pub cc_number: Option<String>,
+    pub category: Option<String>,
+    pub amount: Option<String>,
+    pub amount_unit: AmountUnit,
+    pub city: Option<String>,
+    pub country_iso: Option<String>,
+    pub latitude: Option<String>,
+    pub longitude: Option<String>,
+    pub is_online: Option<String>,
+    pub is_fraud: Option<String>,
+    /// Country used when the dataset has no country column.
+    pub default_country_iso: Option<String>,
+    /// Time the numeric timestamps are seconds since, the unix epoch if unset.
+    pub time_origin: Option<DateTime<Utc>>,
+}
+
+impl Default for ColumnMapping {
+    /// The mapping for datasets using the `Transaction` field names.
+    fn default() -> Self {
+        Self {
+            id: Some("id".to_string()),
+            timestamp: Some("timestamp".to_string()),
+            cc_number: Some("cc_number".to_string()),
+            category: Some("category".to_string()),
+            amount: Some("amount_usd_cents".to_string()),
+            amount_unit: AmountUnit::Cents,
+            city: Some("city".to_string()),
+            country_iso: Some("country_iso".to_string()),
+            latitude: Some("latitude".to_string()),
+            longitude: Some("longitude".to_string()),
+            is_online: Some("is_online".to_string()),
+            is_fraud: Some("is_fraud".to_string()),
+            default_country_iso: None,
+            time_origin: None,
+        }
+    }
+}
+
+impl ColumnMapping {
+    /// The mapping for the Kaggle "Credit Card Transactions Fraud Detection"
+    /// dataset (`fraudTrain.csv` / `fraudTest.csv`, generated with Sparkov).
+    pub fn sparkov() -> Self {
+        Self {
+            id: Some("trans_num".to_string()),
+            timestamp: Some("trans_date_trans_time".to_string()),
+            cc_number: Some("cc_num".to_string()),
+            category: Some("category".to_string()),
+            amount: Some("amt".to_string()),
+            amount_unit: AmountUnit::Dollars,
+            city: Some("city".to_string()),
+            country_iso: None,
+            latitude: Some("merch_lat".to_string()),
+            longitude: Some("merch_long".to_string()),
+            is_online: None,
+            is_fraud: Some("is_fraud".to_string()),
+            default_country_iso: Some("US".to_string()),
+            time_origin: None,
+        }
+    }
+
+    /// The mapping for the Kaggle "Credit Card Fraud Detection" dataset
+    /// (`creditcard.csv`, ULB), which only has a time offset, the amount and
+    /// the label.
+    ///
+    /// The time offsets are seconds since the first transaction, recorded
+    /// in September 2013: they are counted from 2013-09-01 so that the
+    /// timestamps are plausible when they are not rewritten.
+    pub fn ulb() -> Self {
+        Self {
+            id: None,
+            timestamp: Some("Time".to_string()),
+            cc_number: None,
+            category: None,
+            amount: Some("Amount".to_string()),
+            amount_unit: AmountUnit::Dollars,
+            city: None,
+            country_iso: None,
+            latitude: None,
+            longitude: None,
+            is_online: None,
+            is_fraud: Some("Class".to_string()),
+            default_country_iso: None,
+            time_origin: Utc.with_ymd_and_hms(2013, 9, 1, 0, 0, 0).single(),
+        }
+    }
+
+    /// Applies comma separated `field=column` overrides.
+    ///
+    /// The `amount_unit` pseudo field accepts `cents` or `dollars`.
+    pub fn apply_overrides(&mut self, overrides: &str) -> Result<(), String> {
+        for pair in overrides
+            .split(',')
+            .map(str::trim)
+            .filter(|p| !p.is_empty())
+        {
+            let (field, column) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid column mapping: {}", pair))?;
+            let column = Some(column.trim().to_string()).filter(|c| !c.is_empty());
+
+            match field.trim() {
+                "id" => self.id = column,
+                "timestamp" => self.timestamp = column,
+                "cc_number" => self.cc_number = column,
+                "category" => self.category = column,
+                "amount" | "amount_usd_cents" => self.amount = column,
+                "city" => self.city = column,
+                "country_iso" => self.country_iso = column,
+                "latitude" => self.latitude = column,
+                "longitude" => self.longitude = column,
+                "is_online" => self.is_online = column,
+                "is_fraud" => self.is_fraud = column,
+                "amount_unit" => {
+                    self.amount_unit = match column.as_deref() {
+                        Some("cents") => AmountUnit::Cents,
+                        Some("dollars") => AmountUnit::Dollars,
+                        _ => return Err(format!("Invalid amount unit: {}", pair)),
+                    }
+                }
+                other => return Err(format!("Unknown transaction field: {}", other)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps a dataset record to a transaction.
+    ///
+    /// Returns the transaction along with the recorded time, which is used
+    /// for pacing.
+    fn to_transaction(
+        &self,
+        record: &HashMap<String, String>,
+    ) -> Result<(Transaction, Option<DateTime<Utc>>), String> {
+        let get = |column: &Option<String>| {
+            column
+                .as_ref()
+                .and_then(|column| record.get(column))
+                .map(|value| value.trim())
+                .filter(|value| !value.is_empty())
+        };
+
+        let amount = get(&self.amount).ok_or("missing amount")?;
+        let amount_usd_cents = match self.amount_unit {
+            AmountUnit::Cents => amount
+                .parse::<u64>()
+                .map_err(|_| format!("invalid amount: {}", amount))?,
+            AmountUnit::Dollars => match amount.parse::<f64>() {
+                Ok(dollars) if dollars >= 0.0 => (dollars * 100.0).round() as u64,
+                _ => return Err(format!("invalid amount: {}", amount)),
+            },
+        };
+
+        let recorded_at = get(&self.timestamp)
+            .map(|value| parse_timestamp(value, self.time_origin))
+            .transpose()?;
+
+        let (category, online_hint) = match get(&self.category) {
+            Some(label) => parse_category(label)?,
+            None => (TransactionCategory::random(), None),
+        };
+
+        let mut location = Location::random();
+        if let Some(city) = get(&self.city) {
+            location.city = city.to_string();
+        }
+        if let Some(country) = get(&self.country_iso).or(self.default_country_iso.as_deref()) {
+            location.country_iso = country.to_uppercase();
+        }
+        if let (Some(lat), Some(lon)) = (get(&self.latitude), get(&self.longitude)) {
+            location.latitude = lat
+                .parse()
+                .map_err(|_| format!("invalid latitude: {}", lat))?;
+            location.longitude = lon
+                .parse()
+                .map_err(|_| format!("invalid longitude: {}", lon))?;
+        }
+
+        let is_online = match get(&self.is_online) {
+            Some(value) => parse_bool(value).ok_or(format!("invalid is_online: {}", value))?,
+            None => online_hint.unwrap_or(false),
+        };
+        let is_fraud = get(&self.is_fraud)
+            .map(|value| parse_bool(value).ok_or(format!("invalid is_fraud: {}", value)))
+            .transpose()?;
+
+        let transaction = Transaction {
+            id: get(&self.id)
+                .map(|id| id.replace('-', ""))
+                .unwrap_or_else(|| uuid::Uuid::new_v4().simple().to_string()),
+            timestamp: recorded_at.unwrap_or_else(Utc::now).to_rfc3339(),
+            cc_number: get(&self.cc_number)
+                .map(str::to_string)
+                .unwrap_or_else(Transaction::generate_valid_cc_number),
+            category,
+            amount_usd_cents,
+            location,
+            is_online,
+            is_fraud,
+            source: None,
+            authorization: None,
+        };
+
+        Ok((transaction, recorded_at))
+    }
+}
+
+impl std::str::FromStr for ColumnMapping {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "native" => Ok(Self::default()),
+            "sparkov" => Ok(Self::sparkov()),
+            "ulb" => Ok(Self::ulb()),
+            _ => Err(format!("Invalid replay schema: {}", s)),
+        }
+    }
+}
+
+/// The file replay source.
+pub struct ReplaySource {
+    config: ReplayConfig,
+    health: HealthCell,
+}
+
+impl ReplaySource {
+    pub fn new(config: ReplayConfig) -> Self {
+        Self {
+            config,
+            health: HealthCell::default(),
+        }
+    }
+}
+
+impl TransactionSource for ReplaySource {
+    fn name(&self) -> &str {
+        "replay"
+    }
+
+    fn start(&self) -> Result<BoxStream<'static, Transaction>, String> {
+        if !self.config.path.is_file() {
+            let reason = format!("{} is not a file", self.config.path.display());
+            self.health.set(SourceHealth::Failed {
+                reason: reason.clone(),
+            });
+            return Err(reason);
+        }
+
+        self.health.set(SourceHealth::Running);
+        Ok(stream_from_file(self.config.clone(), self.health.clone()).boxed())
+    }
+
+    fn health(&self) -> SourceHealth {
+        self.health.get()
+    }
+
+    fn shutdown(&self) -> BoxFuture<'_, ()> {
+        if self.health.get() == SourceHealth::Running {
+            self.health.set(SourceHealth::Stopped);
+        }
+        Box::pin(async {})
+    }
+}
+
+/// A stream that replays transactions from a recorded dataset.
+///
+/// The file is read on a blocking thread and parsed records are handed over
+/// through a bounded channel, so only a small window of the dataset is held
+/// in memory. Records that cannot be mapped to a transaction are skipped.
+///
+fn stream_from_file(
+    config: ReplayConfig,
+    health: HealthCell,
+) -> impl Stream<Item = Transaction> + Send {
+    let (tx, rx) = mpsc::channel(256);
+    let reader_config = config.clone();
+    let reader_health = health.clone();
+    tokio::task::spawn_blocking(move || read_records(reader_config, reader_health, tx));
+
+    let state = (rx, config, health, None::<DateTime<Utc>>);
+    let stream = futures::stream::unfold(state, |(mut rx, config, health, previous)| async move {
+        let Some((mut transaction, recorded_at)) = rx.recv().await else {
+            if health.get() == SourceHealth::Running {
+                health.set(SourceHealth::Finished);
+            }
+            return None;
+        };
+
+        tokio::time::sleep(config.pace.delay(previous, recorded_at)).await;
+        if config.restamp {
+            transaction.timestamp = Utc::now().to_rfc3339();
+        }
+
+        Some((transaction, (rx, config, health, recorded_at.or(previous))))
+    });
+
+    Box::pin(stream)
+}
+
+/// Reads the dataset and sends the mapped transactions to the channel,
+/// until the end of the file or until the receiving stream is dropped.
+fn read_records(
+    config: ReplayConfig,
+    health: HealthCell,
+    tx: mpsc::Sender<(Transaction, Option<DateTime<Utc>>)>,
+) {
+    loop {
+        tracing::info!("Replaying transactions from {}", config.path.display());
+
+        let result = match config.format {
+            ReplayFormat::Csv => read_csv(&config, &tx),
+            ReplayFormat::Ndjson => read_ndjson(&config, &tx),
+        };
+
+        match result {
+            Err(e) => {
+                tracing::error!("Failed to read {}: {}", config.path.display(), e);
+                health.set(SourceHealth::Failed { reason: e });
+                return;
+            }
+            // the receiving stream was dropped
+            Ok(false) => return,
+            Ok(true) if !config.loop_forever => {
+                tracing::info!("Finished replaying {}", config.path.display());
+                return;
+            }
+            Ok(true) => {}
+        }
+    }
+}
+
+/// Reads a CSV file with headers, returning `Ok(false)` if the receiver is gone.
+fn read_csv(
+    config: &ReplayConfig,
+    tx: &mpsc::Sender<(Transaction, Option<DateTime<Utc>>)>,
+) -> Result<bool, String> {
+    let mut reader = csv::Reader::from_path(&config.path).map_err(|e| e.to_string())?;
+    let headers = reader.headers().map_err(|e| e.to_string())?.clone();
+
+    for (line, row) in reader.records().enumerate() {
+        let record = match row {
+            Ok(row) => headers
+                .iter()
+                .zip(row.iter())
+                .map(|(header, value)| (header.to_string(), value.to_string()))
+                .collect(),
+            Err(e) => {
+                tracing::warn!("Skipping unreadable row {}: {}", line + 1, e);
+                continue;
+            }
+        };
+        if !forward(config, &record, line + 1, tx) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Reads a newline delimited JSON file, returning `Ok(false)` if the receiver is gone.
+fn read_ndjson(
+    config: &ReplayConfig,
+    tx: &mpsc::Sender<(Transaction, Option<DateTime<Utc>>)>,
+) -> Result<bool, String> {
+    let file = File::open(&config.path).map_err(|e| e.to_string())?;
+
+    for (line, text) in BufReader::new(file).lines().enumerate() {
+        let text = text.map_err(|e| e.to_string())?;
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        let record = match serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&text)
+        {
+            Ok(object) => object
+                .into_iter()
+                .map(|(key, value)| match value {
+                    serde_json::Value::String(s) => (key, s),
+                    serde_json::Value::Null => (key, String::new()),
+                    other => (key, other.to_string()),
+                })
+                .collect(),
+            Err(e) => {
+                tracing::warn!("Skipping invalid JSON on line {}: {}", line + 1, e);
+                continue;
+            }
+        };
+        if !forward(config, &record, line + 1, tx) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Maps a record and sends it, returning false if the receiver is gone.
+fn forward(
+    config: &ReplayConfig,
+    record: &HashMap<String, String>,
+    line: usize,
+    tx: &mpsc::Sender<(Transaction, Option<DateTime<Utc>>)>,
+) -> bool {
+    match config.mapping.to_transaction(record) {
+        Ok(mapped) => tx.blocking_send(mapped).is_ok(),
+        Err(e) => {
+            tracing::warn!("Skipping record {}: {}", line, e);
+            true
+        }
+    }
+}
+
+/// Parses RFC3339, `YYYY-MM-DD HH:MM:SS` (as UTC) or numeric timestamps, in
+/// seconds since `origin` or the unix epoch.
+fn parse_timestamp(value: &str, origin: Option<DateTime<Utc>>) -> Result<DateTime<Utc>, String> {
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    if let Ok(timestamp) = NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S") {
+        return Ok(Utc.from_utc_datetime(&timestamp));
+    }
+    let origin_millis = origin.map_or(0, |origin| origin.timestamp_millis());
+    match value.parse::<f64>() {
+        Ok(secs) if secs.is_finite() => origin_millis
+            .checked_add((secs * 1000.0) as i64)
+            .and_then(DateTime::from_timestamp_millis)
+            .ok_or_else(|| format!("timestamp out of range: {}", value)),
+        _ => Err(format!("invalid timestamp: {}", value)),
+    }
+}
+
+/// Parses a category label, returning the category and whether the label
+/// implies an online transaction.
+///
+/// Besides the `Transaction` category names, the Sparkov dataset categories
+/// are mapped to their closest equivalent (`*_net` categories are online).
+///
+fn parse_category(label: &str) -> Result<(TransactionCategory, Option<bool>), String> {
+    use TransactionCategory::*;
+
+    let label = label.to_lowercase();
+    let online_hint = if label.ends_with("_net") {
+        Some(true)
+    } else if label.ends_with("_pos") {
+        Some(false)
+    } else {
+        None
+    };
+
+    let category = match label.as_str() {
+        "grocery" | "grocery_pos" | "grocery_net" => Grocery,
+        "gas_station" | "gasstation" | "gas_transport" => GasStation,
+        "restaurant" | "food_dining" => Restaurant,
+        "online_retail" | "onlineretail" | "shopping_net" | "shopping_pos" | "misc_net"
+        | "misc_pos" | "kids_pets" | "personal_care" => OnlineRetail,
+        "entertainment" => Entertainment,
+        "travel" => Travel,
+        "healthcare" | "health_fitness" => Healthcare,
+        "utilities" | "home" => Utilities,
+        _ => return Err(format!("unknown category: {}", label)),
+    };
+    Ok((category, online_hint))
+}
+
+/// Parses the usual boolean spellings found in datasets.
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_lowercase().as_str() {
+        "1" | "true" | "yes" | "y" | "t" => Some(true),
+        "0" | "false" | "no" | "n" | "f" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parses an environment variable value, logging and discarding invalid values.
+fn parse_or_warn<T: std::str::FromStr<Err = String>>(name: &str, value: &str) -> Option<T> {
+    value
+        .parse()
+        .map_err(|e| tracing::warn!("Ignoring {}: {}", name, e))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses an inline CSV fixture into records.
+    fn records(csv: &str) -> Vec<HashMap<String, String>> {
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let headers = reader.headers().unwrap().clone();
+        reader
+            .records()
+            .map(|row| {
+                let row = row.unwrap();
+                headers
+                    .iter()
+                    .zip(row.iter())
+                    .map(|(header, value)| (header.to_string(), value.to_string()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn map(mapping: &ColumnMapping, csv: &str) -> Vec<(Transaction, Option<DateTime<Utc>>)> {
+        records(csv)
+            .iter()
+            .map(|record| mapping.to_transaction(record).unwrap())
+            .collect()
+    }
+
+    fn utc(value: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(value)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn native_records_are_mapped() {
+        let csv = "\
+id,timestamp,cc_number,category,amount_usd_cents,city,country_iso,latitude,longitude,is_online,is_fraud
+t1,2024-03-01T12:00:00Z,4111111111111111,grocery,1250,Paris,fr,48.85,2.35,false,0
+t2,1709294410,5500005555555559,online_retail,99900,,,,,true,
+";
+        let mapped = map(&ColumnMapping::default(), csv);
+
+        let (first, recorded_at) = &mapped[0];
+        assert_eq!(*recorded_at, Some(utc("2024-03-01T12:00:00Z")));
+        assert_eq!(first.id, "t1");
+        assert_eq!(first.timestamp, "2024-03-01T12:00:00+00:00");
+        assert_eq!(first.cc_number, "4111111111111111");
+        assert_eq!(first.category, TransactionCategory::Grocery);
+        assert_eq!(first.amount_usd_cents, 1250);
+        assert_eq!(first.location.city, "Paris");
+        assert_eq!(first.location.country_iso, "FR");
+        assert_eq!(first.location.latitude, 48.85);
+        assert_eq!(first.location.longitude, 2.35);
+        assert!(!first.is_online);
+        assert_eq!(first.is_fraud, Some(false));
+
+        // numeric timestamps are seconds since the unix epoch, empty
+        // columns are mocked and an empty label is unknown
+        let (second, recorded_at) = &mapped[1];
+        assert_eq!(*recorded_at, Some(utc("2024-03-01T12:00:10Z")));
+        assert_eq!(second.category, TransactionCategory::OnlineRetail);
+        assert_eq!(second.amount_usd_cents, 99900);
+        assert!(!second.location.city.is_empty());
+        assert!(second.is_online);
+        assert_eq!(second.is_fraud, None);
+        assert!(second.validate().is_ok());
+    }
+
+    #[test]
+    fn sparkov_records_are_mapped() {
+        let csv = "\
+,trans_date_trans_time,cc_num,merchant,category,amt,city,state,trans_num,merch_lat,merch_long,is_fraud
+0,2019-01-01 00:00:18,2703186189652095,fraud_Rippin,misc_net,4.97,Moravian Falls,NC,0b242abb-623a-fc57-8575-680df30655b9,36.011293,-82.048315,0
+1,2019-01-01 00:00:44,630423337322,fraud_Heller,grocery_pos,107.23,Orient,WA,1f76529f-8574-7349-5a5c-b4a0b6d7a4e1,49.159047,-118.186462,1
+";
+        let mapped = map(&ColumnMapping::sparkov(), csv);
+
+        let (first, recorded_at) = &mapped[0];
+        assert_eq!(*recorded_at, Some(utc("2019-01-01T00:00:18Z")));
+        assert_eq!(first.id, "0b242abb623afc578575680df30655b9");
+        assert_eq!(first.timestamp, "2019-01-01T00:00:18+00:00");
+        assert_eq!(first.cc_number, "2703186189652095");
+        assert_eq!(first.category, TransactionCategory::OnlineRetail);
+        assert_eq!(first.amount_usd_cents, 497);
+        assert_eq!(first.location.city, "Moravian Falls");
+        assert_eq!(first.location.country_iso, "US");
+        assert_eq!(first.location.latitude, 36.011293);
+        assert_eq!(first.location.longitude, -82.048315);
+        assert!(first.is_online);
+        assert_eq!(first.is_fraud, Some(false));
+
+        let (second, recorded_at) = &mapped[1];
+        assert_eq!(*recorded_at, Some(utc("2019-01-01T00:00:44Z")));
+        assert_eq!(second.category, TransactionCategory::Grocery);
+        assert_eq!(second.amount_usd_cents, 10723);
+        assert!(!second.is_online);
+        assert_eq!(second.is_fraud, Some(true));
+    }
+
+    #[test]
+    fn ulb_records_are_mapped() {
+        let csv = "\
+\"Time\",\"V1\",\"V2\",\"Amount\",\"Class\"
+0,-1.3598071336738,-0.0727811733098497,149.62,\"0\"
+406,-2.3122265423263,1.95199201064158,0,\"1\"
+";
+        let mapped = map(&ColumnMapping::ulb(), csv);
+
+        // the offsets are counted from 2013-09-01, the other fields mocked
+        let (first, recorded_at) = &mapped[0];
+        assert_eq!(*recorded_at, Some(utc("2013-09-01T00:00:00Z")));
+        assert_eq!(first.timestamp, "2013-09-01T00:00:00+00:00");
+        assert_eq!(first.amount_usd_cents, 14962);
+        assert_eq!(first.is_fraud, Some(false));
+        assert!(first.validate().is_ok());
+
+        let (second, recorded_at) = &mapped[1];
+        assert_eq!(*recorded_at, Some(utc("2013-09-01T00:06:46Z")));
+        assert_eq!(second.amount_usd_cents, 0);
+        assert_eq!(second.is_fraud, Some(true));
+        assert_ne!(first.id, second.id);
+    }
+
+    #[test]
+    fn numeric_timestamps_are_counted_from_the_origin() {
+        let origin = Some(utc("2024-01-01T00:00:00Z"));
+        assert_eq!(
+            parse_timestamp("90.5", origin),
+            Ok(utc("2024-01-01T00:01:30.500Z"))
+        );
+        assert_eq!(parse_timestamp("0", None), Ok(DateTime::UNIX_EPOCH));
+        // explicit times ignore the origin
+        assert_eq!(
+            parse_timestamp("2024-03-01 08:30:00", origin),
+            Ok(utc("2024-03-01T08:30:00Z"))
+        );
+        assert!(parse_timestamp("1e300", origin).is_err());
+        assert!(parse_timestamp("yesterday", origin).is_err());
+
+        let mut mapping = ColumnMapping::ulb();
+        mapping.time_origin = origin;
+        let mapped = map(&mapping, "Time,Amount,Class\n60,1.00,0\n");
+        assert_eq!(mapped[0].1, Some(utc("2024-01-01T00:01:00Z")));
+    }
+
+    #[test]
+    fn invalid_records_are_rejected() {
+        let csv = "\
+id,timestamp,amount_usd_cents,category,is_fraud
+t1,2024-03-01T12:00:00Z,-5,grocery,0
+t2,2024-03-01T12:00:00Z,100,casino,0
+t3,2024-03-01T12:00:00Z,100,grocery,maybe
+t4,,,grocery,0
+";
+        let errors: Vec<String> = records(csv)
+            .iter()
+            .map(|record| ColumnMapping::default().to_transaction(record).unwrap_err())
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                "invalid amount: -5",
+                "unknown category: casino",
+                "invalid is_fraud: maybe",
+                "missing amount",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn files_are_replayed_with_their_timestamps() {
+        let path = std::env::temp_dir().join(format!("txapi-replay-{}.csv", std::process::id()));
+        std::fs::write(
+            &path,
+            "Time,Amount,Class\n0,1.00,0\nnot a time,2.00,0\n30,3.00,1\n",
+        )
+        .unwrap();
+        let source = ReplaySource::new(ReplayConfig {
+            path: path.clone(),
+            format: ReplayFormat::Csv,
+            mapping: ColumnMapping::ulb(),
+            pace: ReplayPace::Fixed(Duration::ZERO),
+            restamp: false,
+            loop_forever: false,
+        });
+
+        let replayed: Vec<Transaction> = source.start().unwrap().collect().await;
+        std::fs::remove_file(&path).unwrap();
+
+        // the unreadable record is skipped
+        let timestamps: Vec<&str> = replayed.iter().map(|t| t.timestamp.as_str()).collect();
+        assert_eq!(
+            timestamps,
+            ["2013-09-01T00:00:00+00:00", "2013-09-01T00:00:30+00:00"]
+        );
+        assert_eq!(replayed[1].amount_usd_cents, 300);
+        assert_eq!(source.health(), SourceHealth::Finished);
+    }
+}
@@ -0,0 +1,370 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::Deserialize;
+
+use super::enrichment::Enricher;
+use crate::{domain::prelude::*, filter::expr::Expr};
+
+/// A rule raising an alert for the transactions matching its condition.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Rule {
+    pub id: String,
+
+    /// Score of the alerts, between 0 and 1
+    #[serde(default = "default_score")]
+    pub score: f64,
+
+    #[serde(flatten)]
+    pub condition: Condition,
+}
+
+fn default_score() -> f64 {
+    0.5
+}
+
+fn default_min_history() -> u64 {
+    3
+}
+
+/// Condition of a rule, tagged by `type` in the rules file.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Condition {
+    /// More than `max_count` transactions of the card within `window_secs`.
+    Velocity { max_count: u64, window_secs: u64 },
+
+    /// Amount above `ratio` times the average amount of the card, once the
+    /// card has at least `min_history` previous transactions.
+    AmountVsAverage {
+        ratio: f64,
+        #[serde(default = "default_min_history")]
+        min_history: u64,
+    },
+
+    /// Country different from the previous transaction of the card, made
+    /// less than `within_secs` before.
+    CountryChange { within_secs: u64 },
+
+    /// Filter expression over the transaction, see `filter::expr::Expr`.
+    Expr { expr: Expr },
+}
+
+impl Rule {
+    /// Returns the reason of the alert when the transaction matches.
+    fn check(&self, enriched: &EnrichedTransaction, enricher: &Enricher) -> Option<String> {
+        let transaction = &enriched.transaction;
+        let features = &enriched.features;
+
+        match &self.condition {
+            Condition::Velocity {
+                max_count,
+                window_secs,
+            } => {
+                let at = DateTime::parse_from_rfc3339(&transaction.timestamp)
+                    .map(|at| at.with_timezone(&Utc))
+                    .unwrap_or_else(|_| Utc::now());
+                let since = at - TimeDelta::seconds(*window_secs as i64);
+                let count = enricher.count_since(&transaction.cc_number, since);
+                (count > *max_count).then(|| format!("{} transactions in {}s", count, window_secs))
+            }
+            Condition::AmountVsAverage { ratio, min_history } => {
+                let history = enricher
+                    .card_count(&transaction.cc_number)
+                    .saturating_sub(1);
+                let average = features.avg_amount_usd_cents?;
+                let actual = transaction.amount_usd_cents as f64 / average;
+                (history >= *min_history && actual > *ratio)
+                    .then(|| format!("amount {:.1}x the card average", actual))
+            }
+            Condition::CountryChange { within_secs } => {
+                let previous = features.previous_country_iso.as_ref()?;
+                let elapsed = features.secs_since_previous?;
+                let country = &transaction.location.country_iso;
+                (previous != country && elapsed <= *within_secs as f64).then(|| {
+                    format!(
+                        "country changed from {} to {} in {:.0}s",
+                        previous, country, elapsed
+                    )
+                })
+            }
+            Condition::Expr { expr } => expr
+                .matches(transaction)
+                .then(|| "matches the rule expression".to_string()),
+        }
+    }
+}
+
+/// The rules evaluated against every enriched transaction.
+///
+/// Rules are read from the JSON array in the file set by the RULES_FILE
+/// environment variable, for example:
+///
+/// ```json
+/// [
+///   { "id": "velocity_10s", "type": "velocity", "max_count": 2, "window_secs": 10, "score": 0.7 },
+///   { "id": "large_online", "type": "expr", "expr": "is_online && amount_usd_cents > 100000" }
+/// ]
+/// ```
+///
+/// Without RULES_FILE, a default set of velocity, amount and country change
+/// rules is used. RULES_FILE set to an empty string disables the rules.
+///
+#[derive(Debug, Clone, Default)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self { rules }
+    }
+
+    /// Reads the rules from the environment. An invalid rules file is
+    /// logged and leaves the rule set empty.
+    pub fn from_env() -> Self {
+        let Ok(path) = std::env::var("RULES_FILE") else {
+            return Self::default_rules();
+        };
+        if path.is_empty() {
+            return Self::default();
+        }
+
+        let rules = std::fs::read_to_string(&path)
+            .map_err(|e| e.to_string())
+            .and_then(|rules| serde_json::from_str(&rules).map_err(|e| e.to_string()));
+        match rules {
+            Ok(rules) => Self::new(rules),
+            Err(e) => {
+                tracing::error!("Failed to load the rules from {}: {}", path, e);
+                Self::default()
+            }
+        }
+    }
+
+    /// The default rules, a baseline to compare models against.
+    ///
+    /// They are tuned to the mock generator, whose cards make a transaction
+    /// every 100 seconds on average while the fraud scenarios make several
+    /// transactions abroad within seconds.
+    pub fn default_rules() -> Self {
+        Self::new(vec![
+            Rule {
+                id: "velocity_10s".to_string(),
+                score: 0.6,
+                condition: Condition::Velocity {
+                    max_count: 2,
+                    window_secs: 10,
+                },
+            },
+            Rule {
+                id: "amount_10x_average".to_string(),
+                score: 0.5,
+                condition: Condition::AmountVsAverage {
+                    ratio: 10.0,
+                    min_history: 10,
+                },
+            },
+            Rule {
+                id: "country_change_2s".to_string(),
+                score: 0.8,
+                condition: Condition::CountryChange { within_secs: 2 },
+            },
+        ])
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.rules.iter().map(|rule| rule.id.as_str())
+    }
+
+    /// Returns an alert for every rule the transaction matches.
+    pub fn evaluate(&self, enriched: &EnrichedTransaction, enricher: &Enricher) -> Vec<Alert> {
+        self.rules
+            .iter()
+            .filter_map(|rule| {
+                let reason = rule.check(enriched, enricher)?;
+                Some(Alert {
+                    rule_id: rule.id.clone(),
+                    score: rule.score,
+                    reason,
+                    transaction: enriched.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        domain::transactions::Location,
+        stream::enrichment::{Enricher, EnrichmentConfig},
+    };
+    use serde_json::{json, Value};
+
+    /// A transaction of the card in the country, `secs` after a fixed time.
+    fn transaction(card: &str, country: &str, amount_usd_cents: u64, secs: i64) -> Transaction {
+        let start = DateTime::parse_from_rfc3339("2025-01-01T00:00:00Z").unwrap();
+        Transaction {
+            cc_number: card.to_string(),
+            amount_usd_cents,
+            timestamp: (start + TimeDelta::seconds(secs)).to_rfc3339(),
+            location: Location {
+                city: "Somewhere".to_string(),
+                country_iso: country.to_string(),
+                latitude: 0.0,
+                longitude: 0.0,
+            },
+            is_online: false,
+            ..Transaction::simple_mock()
+        }
+    }
+
+    /// Evaluates the rule against the transactions, in order, returning the
+    /// reason of the alert raised for each of them, if any.
+    fn reasons(rule: Value, transactions: Vec<Transaction>) -> Vec<Option<String>> {
+        let rule: Rule = serde_json::from_value(rule).unwrap();
+        let rules = RuleSet::new(vec![rule]);
+        let mut enricher = Enricher::new(EnrichmentConfig {
+            max_cards: 100,
+            max_events_per_card: 100,
+        });
+        transactions
+            .into_iter()
+            .map(|transaction| {
+                let enriched = enricher.enrich(transaction);
+                let alerts = rules.evaluate(&enriched, &enricher);
+                assert!(alerts.len() <= 1);
+                alerts.into_iter().next().map(|alert| alert.reason)
+            })
+            .collect()
+    }
+
+    fn expected(reasons: &[Option<&str>]) -> Vec<Option<String>> {
+        reasons
+            .iter()
+            .map(|reason| reason.map(str::to_string))
+            .collect()
+    }
+
+    #[test]
+    fn velocity_counts_the_transactions_of_the_card_within_the_window() {
+        let rule =
+            json!({ "id": "velocity", "type": "velocity", "max_count": 2, "window_secs": 10 });
+        let transactions = vec![
+            transaction("a", "US", 1000, 0),
+            transaction("a", "US", 1000, 3),
+            transaction("b", "US", 1000, 4),
+            transaction("a", "US", 1000, 6),
+            transaction("a", "US", 1000, 20),
+        ];
+        assert_eq!(
+            reasons(rule, transactions),
+            expected(&[None, None, None, Some("3 transactions in 10s"), None])
+        );
+    }
+
+    #[test]
+    fn amount_vs_average_waits_for_the_card_history() {
+        let rule = json!({ "id": "amount", "type": "amount_vs_average", "ratio": 10.0 });
+        let transactions = vec![
+            transaction("a", "US", 1000, 0),
+            transaction("a", "US", 1000, 100),
+            // 50x the average, but only 2 previous transactions
+            transaction("a", "US", 50_000, 200),
+            transaction("a", "US", 1000, 300),
+            transaction("a", "US", 200_000, 400),
+        ];
+        assert_eq!(
+            reasons(rule, transactions),
+            expected(&[
+                None,
+                None,
+                None,
+                None,
+                Some("amount 15.1x the card average")
+            ])
+        );
+    }
+
+    #[test]
+    fn country_change_is_limited_to_the_delay() {
+        let rule = json!({ "id": "country", "type": "country_change", "within_secs": 2 });
+        let transactions = vec![
+            transaction("a", "US", 1000, 0),
+            transaction("a", "FR", 1000, 1),
+            transaction("a", "FR", 1000, 2),
+            transaction("a", "US", 1000, 10),
+        ];
+        assert_eq!(
+            reasons(rule, transactions),
+            expected(&[
+                None,
+                Some("country changed from US to FR in 1s"),
+                None,
+                None
+            ])
+        );
+    }
+
+    #[test]
+    fn expr_matches_the_transaction_fields() {
+        let rule = json!({
+            "id": "large_online",
+            "type": "expr",
+            "expr": "is_online && amount_usd_cents > 10000",
+            "score": 0.9
+        });
+        let online = |amount| Transaction {
+            is_online: true,
+            ..transaction("a", "US", amount, 0)
+        };
+        let transactions = vec![
+            online(20_000),
+            transaction("a", "US", 20_000, 0),
+            online(5000),
+        ];
+        assert_eq!(
+            reasons(rule, transactions),
+            expected(&[Some("matches the rule expression"), None, None])
+        );
+    }
+
+    #[test]
+    fn default_rules_match_a_fraud_burst_only() {
+        let rules = RuleSet::default_rules();
+        let mut enricher = Enricher::new(EnrichmentConfig {
+            max_cards: 100,
+            max_events_per_card: 100,
+        });
+        let mut matched = |transaction| {
+            let enriched = enricher.enrich(transaction);
+            let alerts = rules.evaluate(&enriched, &enricher);
+            alerts
+                .into_iter()
+                .map(|alert| alert.rule_id)
+                .collect::<Vec<_>>()
+        };
+
+        // a card making a transaction at home every 100 seconds
+        for i in 0..10 {
+            assert!(matched(transaction("a", "US", 2000, 100 * i)).is_empty());
+        }
+        // then a burst of large transactions abroad
+        assert_eq!(
+            matched(transaction("a", "FR", 100_000, 1000)),
+            ["amount_10x_average"]
+        );
+        assert_eq!(
+            matched(transaction("a", "DE", 100_000, 1001)),
+            ["country_change_2s"]
+        );
+        assert_eq!(
+            matched(transaction("a", "FR", 100_000, 1002)),
+            ["velocity_10s", "country_change_2s"]
+        );
+    }
+}
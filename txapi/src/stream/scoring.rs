@@ -0,0 +1,435 @@
+use futures::StreamExt;
+use serde::Deserialize;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+use super::sequenced::{Sequenced, SequencedSender};
+use crate::{core::metrics::Metrics, domain::prelude::*};
+
+/// Maximum number of retries of a failed request.
+const MAX_RETRIES: u32 = 10;
+
+/// Maximum delay before a retry.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+/// Settings of the scoring webhook.
+///
+/// - SCORING_URL: URL the transactions are POSTed to, enables the scoring
+/// - SCORING_CONCURRENCY: requests in flight (default 16)
+/// - SCORING_TIMEOUT_MS: timeout of a request (default 500)
+/// - SCORING_RETRIES: retries of a failed request (default 2, max 10)
+/// - SCORING_RETRY_BACKOFF_MS: delay before the first retry, doubled at
+///   every retry up to 5 seconds (default 50)
+/// - SCORING_DEADLINE_MS: time spent scoring a transaction, retries
+///   included, before giving up (default 2000)
+///
+#[derive(Debug, Clone)]
+pub struct ScoringConfig {
+    pub url: String,
+    pub concurrency: usize,
+    pub timeout: Duration,
+    pub retries: u32,
+    pub retry_backoff: Duration,
+    pub deadline: Duration,
+}
+
+impl ScoringConfig {
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var("SCORING_URL")
+            .ok()
+            .filter(|url| !url.is_empty())?;
+        let env_or = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+
+        Some(Self {
+            url,
+            concurrency: env_or("SCORING_CONCURRENCY", 16).max(1) as usize,
+            timeout: Duration::from_millis(env_or("SCORING_TIMEOUT_MS", 500).max(1)),
+            retries: env_or("SCORING_RETRIES", 2).min(MAX_RETRIES as u64) as u32,
+            retry_backoff: Duration::from_millis(env_or("SCORING_RETRY_BACKOFF_MS", 50)),
+            deadline: Duration::from_millis(env_or("SCORING_DEADLINE_MS", 2000).max(1)),
+        })
+    }
+}
+
+/// Initialize the scored transactions channel.
+/// This channel is used to broadcast the transactions with the score
+/// returned by the scoring webhook to the websocket clients.
+///
+/// Every transaction is POSTed as JSON to the webhook, which replies with
+/// `{"score": <number>}`. Up to `concurrency` requests are in flight, the
+/// scored transactions are sent in the order of the transactions channel.
+/// Transactions that could not be scored are sent with the error.
+///
+/// As the order is kept, a slow transaction holds back the ones scored after
+/// it: each transaction is given up after the `deadline`, so a transaction
+/// is sent at most `deadline` after the previous one.
+///
+/// The channel stays idle when the scoring is disabled.
+///
+/// The cancellation_token parameter allows for graceful shutdown of the background task.
+///
+pub async fn channel(
+    config: Option<ScoringConfig>,
+    transactions_tx: &SequencedSender<Transaction>,
+    metrics: Arc<Metrics>,
+    cancellation_token: CancellationToken,
+) -> (
+    SequencedSender<ScoredTransaction>,
+    broadcast::Receiver<Sequenced<ScoredTransaction>>,
+) {
+    let (tx, rx) = SequencedSender::new(100, 0, 1);
+    let Some(config) = config else {
+        return (tx, rx);
+    };
+
+    let client = match reqwest::Client::builder().timeout(config.timeout).build() {
+        Ok(client) => client,
+        Err(e) => {
+            tracing::error!("Failed to create the scoring client: {}", e);
+            return (tx, rx);
+        }
+    };
+    tracing::info!("Scoring the transactions with {}", config.url);
+
+    let transactions = futures::stream::unfold(transactions_tx.subscribe(), |mut rx| async {
+        loop {
+            match rx.recv().await {
+                Ok(transaction) => return Some((transaction.data, rx)),
+                Err(RecvError::Lagged(n)) => {
+                    tracing::warn!("Scoring lagged, {} transactions not scored", n)
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+    let tx_clone = tx.clone();
+    tokio::spawn(async move {
+        let scorer = Scorer {
+            client,
+            config,
+            metrics,
+        };
+        let mut scored = transactions
+            .map(|transaction| scorer.score(transaction))
+            .buffered(scorer.config.concurrency)
+            .take_until(cancellation_token.cancelled())
+            .boxed();
+        while let Some(scored) = scored.next().await {
+            tx_clone.send(scored);
+        }
+        tracing::info!("Scoring shutting down gracefully");
+    });
+    (tx, rx)
+}
+
+/// Reply of the scoring webhook.
+#[derive(Deserialize)]
+struct ScoreReply {
+    score: f64,
+}
+
+/// A failed scoring request.
+struct ScoreError {
+    message: String,
+    /// Whether the request may succeed when retried
+    retryable: bool,
+}
+
+struct Scorer {
+    client: reqwest::Client,
+    config: ScoringConfig,
+    metrics: Arc<Metrics>,
+}
+
+impl Scorer {
+    /// Scores the transaction, retrying the failed requests with an
+    /// exponential backoff.
+    async fn score(&self, transaction: Transaction) -> ScoredTransaction {
+        let started = Instant::now();
+        let mut attempts = 0;
+        let retries = async {
+            loop {
+                attempts += 1;
+                self.metrics.scoring_requests.inc();
+                let sent = Instant::now();
+                let result = self.request(&transaction).await;
+                self.metrics.scoring_latency.observe(sent.elapsed());
+
+                match result {
+                    Err(e) if e.retryable && attempts <= self.config.retries => {
+                        self.metrics.scoring_retries.inc();
+                        tokio::time::sleep(self.backoff(attempts)).await;
+                    }
+                    result => break result,
+                }
+            }
+        };
+        let result = tokio::time::timeout(self.config.deadline, retries)
+            .await
+            .unwrap_or_else(|_| {
+                Err(ScoreError {
+                    message: "deadline exceeded".to_string(),
+                    retryable: false,
+                })
+            });
+
+        let (score, error) = match result {
+            Ok(score) => (Some(score), None),
+            Err(e) => {
+                tracing::debug!(
+                    "Failed to score transaction {}: {}",
+                    transaction.id,
+                    e.message
+                );
+                self.metrics.scoring_failures.inc();
+                (None, Some(e.message))
+            }
+        };
+        ScoredTransaction {
+            transaction,
+            scoring: Score {
+                score,
+                error,
+                attempts,
+                latency_ms: started.elapsed().as_secs_f64() * 1000.0,
+            },
+        }
+    }
+
+    /// Returns the delay before retrying the given attempt.
+    fn backoff(&self, attempts: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+        self.config
+            .retry_backoff
+            .saturating_mul(factor)
+            .min(MAX_RETRY_BACKOFF)
+    }
+
+    async fn request(&self, transaction: &Transaction) -> Result<f64, ScoreError> {
+        let transport = |e: reqwest::Error| ScoreError {
+            message: if e.is_timeout() {
+                "timeout".to_string()
+            } else {
+                e.to_string()
+            },
+            retryable: true,
+        };
+        // the fraud label is never serialized, the webhook only gets the
+        // features of the transaction
+        let body = serde_json::to_vec(transaction).map_err(|e| ScoreError {
+            message: e.to_string(),
+            retryable: false,
+        })?;
+
+        let response = self
+            .client
+            .post(&self.config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await
+            .map_err(transport)?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(ScoreError {
+                message: format!("status {}", status),
+                retryable: status.is_server_error()
+                    || status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+            });
+        }
+
+        let body = response.bytes().await.map_err(transport)?;
+        match serde_json::from_slice::<ScoreReply>(&body) {
+            Ok(reply) if reply.score.is_finite() => Ok(reply.score),
+            Ok(reply) => Err(ScoreError {
+                message: format!("invalid score: {}", reply.score),
+                retryable: false,
+            }),
+            Err(e) => Err(ScoreError {
+                message: format!("invalid response: {}", e),
+                retryable: false,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream::mock::CardPool;
+    use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+    use serde_json::json;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Starts a webhook failing the first `failures` requests with `status`,
+    /// and returns its URL and request counter.
+    async fn webhook(failures: u32, status: StatusCode) -> (String, Arc<AtomicU32>) {
+        let requests = Arc::new(AtomicU32::new(0));
+        let reply = move |State(requests): State<Arc<AtomicU32>>| async move {
+            if requests.fetch_add(1, Ordering::Relaxed) < failures {
+                return Err(status);
+            }
+            Ok(Json(json!({ "score": 0.25 })))
+        };
+        let app: Router = Router::new()
+            .route("/score", post(reply))
+            .with_state(requests.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/score", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, requests)
+    }
+
+    fn scorer(url: String, retries: u32) -> Scorer {
+        Scorer {
+            client: reqwest::Client::new(),
+            config: ScoringConfig {
+                url,
+                concurrency: 1,
+                timeout: Duration::from_secs(1),
+                retries,
+                retry_backoff: Duration::from_millis(1),
+                deadline: Duration::from_secs(5),
+            },
+            metrics: Arc::new(Metrics::default()),
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_the_server_errors() {
+        let (url, requests) = webhook(2, StatusCode::SERVICE_UNAVAILABLE).await;
+        let scorer = scorer(url, 2);
+
+        let scored = scorer.score(Transaction::fixture()).await;
+        assert_eq!(scored.scoring.score, Some(0.25));
+        assert_eq!(scored.scoring.error, None);
+        assert_eq!(scored.scoring.attempts, 3);
+        assert_eq!(requests.load(Ordering::Relaxed), 3);
+        assert_eq!(scorer.metrics.scoring_retries.get(), 2);
+        assert_eq!(scorer.metrics.scoring_failures.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn fails_after_the_last_retry() {
+        let (url, _) = webhook(u32::MAX, StatusCode::TOO_MANY_REQUESTS).await;
+        let scorer = scorer(url, 1);
+
+        let scored = scorer.score(Transaction::fixture()).await;
+        assert_eq!(scored.scoring.score, None);
+        assert_eq!(
+            scored.scoring.error.as_deref(),
+            Some("status 429 Too Many Requests")
+        );
+        assert_eq!(scored.scoring.attempts, 2);
+        assert_eq!(scorer.metrics.scoring_failures.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn does_not_retry_the_client_errors() {
+        let (url, requests) = webhook(u32::MAX, StatusCode::BAD_REQUEST).await;
+        let scorer = scorer(url, 2);
+
+        let scored = scorer.score(Transaction::fixture()).await;
+        assert_eq!(scored.scoring.score, None);
+        assert_eq!(scored.scoring.attempts, 1);
+        assert_eq!(requests.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn scores_the_transactions_in_order() {
+        let (url, _) = webhook(0, StatusCode::OK).await;
+        let (transactions_tx, _) = SequencedSender::new(100, 0, 1);
+        let config = scorer(url, 0).config;
+        let (_, mut scored_rx) = channel(
+            Some(ScoringConfig {
+                concurrency: 4,
+                ..config
+            }),
+            &transactions_tx,
+            Arc::new(Metrics::default()),
+            CancellationToken::new(),
+        )
+        .await;
+
+        let mut pool = CardPool::new(10, 0.0, Default::default());
+        let ids: Vec<_> = (0..10)
+            .map(|_| {
+                let transaction = pool.transaction();
+                let id = transaction.id.clone();
+                transactions_tx.send(transaction);
+                id
+            })
+            .collect();
+        for (seq, id) in (1..).zip(ids) {
+            let scored = scored_rx.recv().await.unwrap();
+            assert_eq!(scored.seq, seq);
+            assert_eq!(scored.data.transaction.id, id);
+            assert_eq!(scored.data.scoring.score, Some(0.25));
+        }
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_deadline() {
+        let reply = || async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+        let app: Router = Router::new().route("/score", post(reply));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/score", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let mut scorer = scorer(url, MAX_RETRIES);
+        scorer.config.deadline = Duration::from_millis(500);
+
+        let started = Instant::now();
+        let scored = scorer.score(Transaction::fixture()).await;
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(scored.scoring.score, None);
+        assert_eq!(scored.scoring.error.as_deref(), Some("deadline exceeded"));
+        assert_eq!(scored.scoring.attempts, 2);
+        assert_eq!(scorer.metrics.scoring_failures.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn does_not_send_the_fraud_label() {
+        let (body_tx, mut body_rx) = tokio::sync::mpsc::unbounded_channel();
+        let reply = move |body: axum::body::Bytes| async move {
+            body_tx.send(body).unwrap();
+            Json(json!({ "score": 0.25 }))
+        };
+        let app: Router = Router::new().route("/score", post(reply));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/score", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        let scorer = scorer(url, 0);
+
+        let transaction = Transaction {
+            is_fraud: Some(true),
+            ..Transaction::fixture()
+        };
+        scorer.score(transaction.clone()).await;
+        let body: serde_json::Value =
+            serde_json::from_slice(&body_rx.recv().await.unwrap()).unwrap();
+        assert_eq!(body["id"], json!(transaction.id));
+        assert!(body.get("is_fraud").is_none());
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let mut scorer = scorer(String::new(), MAX_RETRIES);
+        scorer.config.retry_backoff = Duration::from_millis(50);
+        assert_eq!(scorer.backoff(1), Duration::from_millis(50));
+        assert_eq!(scorer.backoff(3), Duration::from_millis(200));
+        assert_eq!(scorer.backoff(40), MAX_RETRY_BACKOFF);
+        assert_eq!(scorer.backoff(u32::MAX), MAX_RETRY_BACKOFF);
+    }
+}
@@ -0,0 +1,151 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast;
+
+/// A message tagged with its sequence number in the channel.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Sequenced<T> {
+    pub seq: u64,
+    pub data: T,
+}
+
+/// The result of a resume request.
+#[derive(Debug)]
+pub struct Resume<T> {
+    /// The retained messages from the requested sequence number onwards.
+    pub replay: Vec<Sequenced<T>>,
+
+    /// The oldest retained sequence number, when the requested one has
+    /// already been evicted.
+    pub gap: Option<u64>,
+
+    /// The next sequence number, when the requested one is beyond it, as
+    /// when the numbering restarted with the server.
+    pub ahead: Option<u64>,
+}
+
+/// A broadcast sender that numbers the messages and retains the latest ones.
+///
+/// Sequence numbers start at 1 and increase by one for every message sent,
+/// whether there are receivers or not. The last `retention` messages are kept
+/// in a ring buffer so that reconnecting clients can resume from the last
+/// sequence number they have seen.
+///
+/// The sender is cheap to clone, clones share the same channel and buffer.
+///
+pub struct SequencedSender<T> {
+    tx: broadcast::Sender<Sequenced<T>>,
+    buffer: Arc<Mutex<RingBuffer<T>>>,
+}
+
+struct RingBuffer<T> {
+    next_seq: u64,
+    retention: usize,
+    messages: VecDeque<Sequenced<T>>,
+}
+
+impl<T> Clone for SequencedSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            buffer: self.buffer.clone(),
+        }
+    }
+}
+
+impl<T: Clone> SequencedSender<T> {
+    /// Creates a channel with the given broadcast buffer size, retaining
+    /// the last `retention` messages, and numbering from `first_seq`.
+    pub fn new(
+        buffer_size: usize,
+        retention: usize,
+        first_seq: u64,
+    ) -> (Self, broadcast::Receiver<Sequenced<T>>) {
+        let (tx, rx) = broadcast::channel(buffer_size);
+        let buffer = RingBuffer {
+            next_seq: first_seq.max(1),
+            retention,
+            messages: VecDeque::with_capacity(retention),
+        };
+        let sender = Self {
+            tx,
+            buffer: Arc::new(Mutex::new(buffer)),
+        };
+        (sender, rx)
+    }
+
+    /// Numbers, retains and broadcasts a message, returning its sequence number.
+    ///
+    /// The buffer lock is held while broadcasting so that messages are
+    /// broadcast in sequence order.
+    pub fn send(&self, data: T) -> u64 {
+        let mut buffer = self.buffer.lock().unwrap();
+        let message = Sequenced {
+            seq: buffer.next_seq,
+            data,
+        };
+        buffer.next_seq += 1;
+
+        if buffer.retention > 0 {
+            if buffer.messages.len() == buffer.retention {
+                buffer.messages.pop_front();
+            }
+            buffer.messages.push_back(message.clone());
+        }
+
+        // ignore send errors (occurs when no receivers)
+        let _ = self.tx.send(message);
+        buffer.next_seq - 1
+    }
+
+    /// Subscribes to the messages broadcast from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Sequenced<T>> {
+        self.tx.subscribe()
+    }
+
+    /// Returns the sequence number of the last message sent, if any.
+    pub fn last_seq(&self) -> Option<u64> {
+        let buffer = self.buffer.lock().unwrap();
+        Some(buffer.next_seq - 1).filter(|seq| *seq > 0)
+    }
+
+    /// Returns the sequence number of the oldest retained message, or the
+    /// next one if none is retained.
+    pub fn oldest_seq(&self) -> u64 {
+        let buffer = self.buffer.lock().unwrap();
+        buffer.oldest_seq()
+    }
+
+    /// Returns the retained messages with a sequence number of at least `from`.
+    ///
+    /// If `from` has already been evicted, the replay starts at the oldest
+    /// retained message and `gap` is set. If `from` has not been reached
+    /// yet, nothing is replayed and `ahead` is set.
+    pub fn resume(&self, from: u64) -> Resume<T> {
+        let buffer = self.buffer.lock().unwrap();
+        let oldest = buffer.oldest_seq();
+
+        Resume {
+            replay: buffer
+                .messages
+                .iter()
+                .filter(|message| message.seq >= from)
+                .cloned()
+                .collect(),
+            gap: Some(oldest).filter(|oldest| from < *oldest),
+            ahead: Some(buffer.next_seq).filter(|next_seq| from > *next_seq),
+        }
+    }
+}
+
+impl<T> RingBuffer<T> {
+    fn oldest_seq(&self) -> u64 {
+        self.messages
+            .front()
+            .map(|message| message.seq)
+            .unwrap_or(self.next_seq)
+    }
+}
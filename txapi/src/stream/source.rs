@@ -0,0 +1,172 @@
+use futures::{future::BoxFuture, stream::BoxStream};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+
+use crate::{core::metrics::Metrics, domain::prelude::*};
+
+/// Health status reported by a transaction source.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SourceHealth {
+    /// The source is registered but has not been started yet.
+    Idle,
+    /// The source is emitting transactions.
+    Running,
+    /// The source has no more transactions to emit.
+    Finished,
+    /// The source is running but having trouble (ex. reconnecting).
+    Degraded { reason: String },
+    /// The source stopped because of an error.
+    Failed { reason: String },
+    /// The source was shut down.
+    Stopped,
+}
+
+impl SourceHealth {
+    /// Returns the status name, without the reason.
+    pub fn status(&self) -> &'static str {
+        match self {
+            Self::Idle => "idle",
+            Self::Running => "running",
+            Self::Finished => "finished",
+            Self::Degraded { .. } => "degraded",
+            Self::Failed { .. } => "failed",
+            Self::Stopped => "stopped",
+        }
+    }
+}
+
+/// A backend that produces transactions for the transactions channel.
+///
+/// Sources are registered in a `SourceRegistry` and started by
+/// `stream::transactions::channel`, which merges their streams and tags
+/// every emitted transaction with the source name.
+///
+/// Lifecycle:
+/// - `start` is called once and returns the stream of transactions
+/// - `health` can be called at any time (ex. by the health endpoint)
+/// - `shutdown` is called once on graceful shutdown, after the stream is dropped
+/// - `declined` can be called at any time for the emitted transactions
+///
+pub trait TransactionSource: Send + Sync + 'static {
+    /// Unique name of the source, used to tag the emitted transactions.
+    fn name(&self) -> &str;
+
+    /// Starts the source, returning the stream of transactions it emits.
+    fn start(&self) -> Result<BoxStream<'static, Transaction>, String>;
+
+    /// Returns the current health of the source.
+    fn health(&self) -> SourceHealth;
+
+    /// Releases the resources held by the source.
+    fn shutdown(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+
+    /// Notifies the source that one of its transactions was declined by the
+    /// authorization simulation. Ignored by default.
+    fn declined(&self, _transaction: &Transaction) {}
+}
+
+/// Registry of the transaction sources feeding the transactions channel.
+///
+/// The registry is cheap to clone, clones share the same sources so that
+/// the health of the running sources can be queried from the app state.
+///
+#[derive(Clone, Default)]
+pub struct SourceRegistry {
+    sources: Arc<Mutex<Vec<Arc<dyn TransactionSource>>>>,
+}
+
+impl SourceRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry with the built-in sources enabled by the environment:
+    ///
+    /// - the mock generator, unless MOCK_ENABLED=false
+    /// - the file replay source, if REPLAY_FILE is set (see `ReplayConfig`)
+    /// - the Kafka source, if KAFKA_SOURCE_TOPIC is set (see `KafkaConfig`),
+    ///   counting the messages it rejects in the `metrics`
+    ///
+    #[cfg_attr(not(feature = "kafka"), allow(unused_variables))]
+    pub fn from_env(metrics: &Arc<Metrics>) -> Self {
+        use super::{mock::MockSource, replay::ReplayConfig, replay::ReplaySource};
+
+        let registry = Self::new();
+
+        let mock_enabled = std::env::var("MOCK_ENABLED")
+            .map(|s| s != "false" && s != "0")
+            .unwrap_or(true);
+        if mock_enabled {
+            registry.register(MockSource::default());
+        }
+        if let Some(config) = ReplayConfig::from_env() {
+            registry.register(ReplaySource::new(config));
+        }
+        #[cfg(feature = "kafka")]
+        if let Some(config) = super::kafka::KafkaConfig::from_env() {
+            if config.source_topic.is_some() {
+                registry.register(super::kafka::KafkaSource::new(config, metrics.clone()));
+            }
+        }
+
+        registry
+    }
+
+    /// Registers a source.
+    ///
+    /// Sources must be registered before the transactions channel is started,
+    /// a source registered with a name that is already taken replaces the
+    /// previous one.
+    pub fn register(&self, source: impl TransactionSource) -> &Self {
+        let mut sources = self.sources.lock().unwrap();
+        sources.retain(|existing| existing.name() != source.name());
+        sources.push(Arc::new(source));
+        self
+    }
+
+    /// Returns the registered sources.
+    pub fn sources(&self) -> Vec<Arc<dyn TransactionSource>> {
+        self.sources.lock().unwrap().clone()
+    }
+
+    /// Notifies the source of the transaction that it was declined.
+    pub fn declined(&self, transaction: &Transaction) {
+        let Some(name) = &transaction.source else {
+            return;
+        };
+        if let Some(source) = self.sources().iter().find(|source| source.name() == name) {
+            source.declined(transaction);
+        }
+    }
+
+    /// Returns the name and health of every registered source.
+    pub fn health(&self) -> Vec<(String, SourceHealth)> {
+        self.sources()
+            .iter()
+            .map(|source| (source.name().to_string(), source.health()))
+            .collect()
+    }
+}
+
+/// Shared health slot, for sources whose health is updated from their stream.
+#[derive(Clone, Debug)]
+pub struct HealthCell(Arc<Mutex<SourceHealth>>);
+
+impl Default for HealthCell {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(SourceHealth::Idle)))
+    }
+}
+
+impl HealthCell {
+    pub fn get(&self) -> SourceHealth {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn set(&self, health: SourceHealth) {
+        *self.0.lock().unwrap() = health;
+    }
+}
@@ -0,0 +1,347 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::Duration,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_util::sync::CancellationToken;
+
+use super::sequenced::{Sequenced, SequencedSender};
+use crate::domain::prelude::*;
+
+/// Windows computed by default: a 1 minute tumbling window, and a 5 minutes
+/// window sliding every 10 seconds.
+const DEFAULT_WINDOWS: &str = "tumbling:60,sliding:300:10";
+
+/// Longest window, 7 days.
+const MAX_WINDOW_SECS: u64 = 7 * 24 * 3600;
+
+/// Most steps in a window, each of them being aggregated separately.
+const MAX_STEPS_PER_WINDOW: u64 = 10_000;
+
+/// A time window over the broadcast transactions.
+///
+/// The window spans `size` and is published every `step`, a tumbling window
+/// being a sliding window whose step is its size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Window {
+    pub size: Duration,
+    pub step: Duration,
+}
+
+impl Window {
+    /// Parses `tumbling:<secs>` or `sliding:<secs>:<step secs>`.
+    ///
+    /// The size is rounded up to a multiple of the step. Windows longer than
+    /// `MAX_WINDOW_SECS` or with more than `MAX_STEPS_PER_WINDOW` steps are
+    /// rejected.
+    fn parse(s: &str) -> Result<Self, String> {
+        let parts: Vec<_> = s.trim().split(':').collect();
+        let secs = |s: &str| {
+            s.parse::<u64>()
+                .ok()
+                .filter(|secs| *secs > 0)
+                .ok_or_else(|| format!("invalid number of seconds: {}", s))
+        };
+
+        let (size, step) = match parts.as_slice() {
+            ["tumbling", size] => (secs(size)?, secs(size)?),
+            ["sliding", size, step] => (secs(size)?, secs(step)?),
+            _ => return Err(format!("invalid window: {}", s)),
+        };
+        let steps = size.div_ceil(step);
+        let size = steps
+            .checked_mul(step)
+            .filter(|size| *size <= MAX_WINDOW_SECS)
+            .ok_or_else(|| format!("window longer than {}s: {}", MAX_WINDOW_SECS, s))?;
+        if steps > MAX_STEPS_PER_WINDOW {
+            return Err(format!(
+                "window of more than {} steps: {}",
+                MAX_STEPS_PER_WINDOW, s
+            ));
+        }
+        Ok(Self {
+            size: Duration::from_secs(size),
+            step: Duration::from_secs(step),
+        })
+    }
+
+    fn name(&self) -> String {
+        let kind = if self.size == self.step {
+            "tumbling"
+        } else {
+            "sliding"
+        };
+        format!("{}_{}s", kind, self.size.as_secs())
+    }
+}
+
+/// Reads the windows from the STATS_WINDOWS environment variable, a comma
+/// separated list such as `tumbling:60,sliding:300:10` (the default).
+///
+/// Invalid windows are logged and skipped, an empty list disables the stats.
+pub fn windows_from_env() -> Vec<Window> {
+    let windows = std::env::var("STATS_WINDOWS").unwrap_or_else(|_| DEFAULT_WINDOWS.to_string());
+    windows
+        .split(',')
+        .filter(|window| !window.trim().is_empty())
+        .filter_map(|window| {
+            Window::parse(window)
+                .map_err(|e| tracing::warn!("Ignoring stats window: {}", e))
+                .ok()
+        })
+        .collect()
+}
+
+/// Initialize the stats channel.
+/// This channel is used to broadcast aggregates of the transactions channel
+/// to the websocket clients, computed once for all of them.
+///
+/// Every window is computed by its own background task, subscribed to the
+/// transactions channel. Windows follow the server clock, not the timestamps
+/// of the transactions.
+///
+/// The cancellation_token parameter allows for graceful shutdown of the background tasks.
+///
+pub async fn channel(
+    windows: Vec<Window>,
+    transactions_tx: &SequencedSender<Transaction>,
+    cancellation_token: CancellationToken,
+) -> (
+    SequencedSender<Stats>,
+    broadcast::Receiver<Sequenced<Stats>>,
+) {
+    let (tx, rx) = SequencedSender::new(16, 16, 1);
+
+    for window in windows {
+        tracing::info!("Computing {} stats", window.name());
+        tokio::spawn(aggregate(
+            window,
+            transactions_tx.subscribe(),
+            tx.clone(),
+            cancellation_token.clone(),
+        ));
+    }
+    (tx, rx)
+}
+
+/// Aggregates the transactions into buckets of one step, publishing the
+/// merge of the buckets covering the window at the end of every step.
+async fn aggregate(
+    window: Window,
+    mut transactions_rx: broadcast::Receiver<Sequenced<Transaction>>,
+    stats_tx: SequencedSender<Stats>,
+    cancellation_token: CancellationToken,
+) {
+    let name = window.name();
+    let buckets_per_window = (window.size.as_secs() / window.step.as_secs()) as usize;
+
+    let mut buckets: VecDeque<Tally> = VecDeque::with_capacity(buckets_per_window);
+    let mut current = Tally::default();
+    let mut ticker =
+        tokio::time::interval_at(tokio::time::Instant::now() + window.step, window.step);
+
+    loop {
+        tokio::select! {
+            _ = cancellation_token.cancelled() => break,
+
+            _ = ticker.tick() => {
+                if buckets.len() == buckets_per_window {
+                    buckets.pop_front();
+                }
+                buckets.push_back(std::mem::take(&mut current));
+
+                let mut total = Tally::default();
+                for bucket in &buckets {
+                    total.merge(bucket);
+                }
+                let end = Utc::now();
+                let start = TimeDelta::from_std(window.size)
+                    .ok()
+                    .and_then(|size| end.checked_sub_signed(size))
+                    .unwrap_or(DateTime::<Utc>::MIN_UTC);
+                stats_tx.send(total.into_stats(name.clone(), start, end));
+            }
+
+            transaction = transactions_rx.recv() => match transaction {
+                Ok(transaction) => current.add(&transaction.data),
+                Err(RecvError::Lagged(n)) => {
+                    tracing::warn!("{} stats lagged, {} transactions not counted", name, n)
+                }
+                Err(RecvError::Closed) => break,
+            },
+        }
+    }
+}
+
+/// Running aggregates of a set of transactions.
+#[derive(Debug, Default)]
+struct Tally {
+    total: Aggregate,
+    by_category: BTreeMap<TransactionCategory, Aggregate>,
+    by_country: BTreeMap<String, Aggregate>,
+    online: Aggregate,
+    offline: Aggregate,
+    labelled: u64,
+    fraudulent: u64,
+}
+
+impl Tally {
+    fn add(&mut self, transaction: &Transaction) {
+        self.total.add(transaction);
+        self.by_category
+            .entry(transaction.category)
+            .or_default()
+            .add(transaction);
+        self.by_country
+            .entry(transaction.location.country_iso.clone())
+            .or_default()
+            .add(transaction);
+        if transaction.is_online {
+            self.online.add(transaction);
+        } else {
+            self.offline.add(transaction);
+        }
+        if let Some(is_fraud) = transaction.is_fraud {
+            self.labelled += 1;
+            self.fraudulent += is_fraud as u64;
+        }
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.total.merge(&other.total);
+        for (category, aggregate) in &other.by_category {
+            self.by_category
+                .entry(*category)
+                .or_default()
+                .merge(aggregate);
+        }
+        for (country, aggregate) in &other.by_country {
+            self.by_country
+                .entry(country.clone())
+                .or_default()
+                .merge(aggregate);
+        }
+        self.online.merge(&other.online);
+        self.offline.merge(&other.offline);
+        self.labelled += other.labelled;
+        self.fraudulent += other.fraudulent;
+    }
+
+    fn into_stats(self, window: String, start: DateTime<Utc>, end: DateTime<Utc>) -> Stats {
+        let fraud = (self.labelled > 0).then(|| FraudRate {
+            labelled: self.labelled,
+            fraudulent: self.fraudulent,
+            rate: self.fraudulent as f64 / self.labelled as f64,
+        });
+
+        Stats {
+            window,
+            start,
+            end,
+            total: self.total,
+            by_category: self.by_category,
+            by_country: self.by_country,
+            online: self.online,
+            offline: self.offline,
+            fraud,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::transactions::Location;
+
+    fn transaction(
+        category: TransactionCategory,
+        country: &str,
+        amount_usd_cents: u64,
+        is_online: bool,
+        is_fraud: Option<bool>,
+    ) -> Transaction {
+        let location = Location {
+            country_iso: country.to_string(),
+            ..Location::random()
+        };
+        Transaction {
+            category,
+            location,
+            amount_usd_cents,
+            is_online,
+            is_fraud,
+            ..Transaction::simple_mock()
+        }
+    }
+
+    #[test]
+    fn windows_are_parsed() {
+        let secs = Duration::from_secs;
+        let window = Window::parse("tumbling:60").unwrap();
+        assert_eq!((window.size, window.step), (secs(60), secs(60)));
+        assert_eq!(window.name(), "tumbling_60s");
+        let window = Window::parse(" sliding:300:10 ").unwrap();
+        assert_eq!((window.size, window.step), (secs(300), secs(10)));
+        assert_eq!(window.name(), "sliding_300s");
+        // rounded up to a multiple of the step
+        let window = Window::parse("sliding:65:10").unwrap();
+        assert_eq!(window.size, secs(70));
+
+        for invalid in [
+            "tumbling",
+            "tumbling:0",
+            "tumbling:-1",
+            "sliding:60",
+            "sliding:60:0",
+            "hopping:60:10",
+            "tumbling:604801",
+            "sliding:18446744073709551615:10",
+            "sliding:18446744073709551615:18446744073709551614",
+            "sliding:100000:1",
+        ] {
+            assert!(Window::parse(invalid).is_err(), "{}", invalid);
+        }
+        assert!(Window::parse("tumbling:604800").is_ok());
+    }
+
+    #[test]
+    fn tallies_are_merged_into_stats() {
+        use TransactionCategory::{Grocery, Travel};
+
+        let mut first = Tally::default();
+        first.add(&transaction(Grocery, "FR", 1000, false, Some(false)));
+        first.add(&transaction(Travel, "US", 5000, true, Some(true)));
+        let mut second = Tally::default();
+        second.add(&transaction(Grocery, "US", 2000, true, None));
+
+        let mut total = Tally::default();
+        total.merge(&first);
+        total.merge(&second);
+        let end = Utc::now();
+        let start = end - TimeDelta::seconds(60);
+        let stats = total.into_stats("tumbling_60s".to_string(), start, end);
+
+        assert_eq!(
+            (stats.window.as_str(), stats.start, stats.end),
+            ("tumbling_60s", start, end)
+        );
+        assert_eq!((stats.total.count, stats.total.amount_usd_cents), (3, 8000));
+        assert_eq!(stats.by_category[&Grocery].count, 2);
+        assert_eq!(stats.by_category[&Grocery].amount_usd_cents, 3000);
+        assert_eq!(stats.by_category[&Travel].count, 1);
+        assert_eq!(stats.by_country["US"].amount_usd_cents, 7000);
+        assert_eq!(stats.by_country["FR"].count, 1);
+        assert_eq!((stats.online.count, stats.offline.count), (2, 1));
+        let fraud = stats.fraud.unwrap();
+        assert_eq!((fraud.labelled, fraud.fraudulent, fraud.rate), (2, 1, 0.5));
+
+        // the fraud rate is omitted without labels
+        let mut unlabelled = Tally::default();
+        unlabelled.add(&transaction(Grocery, "US", 2000, true, None));
+        assert!(unlabelled
+            .into_stats(String::new(), start, end)
+            .fraud
+            .is_none());
+    }
+}
@@ -1,9 +1,16 @@
-use futures::{stream::select_all, Stream, StreamExt};
-use std::time::Duration;
+use futures::{stream::select_all, StreamExt};
+use std::sync::Arc;
 use tokio::sync::broadcast;
 use tokio_util::sync::CancellationToken;
 
-use crate::domain::prelude::*;
+use super::{
+    sequenced::{Sequenced, SequencedSender},
+    source::SourceRegistry,
+};
+use crate::{
+    core::authorization::{Authorizer, MAX_IN_FLIGHT},
+    domain::prelude::*,
+};
 
 /// Initialize the transactions channel.
 /// This channel is used to broadcast transactions from the combined backend
@@ -12,29 +19,72 @@ use crate::domain::prelude::*;
 /// The broadcaster is initialized with a buffer size of 100 by default, but
 /// this can be overridden by the BROADCAST_BUFFER_SIZE environment variable.
 ///
+/// Transactions are numbered, and the last 1000 are retained for clients
+/// resuming after a reconnect. The retention can be overridden by the
+/// RETENTION_SIZE environment variable. Numbering starts at `first_seq`, so
+/// that it can continue the event log after a restart.
+///
 /// This initializer is meant to be used to create a broadcaster at App State level,
 /// in order to make it available to the websocket handler.
 ///
+/// Every source of the registry is started and its transactions are tagged
+/// with the source name. Sources that fail to start are logged and skipped.
+///
+/// When the authorization simulation is enabled, every transaction is
+/// authorized before it is broadcast, with its outcome attached. Up to
+/// `MAX_IN_FLIGHT` transactions are authorized at once, and they are
+/// broadcast in the order of the sources.
+///
 /// The cancellation_token parameter allows for graceful shutdown of the background task.
 ///
 pub async fn channel(
+    sources: SourceRegistry,
+    first_seq: u64,
+    authorizer: Option<Arc<Authorizer>>,
     cancellation_token: CancellationToken,
 ) -> (
-    broadcast::Sender<Transaction>,
-    broadcast::Receiver<Transaction>,
+    SequencedSender<Transaction>,
+    broadcast::Receiver<Sequenced<Transaction>>,
 ) {
     let buffer_size = 100;
     let buffer_size = std::env::var("BROADCAST_BUFFER_SIZE")
         .map(|s| s.parse::<usize>().unwrap_or(buffer_size))
         .unwrap_or(buffer_size);
 
-    let (tx, rx) = broadcast::channel(buffer_size);
+    let retention = 1000;
+    let retention = std::env::var("RETENTION_SIZE")
+        .map(|s| s.parse::<usize>().unwrap_or(retention))
+        .unwrap_or(retention);
+
+    let (tx, rx) = SequencedSender::new(buffer_size, retention, first_seq);
 
     // combine all streams into a single consolidated stream
-    let mut stream = select_all(vec![
-        stream_from_mocks(),
-        // add more streams here (ex. kafka, mongodb, etc.)
-    ]);
+    let mut streams = Vec::new();
+    for source in sources.sources() {
+        let name = source.name().to_string();
+        match source.start() {
+            Err(e) => tracing::error!("Failed to start {} source: {}", name, e),
+            Ok(stream) => {
+                tracing::info!("Started {} source", name);
+                streams.push(stream.map(move |mut transaction| {
+                    transaction.source = Some(name.clone());
+                    transaction.authorization = None;
+                    transaction
+                }));
+            }
+        }
+    }
+    let mut stream = select_all(streams)
+        .map(move |transaction| {
+            let authorizer = authorizer.clone();
+            async move {
+                match authorizer {
+                    Some(authorizer) => authorizer.authorize(transaction).await,
+                    None => transaction,
+                }
+            }
+        })
+        .buffered(MAX_IN_FLIGHT);
 
     // spawn the message stream processor
     let tx_clone = tx.clone();
@@ -48,30 +98,22 @@ pub async fn channel(
                 }
                 // Process next transaction
                 transaction = stream.next() => {
-                    if let Some(transaction) = transaction {
-                        // ignore send errors (occurs when no receivers)
-                        let _ = tx_clone.send(transaction);
+                    match transaction {
+                        Some(transaction) => { tx_clone.send(transaction); }
+                        None => {
+                            tracing::info!("All transaction streams are exhausted");
+                            cancellation_token.cancelled().await;
+                            break;
+                        }
                     }
                 }
             }
         }
-    });
-    (tx, rx)
-}
-
-/// A stream that generates mock transactions
-///
-/// This stream is used to generate mock transactions for testing purposes.
-/// It is used to simulate a stream of transactions that are being processed
-/// by the backend.
-///
-fn stream_from_mocks() -> impl Stream<Item = Transaction> + Send {
-    let stream = futures::stream::unfold((), |()| async {
-        tokio::time::sleep(Duration::from_millis(100)).await;
 
-        let transaction = Transaction::simple_mock();
-        Some((transaction, ()))
+        drop(stream);
+        for source in sources.sources() {
+            source.shutdown().await;
+        }
     });
-
-    Box::pin(stream)
+    (tx, rx)
 }